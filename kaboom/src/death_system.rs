@@ -12,6 +12,7 @@ use crate::fighter::Fighter;
 use crate::game_state::GameState;
 use crate::health::Health;
 use crate::message::Message;
+use crate::player::Player;
 
 /// Identifies fighters that have run out of health,
 /// awards points to their killer, and respawns the victim.
@@ -88,6 +89,7 @@ impl<'a> specs::System<'a> for DeathSystem {
                                 info!(self.log, "Fighter killed!"; "victim" => &victim_name, "killer" => &killer.name);
                                 info!(self.log, "Killer won a point"; "new_points" => killer.points);
                             }
+                            log_scores(&self.log, players);
                         } else {
                             // Don't award any points.
                             info!(self.log, "Fighter killed by disconnected player!");
@@ -164,3 +166,11 @@ impl<'a> specs::System<'a> for DeathSystem {
         }
     }
 }
+
+fn log_scores(log: &Logger, players: &[Player]) {
+    let scores: Vec<String> = players
+        .iter()
+        .map(|player| format!("{}: {}", player.name, player.points))
+        .collect();
+    info!(log, "Scores"; "scores" => scores.join(", "));
+}
@@ -74,6 +74,7 @@ pub fn create(
             Some(globe_entity),
         ),
     );
+    updater.insert(entity, cell_dweller::Inventory::default());
     updater.insert(entity, fighter_visual);
     // The CellDweller's transformation will be set based
     // on its coordinates in cell space.
@@ -27,6 +27,15 @@ pub struct GameState {
     pub new_players: VecDeque<PlayerId>,
     // Used for default player names when they are created.
     pub next_unnamed_player_number: usize,
+    // Used to generate the planet. Clients hear about this
+    // from the server when they join.
+    pub planet_seed: u64,
+    // Whether the server should make a player for itself.
+    // Dedicated servers don't.
+    pub has_local_player: bool,
+    // The server won't make players for any more new peers
+    // than this, if set. They can still connect and watch.
+    pub max_players: Option<usize>,
 }
 
 impl Default for GameState {
@@ -36,6 +45,9 @@ impl Default for GameState {
             players: Vec::<Player>::new(),
             new_players: VecDeque::<PlayerId>::new(),
             next_unnamed_player_number: 1,
+            planet_seed: 14,
+            has_local_player: true,
+            max_players: None,
         }
     }
 }
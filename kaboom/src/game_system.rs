@@ -5,10 +5,10 @@ use specs::{Entities, LazyUpdate, Read, ReadStorage, Write, WriteStorage};
 use crate::pk;
 use crate::pk::camera::DefaultCamera;
 use crate::pk::cell_dweller::{ActiveCellDweller, CellDweller};
-use crate::pk::globe::Globe;
+use crate::pk::globe::{Globe, MaterialRegistry};
 use crate::pk::net::{
-    Destination, EntityIds, NetMarker, NetworkPeers, NodeResource, PeerId, SendMessage,
-    SendMessageQueue, Transport,
+    Destination, EntityIds, NetMarker, NetworkPeers, NodeResource, PeerId, RecvMessage,
+    SendMessage, SendMessageQueue, Transport,
 };
 
 use crate::client_state::ClientState;
@@ -45,11 +45,13 @@ impl GameSystem {
         game_state.players.push(Player {
             id: next_player_id,
             peer_id: peer_id,
+            connected: true,
             fighter_entity: None,
             name: player_name.clone(),
             points: 0,
         });
         game_state.new_players.push_back(next_player_id);
+        info!(self.log, "Player joined"; "player_id" => next_player_id.0, "name" => &player_name, "peer_id" => peer_id.0);
 
         // Tell all the other peers about this new player.
         send_message_queue.queue.push_back(SendMessage {
@@ -68,6 +70,31 @@ impl GameSystem {
             transport: Transport::TCP,
         });
     }
+
+    fn remove_fighter(
+        &mut self,
+        entities: &Entities<'_>,
+        entity_ids: &mut Write<'_, EntityIds>,
+        game_state: &mut Write<'_, GameState>,
+        entity_id: u64,
+    ) {
+        let fighter_entity = match entity_ids.mapping.remove(&entity_id) {
+            Some(fighter_entity) => fighter_entity,
+            None => {
+                debug!(self.log, "Asked to remove fighter we don't know about"; "entity_id" => entity_id);
+                return;
+            }
+        };
+        debug!(self.log, "Removing fighter"; "entity_id" => entity_id);
+        for player in &mut game_state.players {
+            if player.fighter_entity == Some(fighter_entity) {
+                player.fighter_entity = None;
+            }
+        }
+        entities
+            .delete(fighter_entity)
+            .expect("Somehow tried to use an entity with the wrong generation!");
+    }
 }
 
 impl<'a> specs::System<'a> for GameSystem {
@@ -77,6 +104,7 @@ impl<'a> specs::System<'a> for GameSystem {
         Write<'a, ClientState>,
         Entities<'a>,
         Read<'a, LazyUpdate>,
+        Read<'a, MaterialRegistry>,
         WriteStorage<'a, Globe>,
         Write<'a, ActiveCellDweller>,
         WriteStorage<'a, CellDweller>,
@@ -96,6 +124,7 @@ impl<'a> specs::System<'a> for GameSystem {
             mut client_state,
             entities,
             updater,
+            materials,
             mut globes,
             mut active_cell_dweller,
             cell_dwellers,
@@ -112,9 +141,27 @@ impl<'a> specs::System<'a> for GameSystem {
         // But for now, we need to make sure we create it _and_ it is realised before we try to
         // process any network messages.
         if game_state.globe_entity.is_none() {
+            if !node_resource.is_master {
+                // Wait until the server tells us which planet we're on.
+                // It'll do that before telling us about anything else.
+                match player_recv_message_queue.queue.front() {
+                    Some(RecvMessage {
+                        game_message: PlayerMessage::Welcome(welcome_message),
+                        ..
+                    }) => game_state.planet_seed = welcome_message.planet_seed,
+                    _ => return,
+                }
+                player_recv_message_queue.queue.pop_front();
+            }
+
             // Create the globe first, because we'll need it to figure out where
             // to place the player character.
-            game_state.globe_entity = Some(planet::create(&entities, &updater));
+            game_state.globe_entity = Some(planet::create(
+                &entities,
+                &updater,
+                &materials,
+                game_state.planet_seed,
+            ));
 
             // Don't do anything else in the GameSystem for the rest of the frame.
             // All we're really trying to achieve here is to not process any messages
@@ -126,6 +173,14 @@ impl<'a> specs::System<'a> for GameSystem {
 
         while let Some(message) = player_recv_message_queue.queue.pop_front() {
             match message.game_message {
+                PlayerMessage::Welcome(_) => {
+                    // We only care about the first one,
+                    // which we dealt with before making the globe.
+                    warn!(
+                        self.log,
+                        "Heard welcome message after already being welcomed"
+                    );
+                }
                 PlayerMessage::NewPlayer(new_player_message) => {
                     // Add the new player to our list.
 
@@ -156,6 +211,7 @@ impl<'a> specs::System<'a> for GameSystem {
                         // TODO: make the network server tack on
                         // the ID of the peer that sent these messages!!!!!
                         peer_id: PeerId(1),
+                        connected: true,
                         fighter_entity: None,
                         name: new_player_message.name,
                         // TODO: again, don't just make this up;
@@ -210,21 +266,78 @@ impl<'a> specs::System<'a> for GameSystem {
                     let fighter_entity = entity_ids.mapping[&entity_id];
                     player.fighter_entity = Some(fighter_entity);
                 }
+                PlayerMessage::RemoveFighter(entity_id) => {
+                    // Only the master gets to decide who's still playing.
+                    if node_resource.is_master {
+                        warn!(self.log, "Ignoring request to remove fighter from another peer"; "peer_id" => message.source.0);
+                        continue;
+                    }
+                    self.remove_fighter(&entities, &mut entity_ids, &mut game_state, entity_id);
+                }
             }
         }
 
         // If we are the master, but we don't yet know what our player is,
         // then insert a new player for us now. We'll hear about it on the
         // next tick, and register it as our own.
-        if node_resource.is_master && client_state.player_id.is_none() {
+        if node_resource.is_master
+            && game_state.has_local_player
+            && client_state.player_id.is_none()
+        {
             self.create_and_broadcast_player(&mut game_state, &mut send_message_queue, PeerId(0));
         }
 
+        // Players whose peers have disconnected make room for new ones,
+        // and their fighters leave the game with them.
+        while let Some(disconnected_peer_id) = network_peers.disconnected_peers.pop_front() {
+            // As a client, we don't care; we just want to clean out the list.
+            if !node_resource.is_master {
+                continue;
+            }
+            let mut abandoned_fighter_entities: Vec<specs::Entity> = Vec::new();
+            for player in &mut game_state.players {
+                if player.peer_id == disconnected_peer_id && player.connected {
+                    player.connected = false;
+                    info!(self.log, "Player left"; "player_id" => player.id.0, "name" => &player.name, "peer_id" => disconnected_peer_id.0);
+                    abandoned_fighter_entities.extend(player.fighter_entity);
+                }
+            }
+            for fighter_entity in abandoned_fighter_entities {
+                let entity_id = match net_markers.get(fighter_entity) {
+                    Some(net_marker) => net_marker.id,
+                    None => {
+                        warn!(
+                            self.log,
+                            "Abandoned fighter has no global ID; can't remove it"
+                        );
+                        continue;
+                    }
+                };
+                self.remove_fighter(&entities, &mut entity_ids, &mut game_state, entity_id);
+
+                // Tell everyone who's still here.
+                send_message_queue.queue.push_back(SendMessage {
+                    destination: Destination::EveryoneElse,
+                    game_message: Message::Player(PlayerMessage::RemoveFighter(entity_id)),
+                    transport: Transport::TCP,
+                });
+            }
+        }
+
         // If there are any new network peers, then pop them off
         // and maybe do something with them.
         while let Some(new_peer_id) = network_peers.new_peers.pop_front() {
             // As a client, we don't care; we just want to clean out the list.
             if node_resource.is_master {
+                // Tell the new peer which planet we're on.
+                send_message_queue.queue.push_back(SendMessage {
+                    destination: Destination::One(new_peer_id),
+                    game_message: Message::Player(PlayerMessage::Welcome(player::WelcomeMessage {
+                        planet_seed: game_state.planet_seed,
+                    })),
+                    transport: Transport::TCP,
+                });
+
                 // Tell the new peer about all existing players.
                 for player in &game_state.players {
                     send_message_queue.queue.push_back(SendMessage {
@@ -252,12 +365,24 @@ impl<'a> specs::System<'a> for GameSystem {
                     });
                 }
 
-                // Create a new player for that peer.
-                self.create_and_broadcast_player(
-                    &mut game_state,
-                    &mut send_message_queue,
-                    new_peer_id,
-                );
+                // Create a new player for that peer, if there's room.
+                let connected_players = game_state
+                    .players
+                    .iter()
+                    .filter(|player| player.connected)
+                    .count();
+                let is_full = game_state
+                    .max_players
+                    .is_some_and(|max_players| connected_players >= max_players);
+                if is_full {
+                    warn!(self.log, "Server is full; new peer can watch, but not play"; "peer_id" => new_peer_id.0);
+                } else {
+                    self.create_and_broadcast_player(
+                        &mut game_state,
+                        &mut send_message_queue,
+                        new_peer_id,
+                    );
+                }
             }
 
             // TODO: instead first just create a player for them,
@@ -347,3 +472,115 @@ impl<'a> specs::System<'a> for GameSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow};
+
+    use super::*;
+
+    #[test]
+    fn disconnected_players_make_room_for_new_ones() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let mut game_system = GameSystem::new(&log);
+        specs::System::setup(&mut game_system, &mut world.res);
+
+        world.write_resource::<NodeResource>().is_master = true;
+        {
+            // Pretend we already have a globe, but one that isn't realized,
+            // so that we don't try to make fighters for anyone.
+            let globe_entity = world.create_entity().build();
+            let mut game_state = world.write_resource::<GameState>();
+            game_state.globe_entity = Some(globe_entity);
+            game_state.has_local_player = false;
+            game_state.max_players = Some(1);
+        }
+        let connect = |world: &mut specs::World, peer_id| {
+            world
+                .write_resource::<NetworkPeers<Message>>()
+                .new_peers
+                .push_back(PeerId(peer_id));
+        };
+        let player_peers = |world: &specs::World| -> Vec<PeerId> {
+            world
+                .read_resource::<GameState>()
+                .players
+                .iter()
+                .map(|player| player.peer_id)
+                .collect()
+        };
+
+        connect(&mut world, 1);
+        game_system.run_now(&world.res);
+        assert_eq!(player_peers(&world), vec![PeerId(1)]);
+
+        // Server is full.
+        connect(&mut world, 2);
+        game_system.run_now(&world.res);
+        assert_eq!(player_peers(&world), vec![PeerId(1)]);
+
+        // The first player leaves, and rejoins as a new peer.
+        world
+            .write_resource::<NetworkPeers<Message>>()
+            .disconnected_peers
+            .push_back(PeerId(1));
+        connect(&mut world, 3);
+        game_system.run_now(&world.res);
+        assert_eq!(player_peers(&world), vec![PeerId(1), PeerId(3)]);
+        let game_state = world.read_resource::<GameState>();
+        assert!(!game_state.players[0].connected);
+        assert!(game_state.players[1].connected);
+    }
+
+    #[test]
+    fn disconnected_players_take_their_fighters_with_them() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let mut game_system = GameSystem::new(&log);
+        specs::System::setup(&mut game_system, &mut world.res);
+
+        world.write_resource::<NodeResource>().is_master = true;
+        let fighter_entity = world.create_entity().with(NetMarker { id: 7 }).build();
+        {
+            let globe_entity = world.create_entity().build();
+            let mut game_state = world.write_resource::<GameState>();
+            game_state.globe_entity = Some(globe_entity);
+            game_state.has_local_player = false;
+            game_state.players.push(Player {
+                id: PlayerId(0),
+                peer_id: PeerId(1),
+                connected: true,
+                fighter_entity: Some(fighter_entity),
+                name: "Leaver".to_string(),
+                points: 0,
+            });
+        }
+        world
+            .write_resource::<EntityIds>()
+            .mapping
+            .insert(7, fighter_entity);
+
+        world
+            .write_resource::<NetworkPeers<Message>>()
+            .disconnected_peers
+            .push_back(PeerId(1));
+        game_system.run_now(&world.res);
+        world.maintain();
+
+        assert!(!world.is_alive(fighter_entity));
+        assert!(world.read_resource::<GameState>().players[0]
+            .fighter_entity
+            .is_none());
+        assert!(!world.read_resource::<EntityIds>().mapping.contains_key(&7));
+        let sent_removal = world
+            .read_resource::<SendMessageQueue<Message>>()
+            .queue
+            .iter()
+            .any(|message| match message.game_message {
+                Message::Player(PlayerMessage::RemoveFighter(entity_id)) => entity_id == 7,
+                _ => false,
+            });
+        assert!(sent_removal);
+    }
+}
@@ -1,4 +1,6 @@
 #[macro_use]
+extern crate clap;
+#[macro_use]
 extern crate slog;
 #[macro_use]
 extern crate serde_derive;
@@ -18,18 +20,29 @@ mod weapon;
 
 use std::sync::mpsc;
 
+use crate::game_state::GameState;
 use crate::message::Message;
 use crate::recv_demux_system::RecvDemuxSystem;
 use crate::send_mux_system::SendMuxSystem;
-use clap::{self, AppSettings, Arg, SubCommand};
+use clap::{AppSettings, Arg, SubCommand};
 use planetkit as pk;
 use specs;
 
+// Port to listen on if none is given.
+const DEFAULT_PORT: &str = "62831";
+
 fn main() {
     let matches = clap::App::new("Kaboom")
         .author("Jeff Parsons <jeff@parsons.io>")
         .about("Blow stuff up!")
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("KEY_BINDINGS")
+                .long("key-bindings")
+                .takes_value(true)
+                .global(true)
+                .help("JSON file of which keys to use for each action"),
+        )
         .subcommand(
             SubCommand::with_name("connect")
                 .about("connect to a server")
@@ -40,17 +53,64 @@ fn main() {
                         .index(1),
                 ),
         )
-        .subcommand(SubCommand::with_name("listen").about("start a server, and play"))
-        // TODO: dedicated server, and helper script
-        // to launch a dedicated server then connect
-        // a client to it.
+        .subcommand(
+            SubCommand::with_name("listen")
+                .about("start a server, and play")
+                .arg(port_arg())
+                .arg(
+                    Arg::with_name("RECORD_INPUT")
+                        .long("record-input")
+                        .takes_value(true)
+                        .conflicts_with("REPLAY_INPUT")
+                        .help("File to record all input to, so that the game can be replayed"),
+                )
+                .arg(
+                    Arg::with_name("REPLAY_INPUT")
+                        .long("replay-input")
+                        .takes_value(true)
+                        .help("File of input recorded with --record-input to play back, on the same planet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("start a dedicated server, with no window and no local player")
+                .arg(
+                    Arg::with_name("ADDRESS")
+                        .long("address")
+                        .takes_value(true)
+                        .default_value("0.0.0.0")
+                        .help("The IP address to listen on"),
+                )
+                .arg(port_arg())
+                .arg(
+                    Arg::with_name("MAX_PLAYERS")
+                        .long("max-players")
+                        .takes_value(true)
+                        .help("Most players allowed to join; anyone else can only watch"),
+                )
+                .arg(
+                    Arg::with_name("SEED")
+                        .long("seed")
+                        .takes_value(true)
+                        .help("Seed for generating the planet; random if not given"),
+                ),
+        )
+        // TODO: helper script to launch a dedicated server
+        // then connect a client to it.
         .get_matches();
+    let is_dedicated_server = matches.subcommand_matches("serve").is_some();
+
+    let mut app_builder = pk::AppBuilder::new();
+    if let Some(path) = matches.value_of("KEY_BINDINGS") {
+        let key_bindings =
+            pk::input_adapter::KeyBindings::load(path).expect("Failed to load KEY_BINDINGS");
+        app_builder = app_builder.with_key_bindings(key_bindings);
+    }
 
     // Set up input adapters.
     let (shoot_input_sender, shoot_input_receiver) = mpsc::channel();
-    let shoot_input_adapter = Box::new(weapon::ShootInputAdapter::new(shoot_input_sender));
 
-    let mut app = pk::AppBuilder::new()
+    let app_builder = app_builder
         .with_networking::<Message>()
         .with_common_systems()
         .with_systems(
@@ -59,45 +119,123 @@ fn main() {
              dispatcher_builder: specs::DispatcherBuilder<'static, 'static>| {
                 add_systems(logger, world, dispatcher_builder, shoot_input_receiver)
             },
-        )
-        .build_gui();
+        );
+    let mut app = if is_dedicated_server {
+        app_builder.build_headless()
+    } else {
+        app_builder.build_gui()
+    };
+
+    if !is_dedicated_server {
+        use crate::pk::input_adapter::SharedKeyBindings;
+
+        let key_bindings = app.world_mut().read_resource::<SharedKeyBindings>().clone();
+        app.add_input_adapter(Box::new(weapon::ShootInputAdapter::new(
+            shoot_input_sender,
+            key_bindings,
+        )));
+    }
 
-    app.add_input_adapter(shoot_input_adapter);
+    // Recording or replaying input needs to know which planet we're on,
+    // so remember what to do until we know.
+    let mut record_input: Option<(String, u64)> = None;
+    let mut replay_input: Option<pk::input_recording::InputReplay> = None;
 
     // Should we start a server or connect to one?
     // NLL SVP.
     {
         use crate::pk::net::ServerResource;
         use piston_window::AdvancedWindow;
-        use std::net::SocketAddr;
+        use std::net::{IpAddr, SocketAddr};
 
+        // Dedicated servers don't have a window.
+        let (world, maybe_window) = if is_dedicated_server {
+            (app.world_mut(), None)
+        } else {
+            let (world, window) = app.world_and_window_mut();
+            (world, Some(window))
+        };
         // Systems we added will have ensured ServerResource is present.
-        let (world, window) = app.world_and_window_mut();
         let server_resource = world.write_resource::<ServerResource<Message>>();
         let mut server = server_resource
             .server
             .lock()
             .expect("Failed to lock server");
-        if let Some(_matches) = matches.subcommand_matches("listen") {
-            window.set_title("Kaboom (server)".to_string());
-            // TODO: make port configurable
-            server.start_listen(62831);
+        if let Some(matches) = matches.subcommand_matches("serve") {
+            let ip: IpAddr = matches
+                .value_of("ADDRESS")
+                .unwrap()
+                .parse()
+                .expect("Invalid ADDRESS");
+            let port = value_t!(matches, "PORT", u16).unwrap_or_else(|e| e.exit());
+            server.start_listen_on(ip, port);
+
+            let mut game_state = world.write_resource::<GameState>();
+            game_state.has_local_player = false;
+            if matches.is_present("MAX_PLAYERS") {
+                game_state.max_players =
+                    Some(value_t!(matches, "MAX_PLAYERS", usize).unwrap_or_else(|e| e.exit()));
+            }
+            game_state.planet_seed = if matches.is_present("SEED") {
+                value_t!(matches, "SEED", u64).unwrap_or_else(|e| e.exit())
+            } else {
+                rand::random()
+            };
+
+            // Let the game know it's in charge of the world.
+            let mut node_resource = world.write_resource::<pk::net::NodeResource>();
+            node_resource.is_master = true;
+        } else if let Some(matches) = matches.subcommand_matches("listen") {
+            if let Some(window) = maybe_window {
+                window.set_title("Kaboom (server)".to_string());
+            }
+            let port = value_t!(matches, "PORT", u16).unwrap_or_else(|e| e.exit());
+            server.start_listen(port);
+
+            let mut game_state = world.write_resource::<GameState>();
+            if let Some(path) = matches.value_of("REPLAY_INPUT") {
+                let replay = pk::input_recording::InputReplay::open(path)
+                    .expect("Failed to load REPLAY_INPUT");
+                // Make the same planet the recording was made on.
+                game_state.planet_seed = replay.seed();
+                replay_input = Some(replay);
+            }
+            if let Some(path) = matches.value_of("RECORD_INPUT") {
+                record_input = Some((path.to_string(), game_state.planet_seed));
+            }
 
             // Let the game know it's in charge of the world.
             let mut node_resource = world.write_resource::<pk::net::NodeResource>();
             node_resource.is_master = true;
         } else if let Some(matches) = matches.subcommand_matches("connect") {
-            window.set_title("Kaboom (client)".to_string());
-            // TODO: make port configurable
+            if let Some(window) = maybe_window {
+                window.set_title("Kaboom (client)".to_string());
+            }
             let connect_addr = matches.value_of("SERVER_ADDRESS").unwrap();
             let connect_addr: SocketAddr = connect_addr.parse().expect("Invalid SERVER_ADDRESS");
             server.connect(connect_addr);
         }
     }
 
+    if let Some((path, planet_seed)) = record_input {
+        app.record_input(&path, planet_seed)
+            .expect("Failed to start recording to RECORD_INPUT");
+    }
+    if let Some(replay) = replay_input {
+        app.replay_input(replay);
+    }
+
     app.run();
 }
 
+fn port_arg() -> Arg<'static, 'static> {
+    Arg::with_name("PORT")
+        .long("port")
+        .takes_value(true)
+        .default_value(DEFAULT_PORT)
+        .help("The port to listen on")
+}
+
 fn add_systems(
     logger: &slog::Logger,
     world: &mut specs::World,
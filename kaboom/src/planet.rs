@@ -1,11 +1,17 @@
 use specs;
 use specs::{Entities, LazyUpdate, Read};
+use std::sync::Arc;
 
 use crate::pk;
-use crate::pk::globe::{Globe, Spec};
+use crate::pk::globe::{Globe, MaterialRegistry, Spec};
 
-// Create a planet to fight on.
-pub fn create(entities: &Entities<'_>, updater: &Read<'_, LazyUpdate>) -> specs::Entity {
+// Create a planet to fight on, made of any of the given materials.
+pub fn create(
+    entities: &Entities<'_>,
+    updater: &Read<'_, LazyUpdate>,
+    materials: &MaterialRegistry,
+    seed: u64,
+) -> specs::Entity {
     // Make it small enough that you can find another person easily enough.
     // TODO: eventually make it scale to the number of players present at the start of each round.
     // TODO: special generator for this; you want to have lava beneath the land
@@ -13,8 +19,7 @@ pub fn create(entities: &Entities<'_>, updater: &Read<'_, LazyUpdate>) -> specs:
     let crust_depth = 25.0;
     let floor_radius = ocean_radius - crust_depth;
     let spec = Spec::new(
-        // TODO: random seed every time.
-        14,
+        seed,
         floor_radius,
         ocean_radius,
         0.65,
@@ -24,7 +29,7 @@ pub fn create(entities: &Entities<'_>, updater: &Read<'_, LazyUpdate>) -> specs:
         // better for now in exposing bugs visually.
         [16, 16, 4],
     );
-    let globe = Globe::new(spec);
+    let globe = Globe::new_with_materials(spec, Arc::new(materials.clone()));
 
     let entity = entities.create();
     updater.insert(entity, globe);
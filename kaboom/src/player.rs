@@ -20,6 +20,9 @@ pub struct Player {
     // TODO: definitely revisit this once `specs::saveload` is released (0.11?)
     // and you can start using that.
     pub peer_id: PeerId,
+    // Cleared when the player's peer disconnects, so they no longer
+    // count towards the player limit. Only the server keeps track of this.
+    pub connected: bool,
     pub fighter_entity: Option<specs::Entity>,
     pub name: String,
     pub points: i64,
@@ -27,12 +30,22 @@ pub struct Player {
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub enum PlayerMessage {
+    // Sent by the server to each new peer before anything else,
+    // so that they can make the same planet.
+    Welcome(WelcomeMessage),
     NewPlayer(NewPlayerMessage),
     // Tell a client about the new player ID created for them,
     // or the player they are taking over.
     YourPlayer(PlayerId),
     NewFighter(u64, PlayerId),
     YourFighter(u64),
+    // Sent by the server when a fighter's player has left the game.
+    RemoveFighter(u64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct WelcomeMessage {
+    pub planet_seed: u64,
 }
 
 // REVISIT: just serialize an entire player instead,
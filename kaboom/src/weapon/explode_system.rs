@@ -2,13 +2,21 @@ use slog::Logger;
 use specs;
 use specs::{Entities, Read, ReadStorage, Write, WriteStorage};
 
-use crate::pk::net::NodeResource;
+use crate::pk::cell_dweller;
+use crate::pk::globe::Globe;
+use crate::pk::net::{Destination, NodeResource, SendMessage, SendMessageQueue, Transport};
 use crate::pk::physics;
 use crate::pk::types::*;
 use crate::pk::Spatial;
 
 use super::grenade::Grenade;
+use super::{BlastCraterMessage, WeaponMessage};
 use crate::health::Health;
+use crate::message::Message;
+
+// Anything this close to an exploding grenade gets hurt,
+// and any terrain this close gets blown away.
+const BLAST_RADIUS: Real = 2.5;
 
 pub struct ExplodeSystem {
     log: Logger,
@@ -35,6 +43,8 @@ impl<'a> specs::System<'a> for ExplodeSystem {
         ReadStorage<'a, physics::Collider>,
         Write<'a, physics::RemoveBodyQueue>,
         Write<'a, physics::RemoveColliderQueue>,
+        WriteStorage<'a, Globe>,
+        Write<'a, SendMessageQueue<Message>>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -53,6 +63,8 @@ impl<'a> specs::System<'a> for ExplodeSystem {
             colliders,
             mut remove_body_queue_resource,
             mut remove_collider_queue_resource,
+            mut globes,
+            mut send_message_queue,
         ) = data;
 
         let nphysics_world = &world_resource.world;
@@ -134,7 +146,7 @@ impl<'a> specs::System<'a> for ExplodeSystem {
                     let relative_transform =
                         spatials.a_relative_to_b(living_thing_entity, grenade_entity);
                     let distance_squared = relative_transform.translation.vector.norm_squared();
-                    let blast_radius_squared = 2.5 * 2.5;
+                    let blast_radius_squared = BLAST_RADIUS * BLAST_RADIUS;
 
                     if distance_squared <= blast_radius_squared {
                         health.hp -= 100;
@@ -142,7 +154,51 @@ impl<'a> specs::System<'a> for ExplodeSystem {
                         debug!(self.log, "Damaged something!");
                     }
                 }
+
+                // Blow a hole in the ground, and tell everyone else about it.
+                for (globe_entity, globe) in (&*entities, &mut globes).join() {
+                    if !spatials.have_common_ancestor(grenade_entity, globe_entity) {
+                        continue;
+                    }
+                    let blast_center = Pt3::from(
+                        spatials
+                            .a_relative_to_b(grenade_entity, globe_entity)
+                            .translation
+                            .vector,
+                    );
+                    let removed_cells = blast_crater(globe, blast_center);
+                    if removed_cells.is_empty() {
+                        continue;
+                    }
+                    debug!(self.log, "Blasted a crater"; "cells" => removed_cells.len());
+                    send_message_queue.queue.push_back(SendMessage {
+                        destination: Destination::EveryoneElse,
+                        game_message: Message::Weapon(WeaponMessage::BlastCrater(
+                            BlastCraterMessage { removed_cells },
+                        )),
+                        transport: Transport::TCP,
+                    });
+                }
             }
         }
     }
 }
+
+// Remove everything solid within the blast radius, going through
+// the same path as mining so that shared cells on chunk edges and
+// chunk views all get updated. Returns the cells that were removed.
+//
+// Only cells in chunks we have loaded can be removed; the master
+// should have chunks loaded anywhere a grenade might reach.
+fn blast_crater(globe: &mut Globe, blast_center: Pt3) -> Vec<crate::pk::grid::Point3> {
+    let mut removed_cells = Vec::new();
+    for pos_in_owning_root in globe.loaded_cells_within(blast_center, BLAST_RADIUS) {
+        let material = globe.authoritative_cell(pos_in_owning_root).material;
+        if !globe.materials().is_solid(material) {
+            continue;
+        }
+        cell_dweller::remove_block(globe, pos_in_owning_root);
+        removed_cells.push(pos_in_owning_root.into());
+    }
+    removed_cells
+}
@@ -12,12 +12,14 @@ pub use self::shoot_system::ShootSystem;
 
 use std::collections::vec_deque::VecDeque;
 
+use crate::pk::grid::Point3;
 use crate::pk::net::RecvMessage;
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub enum WeaponMessage {
     ShootGrenade(ShootGrenadeMessage),
     NewGrenade(NewGrenadeMessage),
+    BlastCrater(BlastCraterMessage),
     // TODO: Can this become a generic when the specs
     // release with 'saveload' comes along?
     // DeleteGrenade(...),
@@ -35,6 +37,18 @@ pub struct NewGrenadeMessage {
     fired_by_cell_dweller_entity_id: u64,
 }
 
+/// Cells that the master removed from the globe because of an explosion.
+///
+/// Peers only apply this to chunks they have loaded. Cells in any other
+/// chunks are left alone, so if a peer loads one of those chunks later
+/// it will be out of sync with the master until the master tells it
+/// about those cells again (which it currently never does).
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct BlastCraterMessage {
+    // TODO: identify the globe; see `RemoveBlockMessage`.
+    removed_cells: Vec<Point3>,
+}
+
 /// `World`-global resource for inbound weapon-related network messages.
 #[derive(Default)]
 pub struct RecvMessageQueue {
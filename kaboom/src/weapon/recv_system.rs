@@ -1,9 +1,13 @@
 use slog::Logger;
 use specs;
-use specs::{Entities, LazyUpdate, Read, ReadStorage, Write};
+use specs::{Entities, LazyUpdate, Read, ReadStorage, Write, WriteStorage};
 
-use crate::pk::cell_dweller::CellDweller;
-use crate::pk::net::{Destination, EntityIds, SendMessage, SendMessageQueue, Transport};
+use crate::pk::cell_dweller::{self, CellDweller};
+use crate::pk::globe::Globe;
+use crate::pk::grid::PosInOwningRoot;
+use crate::pk::net::{
+    Destination, EntityIds, NodeResource, SendMessage, SendMessageQueue, Transport,
+};
 use crate::pk::physics::WorldResource;
 use crate::pk::Spatial;
 
@@ -34,6 +38,8 @@ impl<'a> specs::System<'a> for RecvSystem {
         ReadStorage<'a, CellDweller>,
         Read<'a, EntityIds>,
         Write<'a, WorldResource>,
+        WriteStorage<'a, Globe>,
+        Read<'a, NodeResource>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -46,6 +52,8 @@ impl<'a> specs::System<'a> for RecvSystem {
             cell_dwellers,
             entity_ids,
             mut world_resource,
+            mut globes,
+            node_resource,
         ) = data;
 
         while let Some(message) = recv_message_queue.queue.pop_front() {
@@ -102,7 +110,102 @@ impl<'a> specs::System<'a> for RecvSystem {
                         &mut world_resource,
                     );
                 }
+                WeaponMessage::BlastCrater(blast_crater_message) => {
+                    if !node_resource.is_from_master(message.source) {
+                        warn!(self.log, "Ignoring crater from a peer that isn't the master"; "peer_id" => message.source.0);
+                        continue;
+                    }
+
+                    // For now just find the first globe, and assume that's
+                    // the one we're supposed to be working with.
+                    use specs::Join;
+                    let globe = match (&mut globes).join().next() {
+                        Some(globe) => globe,
+                        None => {
+                            warn!(
+                                self.log,
+                                "Told about a crater, but there's no globe to put it in"
+                            );
+                            continue;
+                        }
+                    };
+
+                    trace!(self.log, "Blasting crater because server told me to"; "cells" => blast_crater_message.removed_cells.len());
+
+                    for pos in blast_crater_message.removed_cells {
+                        if !globe.spec().contains(pos) {
+                            warn!(self.log, "Ignoring crater cell that isn't on the globe"; "pos" => format!("{:?}", pos));
+                            continue;
+                        }
+                        let pos_in_owning_root =
+                            PosInOwningRoot::new(pos, globe.spec().root_resolution);
+                        // We'll get out of sync if we load this chunk later;
+                        // see `BlastCraterMessage`.
+                        let chunk_origin = globe.origin_of_chunk_owning(pos_in_owning_root);
+                        if globe.chunk_at(chunk_origin).is_none() {
+                            continue;
+                        }
+                        cell_dweller::remove_block(globe, pos_in_owning_root);
+                    }
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow};
+
+    use super::*;
+    use crate::pk::globe::Material;
+    use crate::pk::grid::{Point3, Root};
+    use crate::pk::net::{PeerId, RecvMessage};
+    use crate::weapon::BlastCraterMessage;
+
+    #[test]
+    fn only_the_master_blasts_craters_and_only_on_the_globe() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let mut recv_system = RecvSystem::new(&log);
+        recv_system.setup(&mut world.res);
+        world.write_resource::<NodeResource>().is_master = true;
+
+        let mut globe = Globe::new_example();
+        let spec = globe.spec();
+        let pos = Point3::new(Root::new(1), 3, 4, 0);
+        let chunk_origin =
+            globe.origin_of_chunk_owning(PosInOwningRoot::new(pos, spec.root_resolution));
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+        let globe_entity = world.create_entity().with(globe).build();
+
+        let mut blast = |world: &mut specs::World, source, removed_cells| {
+            world
+                .write_resource::<RecvMessageQueue>()
+                .queue
+                .push_back(RecvMessage {
+                    source: PeerId(source),
+                    game_message: WeaponMessage::BlastCrater(BlastCraterMessage { removed_cells }),
+                });
+            recv_system.run_now(&world.res);
+        };
+        let material = |world: &specs::World| {
+            let globes = world.read_storage::<Globe>();
+            let globe = globes.get(globe_entity).unwrap();
+            globe
+                .authoritative_cell(PosInOwningRoot::new(pos, spec.root_resolution))
+                .material
+        };
+        assert_ne!(material(&world), Material::AIR);
+
+        // Clients don't get to dig holes by themselves.
+        blast(&mut world, 1, vec![pos]);
+        assert_ne!(material(&world), Material::AIR);
+
+        // Cells that aren't on the globe at all are skipped.
+        let beyond_the_edge = Point3::new(Root::new(1), spec.root_resolution[0] + 5, 4, 0);
+        let in_no_root = Point3::new(Root::new(9), 3, 4, 0);
+        blast(&mut world, 0, vec![beyond_the_edge, in_no_root, pos]);
+        assert_eq!(material(&world), Material::AIR);
+    }
+}
@@ -6,6 +6,7 @@ use std::sync::mpsc;
 
 use crate::pk::cell_dweller::ActiveCellDweller;
 use crate::pk::input_adapter;
+use crate::pk::input_adapter::SharedKeyBindings;
 use crate::pk::net::{Destination, NetMarker, SendMessage, SendMessageQueue, Transport};
 use crate::pk::types::*;
 
@@ -16,30 +17,27 @@ use crate::message::Message;
 
 pub struct ShootInputAdapter {
     sender: mpsc::Sender<ShootEvent>,
+    key_bindings: SharedKeyBindings,
 }
 
 impl ShootInputAdapter {
-    pub fn new(sender: mpsc::Sender<ShootEvent>) -> ShootInputAdapter {
-        ShootInputAdapter { sender: sender }
+    pub fn new(
+        sender: mpsc::Sender<ShootEvent>,
+        key_bindings: SharedKeyBindings,
+    ) -> ShootInputAdapter {
+        ShootInputAdapter {
+            sender,
+            key_bindings,
+        }
     }
 }
 
 impl input_adapter::InputAdapter for ShootInputAdapter {
     fn handle(&self, input_event: &Input) {
-        use piston::input::keyboard::Key;
-        use piston::input::{Button, ButtonState};
-
-        if let &Input::Button(button_args) = input_event {
-            if let Button::Keyboard(key) = button_args.button {
-                let is_down = match button_args.state {
-                    ButtonState::Press => true,
-                    ButtonState::Release => false,
-                };
-                match key {
-                    Key::Space => self.sender.send(ShootEvent(is_down)).unwrap(),
-                    _ => (),
-                }
-            }
+        use crate::pk::input_adapter::Action;
+
+        if let Some((Action::Shoot, is_down)) = self.key_bindings.action_for_input(input_event) {
+            self.sender.send(ShootEvent(is_down)).unwrap();
         }
     }
 }
@@ -1,6 +1,6 @@
 pub type DirIndex = u8;
 
-#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
 pub struct Dir {
     pub index: DirIndex,
 }
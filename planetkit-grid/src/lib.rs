@@ -15,6 +15,7 @@ mod grid_point3;
 pub mod movement;
 mod neighbors;
 mod root;
+mod spiral;
 
 // TODO: be selective in what you export; no wildcards!
 pub use self::dir::*;
@@ -23,18 +24,68 @@ pub use self::grid_point2::Point2;
 pub use self::grid_point3::*;
 pub use self::neighbors::*;
 pub use self::root::*;
+pub use self::spiral::{Ring, Spiral};
 
 // TODO: Generic!
 pub type GridCoord = i64;
 
 /// Generate a random column on the globe.
+///
+/// Every distinct column is equally likely to be chosen, and the
+/// result is always expressed in the root that owns it. Cells are all
+/// roughly the same size, so this is close to uniformly distributed
+/// over the surface of the globe.
 pub fn random_column<R: Rng>(root_resolution: [GridCoord; 2], rng: &mut R) -> Point2 {
-    // TODO: this is a bit dodgy; it isn't uniformly distributed
-    // over all points in the world. You should just reject any points
-    // that turn out not to be the canonical representation,
-    // and try again.
-    let root_index: RootIndex = rng.gen_range(0, 5);
-    let x: GridCoord = rng.gen_range(0, root_resolution[0]);
-    let y: GridCoord = rng.gen_range(0, root_resolution[0]);
-    Point2::new(root_index.into(), x, y)
+    // Each root owns all of the columns in its quad except those along
+    // its north-west, mid-west, and south-west edges (see `PosInOwningRoot`),
+    // which leaves one column per (x, y) pair with `x` in `0..x_res`
+    // and `y` in `1..=y_res`. On top of that there are the two poles.
+    let columns_per_root = root_resolution[0] * root_resolution[1];
+    let column_count = columns_per_root * ROOTS.len() as GridCoord + 2;
+    let index: GridCoord = rng.gen_range(0, column_count);
+    if index == column_count - 2 {
+        Point2::new(ROOTS[0], 0, 0)
+    } else if index == column_count - 1 {
+        Point2::new(ROOTS[4], root_resolution[0], root_resolution[1])
+    } else {
+        let root = ROOTS[(index / columns_per_root) as usize];
+        let index_in_root = index % columns_per_root;
+        let x = index_in_root % root_resolution[0];
+        let y = 1 + index_in_root / root_resolution[0];
+        Point2::new(root, x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn random_columns_cover_whole_globe_evenly() {
+        let resolution = [2, 4];
+        // A geodesic grid like ours has `10 n^2 + 2` columns.
+        let column_count = 10 * 2 * 2 + 2;
+        let samples_per_column = 500;
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut counts: HashMap<Point2, usize> = HashMap::new();
+        for _ in 0..(column_count * samples_per_column) {
+            let column = random_column(resolution, &mut rng);
+            // Should always be in its owning root.
+            let pos_in_owning_root = PosInOwningRoot::new(column.with_z(0), resolution);
+            assert_eq!(pos_in_owning_root.pos().rxy, column);
+            *counts.entry(column).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts.len(), column_count);
+        for &count in counts.values() {
+            assert!(count > samples_per_column * 3 / 4);
+            assert!(count < samples_per_column * 5 / 4);
+        }
+    }
 }
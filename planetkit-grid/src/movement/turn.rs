@@ -3,7 +3,7 @@ use crate::{Dir, GridCoord, Point3};
 use super::transform::*;
 use super::util::*;
 
-#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
 pub enum TurnDir {
     Left,
     Right,
@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+use std::mem;
+
+use super::{GridCoord, Neighbors, Point3, PosInOwningRoot};
+
+/// Iterator over every cell within a given number of steps of a center cell,
+/// nearest first, yielding each cell along with its distance from the center.
+///
+/// Distance is counted in steps between neighboring cells, so near pentagons
+/// the rings of cells at each distance are a bit smaller than elsewhere.
+/// Each distinct cell is yielded exactly once, expressed in its owning root.
+///
+/// Cells at the same distance are yielded in an arbitrary order.
+pub struct Spiral {
+    resolution: [GridCoord; 2],
+    max_distance: GridCoord,
+    include_above_and_below: bool,
+    seen: HashSet<Point3>,
+    current_ring: Vec<Point3>,
+    next_ring: Vec<Point3>,
+    current_distance: GridCoord,
+    next_index: usize,
+}
+
+impl Spiral {
+    /// Cells in the same layer as `center`, i.e., with the same `z`.
+    pub fn new(center: Point3, max_distance: GridCoord, resolution: [GridCoord; 2]) -> Spiral {
+        Spiral::new_impl(center, max_distance, resolution, false)
+    }
+
+    /// Cells in any layer, where each step up or down counts
+    /// the same as a step to a horizontal neighbor.
+    ///
+    /// Doesn't include anything below `z == 0`.
+    pub fn new_3d(center: Point3, max_distance: GridCoord, resolution: [GridCoord; 2]) -> Spiral {
+        Spiral::new_impl(center, max_distance, resolution, true)
+    }
+
+    fn new_impl(
+        center: Point3,
+        max_distance: GridCoord,
+        resolution: [GridCoord; 2],
+        include_above_and_below: bool,
+    ) -> Spiral {
+        let center: Point3 = PosInOwningRoot::new(center, resolution).into();
+        let mut seen = HashSet::new();
+        seen.insert(center);
+        Spiral {
+            resolution,
+            max_distance,
+            include_above_and_below,
+            seen,
+            current_ring: vec![center],
+            next_ring: Vec::new(),
+            current_distance: 0,
+            next_index: 0,
+        }
+    }
+
+    // Find everything one step further out than the current ring
+    // that we haven't already seen, and make that the current ring.
+    fn advance_ring(&mut self) {
+        for &pos in &self.current_ring {
+            for neighbor in Neighbors::new(pos, self.resolution) {
+                if !self.include_above_and_below && neighbor.z != pos.z {
+                    continue;
+                }
+                let neighbor: Point3 = PosInOwningRoot::new(neighbor, self.resolution).into();
+                if self.seen.insert(neighbor) {
+                    self.next_ring.push(neighbor);
+                }
+            }
+        }
+        self.current_ring.clear();
+        mem::swap(&mut self.current_ring, &mut self.next_ring);
+        self.current_distance += 1;
+        self.next_index = 0;
+    }
+}
+
+impl Iterator for Spiral {
+    type Item = (Point3, GridCoord);
+
+    fn next(&mut self) -> Option<(Point3, GridCoord)> {
+        while self.next_index == self.current_ring.len() {
+            if self.current_ring.is_empty() || self.current_distance == self.max_distance {
+                return None;
+            }
+            self.advance_ring();
+        }
+        let pos = self.current_ring[self.next_index];
+        self.next_index += 1;
+        Some((pos, self.current_distance))
+    }
+}
+
+/// Iterator over the cells exactly `distance` steps away from a center cell,
+/// in the same layer. See `Spiral`.
+pub struct Ring {
+    spiral: Spiral,
+    distance: GridCoord,
+}
+
+impl Ring {
+    pub fn new(center: Point3, distance: GridCoord, resolution: [GridCoord; 2]) -> Ring {
+        Ring {
+            spiral: Spiral::new(center, distance, resolution),
+            distance,
+        }
+    }
+}
+
+impl Iterator for Ring {
+    type Item = Point3;
+
+    fn next(&mut self) -> Option<Point3> {
+        let distance = self.distance;
+        self.spiral
+            .by_ref()
+            .find(|&(_, d)| d == distance)
+            .map(|(pos, _)| pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Root;
+    use super::*;
+
+    const RESOLUTION: [GridCoord; 2] = [32, 64];
+
+    // Check that each cell is only yielded once, that they come out
+    // nearest first, and that every cell past the center really is
+    // right next to one a step closer.
+    fn check_spiral(spiral: Spiral) -> Vec<(Point3, GridCoord)> {
+        let cells: Vec<(Point3, GridCoord)> = spiral.collect();
+        let distinct: HashSet<Point3> = cells.iter().map(|&(pos, _)| pos).collect();
+        assert_eq!(distinct.len(), cells.len());
+        for pair in cells.windows(2) {
+            assert!(pair[0].1 <= pair[1].1);
+        }
+        for &(pos, distance) in cells.iter().skip(1) {
+            assert!(Neighbors::new(pos, RESOLUTION)
+                .map(|neighbor| PosInOwningRoot::new(neighbor, RESOLUTION).into())
+                .any(|neighbor: Point3| cells.contains(&(neighbor, distance - 1))));
+        }
+        cells
+    }
+
+    fn count_at(cells: &[(Point3, GridCoord)], distance: GridCoord) -> usize {
+        cells.iter().filter(|&&(_, d)| d == distance).count()
+    }
+
+    #[test]
+    fn spiral_in_middle_of_root() {
+        let center = Point3::new(Root::new(2), 10, 20, 3);
+        let cells = check_spiral(Spiral::new(center, 4, RESOLUTION));
+        assert_eq!(cells[0], (center, 0));
+        for distance in 1..=4 {
+            assert_eq!(count_at(&cells, distance), 6 * distance as usize);
+        }
+        assert_eq!(Ring::new(center, 3, RESOLUTION).count(), 18);
+    }
+
+    #[test]
+    fn spiral_across_root_seam() {
+        // On the edge between two roots, well away from any pentagons.
+        let center = Point3::new(Root::new(0), 0, 48, 0);
+        let cells = check_spiral(Spiral::new(center, 3, RESOLUTION));
+        for distance in 1..=3 {
+            assert_eq!(count_at(&cells, distance), 6 * distance as usize);
+        }
+        assert!(cells.iter().any(|&(pos, _)| pos.root != center.root));
+    }
+
+    #[test]
+    fn spiral_around_pentagon() {
+        let north_pole = Point3::new(Root::new(0), 0, 0, 0);
+        let cells = check_spiral(Spiral::new(north_pole, 3, RESOLUTION));
+        for distance in 1..=3 {
+            assert_eq!(count_at(&cells, distance), 5 * distance as usize);
+        }
+    }
+
+    #[test]
+    fn spiral_3d() {
+        let center = Point3::new(Root::new(3), 10, 20, 5);
+        let cells = check_spiral(Spiral::new_3d(center, 2, RESOLUTION));
+        // Within two steps in the same layer, within one step
+        // in each layer above and below, and right above and below.
+        assert_eq!(cells.len(), 19 + 7 * 2 + 2);
+
+        // There's nothing below the bottom layer.
+        let cells = check_spiral(Spiral::new_3d(center.with_z(0), 1, RESOLUTION));
+        assert_eq!(cells.len(), 1 + 6 + 1);
+    }
+}
@@ -1,16 +1,23 @@
 use camera_controllers;
 use gfx;
 use gfx_device_gl;
-use piston::input::{RenderArgs, UpdateArgs};
+use piston::input::{Input, RenderArgs, UpdateArgs};
 use piston_window::PistonWindow;
 use slog::Logger;
 use specs;
+use std::io;
+use std::path::Path;
 use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::globe::Globe;
 use crate::input_adapter::InputAdapter;
+use crate::input_recording::{InputRecorder, InputReplay};
 use crate::render;
 use crate::render::{Mesh, MeshRepository, Visual};
 use crate::types::*;
+use crate::QuitResource;
 
 fn get_projection(w: &PistonWindow) -> [[f32; 4]; 4] {
     use camera_controllers::CameraPerspective;
@@ -20,19 +27,57 @@ fn get_projection(w: &PistonWindow) -> [[f32; 4]; 4] {
     CameraPerspective {
         fov: 90.0,
         near_clip: 0.01,
-        far_clip: 100.0,
+        // Far enough to see distant terrain drawn by `LodSystem`.
+        far_clip: 1000.0,
         aspect_ratio: (draw_size.width as f32) / (draw_size.height as f32),
     }
     .projection()
 }
 
+/// How many times per second an `App` runs its systems,
+/// unless told otherwise. See `App::set_ticks_per_second`.
+pub const DEFAULT_TICKS_PER_SECOND: f64 = 60.0;
+
+/// Most ticks to run in a single update when the simulation has fallen behind.
+/// Any more time than that is dropped, so that one slow update can't make
+/// every update after it even slower.
+const MAX_TICKS_PER_UPDATE: u32 = 10;
+
+/// Runs the game.
+///
+/// Systems are run ("ticked") at a fixed rate, regardless of how often
+/// the window asks for updates, so that the simulation behaves the same
+/// whatever the frame rate. Every tick sees the same `TimeDeltaResource`,
+/// and counts up the `TickResource`. Rendering happens at most once per
+/// update, and can use the `InterpolationAlphaResource` to smooth over
+/// the gaps between ticks.
+///
+/// Input can be recorded and replayed later; see `input_recording`.
+///
+/// Runs until the window is closed, the `QuitResource` is set,
+/// or the process is sent SIGINT or SIGTERM, and then saves
+/// any modified chunks before returning.
 pub struct App {
     t: TimeDelta,
     log: Logger,
     world: specs::World,
     dispatcher: specs::Dispatcher<'static, 'static>,
-    encoder_channel: render::EncoderChannel<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
     input_adapters: Vec<Box<dyn InputAdapter>>,
+    ticks_per_second: f64,
+    // Time that has passed but that the systems haven't yet been run for.
+    unsimulated_time: TimeDelta,
+    input_recorder: Option<InputRecorder>,
+    input_replay: Option<InputReplay>,
+    // Everything to do with drawing to a window,
+    // or `None` if this app is headless.
+    graphics: Option<Graphics>,
+}
+
+// The parts of an `App` that only exist when there's a window to draw to.
+struct Graphics {
+    // Just the render system; run once per update, after all the ticks.
+    render_dispatcher: specs::Dispatcher<'static, 'static>,
+    encoder_channel: render::EncoderChannel<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
     // TEMP: Share with rendering system until the rendering system
     // is smart enough to take full ownership of it.
     projection: Arc<Mutex<[[f32; 4]; 4]>>,
@@ -118,59 +163,180 @@ impl App {
             mesh_repo_ptr.clone(),
         );
 
-        let dispatcher_builder = dispatcher_builder
-            // Wait for unknown systems to finish before rendering.
-            .with_barrier()
-            .with(render_sys, "render", &[]);
+        let mut render_dispatcher = specs::DispatcherBuilder::new()
+            .with(render_sys, "render", &[])
+            .build();
+        render_dispatcher.setup(&mut world.res);
+
+        let mut dispatcher = dispatcher_builder.build();
+        // We'll be wanting to poke things into queues before we first
+        // call `dispatch`, so ensure all resources exist.
+        dispatcher.setup(&mut world.res);
+        add_app_resources(&mut world);
+
+        App {
+            t: 0.0,
+            log,
+            world,
+            dispatcher,
+            input_adapters: Vec::new(),
+            ticks_per_second: DEFAULT_TICKS_PER_SECOND,
+            unsimulated_time: 0.0,
+            input_recorder: None,
+            input_replay: None,
+            graphics: Some(Graphics {
+                render_dispatcher,
+                encoder_channel: device_encoder_channel,
+                projection,
+                first_person: first_person_mutex_arc,
+                factory: factory.clone(),
+                output_color: window.output_color.clone(),
+                output_stencil: window.output_stencil.clone(),
+                mesh_repo: mesh_repo_ptr,
+                window,
+            }),
+        }
+    }
+
+    /// Make an app with no window, that doesn't render anything,
+    /// and instead just runs its systems at the tick rate.
+    ///
+    /// This is useful for running dedicated servers on machines
+    /// with no display, or for running simulations.
+    pub fn new_headless(
+        parent_log: &Logger,
+        mut world: specs::World,
+        dispatcher_builder: specs::DispatcherBuilder<'static, 'static>,
+    ) -> App {
+        let log = parent_log.new(o!());
 
         let mut dispatcher = dispatcher_builder.build();
         // We'll be wanting to poke things into queues before we first
         // call `dispatch`, so ensure all resources exist.
         dispatcher.setup(&mut world.res);
+        add_app_resources(&mut world);
 
         App {
             t: 0.0,
             log,
             world,
             dispatcher,
-            encoder_channel: device_encoder_channel,
             input_adapters: Vec::new(),
-            projection,
-            first_person: first_person_mutex_arc,
-            factory: factory.clone(),
-            output_color: window.output_color.clone(),
-            output_stencil: window.output_stencil.clone(),
-            mesh_repo: mesh_repo_ptr,
-            window,
+            ticks_per_second: DEFAULT_TICKS_PER_SECOND,
+            unsimulated_time: 0.0,
+            input_recorder: None,
+            input_replay: None,
+            graphics: None,
         }
     }
 
+    /// Set how many times per second to run all the systems.
+    pub fn set_ticks_per_second(&mut self, new_ticks_per_second: f64) {
+        assert!(new_ticks_per_second > 0.0);
+        self.ticks_per_second = new_ticks_per_second;
+    }
+
+    /// Start recording all input events to a file at `path`,
+    /// along with `seed`; see `input_recording`.
+    pub fn record_input<P: AsRef<Path>>(&mut self, path: P, seed: u64) -> io::Result<()> {
+        self.input_recorder = Some(InputRecorder::create(path, seed, self.ticks_per_second)?);
+        Ok(())
+    }
+
+    /// Replay recorded input events at the same ticks they were recorded at,
+    /// ignoring any live input in the meantime. The tick rate is set to match
+    /// the recording.
+    ///
+    /// The world should be set up the same way as when the recording started,
+    /// using the same seed, and the app shouldn't have run any ticks yet.
+    pub fn replay_input(&mut self, replay: InputReplay) {
+        info!(self.log, "Replaying recorded input"; "seed" => replay.seed());
+        self.set_ticks_per_second(replay.ticks_per_second());
+        self.input_replay = Some(replay);
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.graphics.is_none()
+    }
+
     pub fn run(&mut self) {
+        QuitResource::quit_on_signals();
+        if self.is_headless() {
+            self.run_headless();
+        } else {
+            self.run_gui();
+        }
+        self.save_globes();
+    }
+
+    // Save any changes to terrain that are still only in memory,
+    // for globes that have somewhere to save them.
+    fn save_globes(&mut self) {
+        use specs::Join;
+
+        let globes = self.world.read_storage::<Globe>();
+        for globe in globes.join() {
+            if let Err(err) = globe.save_all_chunks() {
+                warn!(self.log, "Failed to save modified chunks"; "error" => format!("{}", err));
+            }
+        }
+    }
+
+    fn run_headless(&mut self) {
+        info!(self.log, "Starting headless update loop"; "ticks_per_second" => self.ticks_per_second);
+
+        // Sleep for about a tick between updates, and let `update` work out
+        // how many ticks are actually due based on how long it's really been.
+        let tick_interval = Duration::from_secs_f64(1.0 / self.ticks_per_second);
+        let mut last_update_at = Instant::now();
+        let quit = self.world.read_resource::<QuitResource>().clone();
+        while !quit.should_quit() {
+            let elapsed = last_update_at.elapsed();
+            if elapsed < tick_interval {
+                thread::sleep(tick_interval - elapsed);
+            }
+            let now = Instant::now();
+            let dt = (now - last_update_at).as_secs_f64();
+            last_update_at = now;
+            self.update(UpdateArgs { dt });
+        }
+
+        info!(self.log, "Quitting");
+    }
+
+    fn run_gui(&mut self) {
         use piston::input::*;
 
         info!(self.log, "Starting event loop");
 
-        let mut events = self.window.events;
-        while let Some(e) = events.next(&mut self.window) {
-            self.first_person.lock().unwrap().event(&e);
+        let mut events = self.graphics().window.events;
+        let quit = self.world.read_resource::<QuitResource>().clone();
+        while let Some(e) = events.next(&mut self.graphics_mut().window) {
+            if quit.should_quit() {
+                break;
+            }
+
+            self.graphics().first_person.lock().unwrap().event(&e);
 
             if let Some(r) = e.render_args() {
                 self.render(&r);
             }
 
             if e.resize_args().is_some() {
-                let mut projection = self.projection.lock().unwrap();
-                *projection = get_projection(&self.window);
+                let graphics = self.graphics();
+                let mut projection = graphics.projection.lock().unwrap();
+                *projection = get_projection(&graphics.window);
             }
 
             if let Some(u) = e.update_args() {
                 self.update(u);
             }
 
-            // Dispatch input events to any systems that care.
+            // Dispatch input events to any systems that care,
+            // unless we're replaying recorded input instead.
             if let Event::Input(input) = e {
-                for adapter in &self.input_adapters {
-                    adapter.handle(&input);
+                if self.input_replay.is_none() {
+                    self.handle_input(&input);
                 }
             }
         }
@@ -185,7 +351,8 @@ impl App {
         // to get around to frame/update rate limiting, so I'm
         // relying on Piston's for now.
         use std::sync::mpsc::TryRecvError;
-        let mut encoder = match self.encoder_channel.receiver.try_recv() {
+        let graphics = self.graphics_mut();
+        let mut encoder = match graphics.encoder_channel.receiver.try_recv() {
             Ok(encoder) => encoder,
             Err(TryRecvError::Empty) => return,
             Err(TryRecvError::Disconnected) => {
@@ -196,21 +363,83 @@ impl App {
         // TODO: what's make_current actually necessary for?
         // Do I even need to do this? (Ripped off `draw_3d`.)
         use piston::window::OpenGLWindow;
-        self.window.window.make_current();
+        graphics.window.window.make_current();
 
-        encoder.flush(&mut self.window.device);
+        encoder.flush(&mut graphics.window.device);
 
-        self.encoder_channel.sender.send(encoder).unwrap();
+        graphics.encoder_channel.sender.send(encoder).unwrap();
     }
 
     fn update(&mut self, args: UpdateArgs) {
         self.t += args.dt;
 
-        self.world.write_resource::<TimeDeltaResource>().0 = args.dt;
+        // Run as many ticks as are due.
+        let tick_dt = 1.0 / self.ticks_per_second;
+        self.unsimulated_time += args.dt;
+        let mut ticks_this_update = 0;
+        while self.unsimulated_time >= tick_dt {
+            if ticks_this_update == MAX_TICKS_PER_UPDATE {
+                warn!(self.log, "Simulation can't keep up; skipping ahead"; "seconds_skipped" => self.unsimulated_time);
+                self.unsimulated_time %= tick_dt;
+                break;
+            }
+            self.tick(tick_dt);
+            self.unsimulated_time -= tick_dt;
+            ticks_this_update += 1;
+        }
+        self.world.write_resource::<InterpolationAlphaResource>().0 =
+            self.unsimulated_time / tick_dt;
+
+        if let Some(graphics) = self.graphics.as_mut() {
+            graphics.render_dispatcher.dispatch(&self.world.res);
+            self.realize_proto_meshes();
+        }
+    }
+
+    /// Run all the systems for the given number of ticks straight away,
+    /// without waiting between them or rendering anything.
+    /// Useful for simulations, and for tests.
+    pub fn run_ticks(&mut self, tick_count: u64) {
+        let tick_dt = 1.0 / self.ticks_per_second;
+        for _ in 0..tick_count {
+            self.tick(tick_dt);
+        }
+    }
+
+    /// Pass an input event to all the input adapters,
+    /// recording it first if input is being recorded.
+    pub fn handle_input(&mut self, input: &Input) {
+        if let Some(recorder) = self.input_recorder.as_mut() {
+            // It'll be handled before the next tick runs.
+            let tick = self.world.read_resource::<TickResource>().0;
+            if let Err(err) = recorder.record(tick, input) {
+                warn!(self.log, "Failed to record input; giving up on recording"; "error" => format!("{}", err));
+                self.input_recorder = None;
+            }
+        }
+        for adapter in &self.input_adapters {
+            adapter.handle(input);
+        }
+    }
+
+    fn tick(&mut self, dt: TimeDelta) {
+        if let Some(replay) = self.input_replay.as_mut() {
+            let tick = self.world.read_resource::<TickResource>().0;
+            for input in replay.take_inputs_for_tick(tick) {
+                for adapter in &self.input_adapters {
+                    adapter.handle(&input);
+                }
+            }
+            if replay.is_finished() {
+                info!(self.log, "Finished replaying recorded input"; "tick" => tick);
+                self.input_replay = None;
+            }
+        }
+
+        self.world.write_resource::<TimeDeltaResource>().0 = dt;
         self.dispatcher.dispatch(&self.world.res);
         self.world.maintain();
-
-        self.realize_proto_meshes();
+        self.world.write_resource::<TickResource>().0 += 1;
     }
 
     // This whole thing is a horrible hack around
@@ -220,10 +449,14 @@ impl App {
     // the whole disgusting thing and find a better way
     // to work around the root problem.
     fn realize_proto_meshes(&mut self) {
+        let graphics = self
+            .graphics
+            .as_mut()
+            .expect("Headless apps have no meshes to realize");
         // NOTE: it is essential that we lock the world first.
         // Otherwise we could dead-lock against, e.g., the render
         // system while it's trying to lock the mesh repository.
-        let mut mesh_repo = self.mesh_repo.lock().unwrap();
+        let mut mesh_repo = graphics.mesh_repo.lock().unwrap();
         let mut visuals = self.world.write_storage::<Visual>();
         use specs::Join;
         for visual in (&mut visuals).join() {
@@ -240,11 +473,11 @@ impl App {
                 .expect("Just ensured this above...");
             // Realize the mesh and hand it off to the mesh repository.
             let mesh = Mesh::new(
-                &mut self.factory,
+                &mut graphics.factory,
                 proto_mesh.vertexes.clone(),
                 proto_mesh.indexes.clone(),
-                self.output_color.clone(),
-                self.output_stencil.clone(),
+                graphics.output_color.clone(),
+                graphics.output_stencil.clone(),
             );
             let mesh_pointer = mesh_repo.add_mesh(mesh);
             // We may or may not be replacing a pointer to another mesh here;
@@ -261,6 +494,18 @@ impl App {
     pub fn add_input_adapter(&mut self, adapter: Box<dyn InputAdapter>) {
         self.input_adapters.push(adapter);
     }
+
+    fn graphics(&self) -> &Graphics {
+        self.graphics
+            .as_ref()
+            .expect("Headless apps don't have any graphics")
+    }
+
+    fn graphics_mut(&mut self) -> &mut Graphics {
+        self.graphics
+            .as_mut()
+            .expect("Headless apps don't have any graphics")
+    }
 }
 
 impl<'a> App {
@@ -269,7 +514,103 @@ impl<'a> App {
     }
 
     // Hacks to get around borrowing App twice mutably.
+    //
+    // Panics if the app is headless.
     pub fn world_and_window_mut(&'a mut self) -> (&'a mut specs::World, &'a mut PistonWindow) {
-        (&mut self.world, &mut self.window)
+        let graphics = self
+            .graphics
+            .as_mut()
+            .expect("Headless apps don't have a window");
+        (&mut self.world, &mut graphics.window)
+    }
+}
+
+// Make sure the resources `App` updates every tick
+// are present, even if no systems use them.
+fn add_app_resources(world: &mut specs::World) {
+    world.add_resource(QuitResource::default());
+    world.add_resource(TimeDeltaResource(0.0));
+    world.add_resource(TickResource(0));
+    world.add_resource(InterpolationAlphaResource(0.0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_at_fixed_rate() {
+        let log = Logger::root(slog::Discard, o!());
+        let mut app = App::new_headless(&log, specs::World::new(), specs::DispatcherBuilder::new());
+        app.set_ticks_per_second(10.0);
+        let tick_and_alpha = |app: &App| {
+            (
+                app.world.read_resource::<TickResource>().0,
+                app.world.read_resource::<InterpolationAlphaResource>().0,
+            )
+        };
+
+        // Not quite enough time for a tick.
+        app.update(UpdateArgs { dt: 0.05 });
+        let (tick, alpha) = tick_and_alpha(&app);
+        assert_eq!(tick, 0);
+        assert!((alpha - 0.5).abs() < 1e-9);
+
+        // Enough for a couple more.
+        app.update(UpdateArgs { dt: 0.2 });
+        let (tick, alpha) = tick_and_alpha(&app);
+        assert_eq!(tick, 2);
+        assert!((alpha - 0.5).abs() < 1e-9);
+        assert_eq!(app.world.read_resource::<TimeDeltaResource>().0, 0.1);
+
+        // Don't try to catch up after a really long pause.
+        app.update(UpdateArgs { dt: 100.0 });
+        let (tick, alpha) = tick_and_alpha(&app);
+        assert_eq!(tick, 2 + u64::from(MAX_TICKS_PER_UPDATE));
+        assert!(alpha < 1.0);
+    }
+
+    #[test]
+    fn saves_modified_chunks_on_shutdown() {
+        use crate::globe::chunk::Material;
+        use crate::globe::ChunkStorage;
+        use crate::grid::{Point3, PosInOwningRoot, Root};
+        use crate::test_util::TempPath;
+        use specs::Builder;
+
+        let dir = TempPath::new("app-save-test");
+
+        let mut globe = Globe::new_example();
+        let spec = globe.spec();
+        globe.set_storage(ChunkStorage::new(&dir, spec.chunk_resolution).unwrap());
+        let pos = PosInOwningRoot::new(Point3::new(Root::new(2), 5, 6, 2), spec.root_resolution);
+        let chunk_origin = globe.origin_of_chunk_owning(pos);
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+        let new_material = if globe.authoritative_cell(pos).material == Material::WATER {
+            Material::DIRT
+        } else {
+            Material::WATER
+        };
+        globe.authoritative_cell_mut(pos).material = new_material;
+
+        let log = Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        world.register::<Globe>();
+        world.create_entity().with(globe).build();
+        let mut app = App::new_headless(&log, world, specs::DispatcherBuilder::new());
+        let quit = app.world.read_resource::<QuitResource>().clone();
+        let quitter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            quit.quit();
+        });
+        app.run();
+        quitter.join().unwrap();
+        assert!(app.world.read_resource::<TickResource>().0 > 0);
+
+        // The change should still be there for the next run.
+        let mut globe = Globe::new_example();
+        globe.set_storage(ChunkStorage::new(&dir, spec.chunk_resolution).unwrap());
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+        assert_eq!(globe.authoritative_cell(pos).material, new_material);
     }
 }
@@ -8,16 +8,18 @@ use slog_async;
 use slog_term;
 use specs;
 
-use crate::app::App;
+use crate::app::{App, DEFAULT_TICKS_PER_SECOND};
 use crate::cell_dweller;
+use crate::globe::{MaterialDef, MaterialRegistry};
+use crate::input_adapter::{InputAdapter, KeyBindings, SharedKeyBindings};
 use crate::net::{GameMessage, ServerResource};
 use crate::window;
 
 /// Builder for [`App`].
 ///
-/// Will eventually learn how to create different kinds of
-/// application runners, including a CLI-only one that doesn't
-/// need to include any renderering systems.
+/// Can create either an app with a window to draw to (`build_gui`),
+/// or a headless one that doesn't include any rendering systems
+/// (`build_headless`).
 ///
 /// Contains some optional convenience functions for adding
 /// commonly used systems.
@@ -26,9 +28,11 @@ pub struct AppBuilder {
     root_log: slog::Logger,
     world: specs::World,
     dispatcher_builder: shred::DispatcherBuilder<'static, 'static>,
+    ticks_per_second: f64,
     // We may or may not create these, depending on the game.
     movement_input_adapter: Option<Box<cell_dweller::MovementInputAdapter>>,
     mining_input_adapter: Option<Box<cell_dweller::MiningInputAdapter>>,
+    placement_input_adapter: Option<Box<cell_dweller::PlacementInputAdapter>>,
 }
 
 impl AppBuilder {
@@ -55,17 +59,28 @@ impl AppBuilder {
         // AutoSystems that use them should ensure that they are registered.
         let mut world = specs::World::new();
         world.register::<crate::cell_dweller::CellDweller>();
+        world.register::<crate::cell_dweller::Inventory>();
         world.register::<crate::render::Visual>();
         world.register::<crate::Spatial>();
         world.register::<crate::physics::Velocity>();
         world.register::<crate::physics::Mass>();
         world.register::<crate::globe::Globe>();
         world.register::<crate::globe::ChunkView>();
+        world.register::<crate::globe::Viewer>();
+        world.register::<crate::globe::LodPatchView>();
         world.register::<crate::net::NetMarker>();
 
         // Initialize resources that can't implement `Default`.
         world.add_resource(LogResource::new(&root_log));
 
+        // Games can add their own materials on top of the built-in ones;
+        // see `with_material`.
+        world.add_resource(MaterialRegistry::new());
+
+        // Shared by all the input adapters, and anything that wants
+        // to let the player change them; see `with_key_bindings`.
+        world.add_resource(SharedKeyBindings::default());
+
         // NOTE: You must opt in to having a `ServerResource`
         // if you want it by calling `with_networking`.
 
@@ -73,32 +88,89 @@ impl AppBuilder {
             root_log,
             world,
             dispatcher_builder: specs::DispatcherBuilder::new(),
+            ticks_per_second: DEFAULT_TICKS_PER_SECOND,
             movement_input_adapter: None,
             mining_input_adapter: None,
+            placement_input_adapter: None,
         }
     }
 
-    pub fn build_gui(self) -> App {
+    pub fn build_gui(mut self) -> App {
         // TODO: move that function into this file; it doesn't need its own module.
         let window = window::make_window(&self.root_log);
 
+        let input_adapters = self.take_input_adapters();
         // TODO: hand the root log over to App, rather than making it borrow it.
         let mut app = App::new(&self.root_log, window, self.world, self.dispatcher_builder);
-        if let Some(movement_input_adapter) = self.movement_input_adapter {
-            app.add_input_adapter(movement_input_adapter);
+        app.set_ticks_per_second(self.ticks_per_second);
+        for input_adapter in input_adapters {
+            app.add_input_adapter(input_adapter);
         }
-        if let Some(mining_input_adapter) = self.mining_input_adapter {
-            app.add_input_adapter(mining_input_adapter);
+        app
+    }
+
+    /// Make an app with no window or rendering, e.g., for dedicated servers.
+    /// Any input adapters set up by `with_common_systems` are kept, so that
+    /// recorded input can be replayed through them; see `App::replay_input`.
+    ///
+    /// See `App::new_headless`.
+    pub fn build_headless(mut self) -> App {
+        let input_adapters = self.take_input_adapters();
+        let mut app = App::new_headless(&self.root_log, self.world, self.dispatcher_builder);
+        app.set_ticks_per_second(self.ticks_per_second);
+        for input_adapter in input_adapters {
+            app.add_input_adapter(input_adapter);
         }
         app
     }
 
+    fn take_input_adapters(&mut self) -> Vec<Box<dyn InputAdapter>> {
+        let mut input_adapters: Vec<Box<dyn InputAdapter>> = Vec::new();
+        if let Some(movement_input_adapter) = self.movement_input_adapter.take() {
+            input_adapters.push(movement_input_adapter);
+        }
+        if let Some(mining_input_adapter) = self.mining_input_adapter.take() {
+            input_adapters.push(mining_input_adapter);
+        }
+        if let Some(placement_input_adapter) = self.placement_input_adapter.take() {
+            input_adapters.push(placement_input_adapter);
+        }
+        input_adapters
+    }
+
+    /// Set how many times per second the app will run all its systems.
+    /// See `App::set_ticks_per_second`.
+    pub fn with_tick_rate(mut self, ticks_per_second: f64) -> Self {
+        self.ticks_per_second = ticks_per_second;
+        self
+    }
+
     pub fn with_systems<F: AddSystemsFn<'static, 'static>>(mut self, add_systems_fn: F) -> Self {
         self.dispatcher_builder =
             add_systems_fn(&self.root_log, &mut self.world, self.dispatcher_builder);
         self
     }
 
+    /// Register a material that cells can be made of, in addition
+    /// to the built-in ones. See `globe::material`.
+    ///
+    /// Only globes created after this will be able to use the material.
+    pub fn with_material(self, material_def: MaterialDef) -> Self {
+        self.world
+            .write_resource::<MaterialRegistry>()
+            .register(material_def);
+        self
+    }
+
+    /// Use the given key bindings instead of the defaults,
+    /// e.g., as loaded from a config file by `KeyBindings::load`.
+    ///
+    /// They can be changed later through the `SharedKeyBindings` resource.
+    pub fn with_key_bindings(self, key_bindings: KeyBindings) -> Self {
+        *self.world.read_resource::<SharedKeyBindings>().lock() = key_bindings;
+        self
+    }
+
     // TODO: Remark (assert!) on how this must
     // be called before adding any networking-related systems.
     pub fn with_networking<G: GameMessage>(mut self) -> Self {
@@ -113,14 +185,23 @@ impl AppBuilder {
         use crate::globe;
 
         // Set up input adapters.
+        let key_bindings = self.world.read_resource::<SharedKeyBindings>().clone();
         let (movement_input_sender, movement_input_receiver) = mpsc::channel();
         self.movement_input_adapter = Some(Box::new(cell_dweller::MovementInputAdapter::new(
             movement_input_sender,
+            key_bindings.clone(),
         )));
 
         let (mining_input_sender, mining_input_receiver) = mpsc::channel();
         self.mining_input_adapter = Some(Box::new(cell_dweller::MiningInputAdapter::new(
             mining_input_sender,
+            key_bindings.clone(),
+        )));
+
+        let (placement_input_sender, placement_input_receiver) = mpsc::channel();
+        self.placement_input_adapter = Some(Box::new(cell_dweller::PlacementInputAdapter::new(
+            placement_input_sender,
+            key_bindings,
         )));
 
         let movement_sys =
@@ -128,6 +209,9 @@ impl AppBuilder {
 
         let mining_sys = cell_dweller::MiningSystem::new(mining_input_receiver, &self.root_log);
 
+        let placement_sys =
+            cell_dweller::PlacementSystem::new(placement_input_receiver, &self.root_log);
+
         let cd_physics_sys = cell_dweller::PhysicsSystem::new(
             &self.root_log,
             0.1, // Seconds between falls
@@ -140,6 +224,11 @@ impl AppBuilder {
             0.05, // Seconds between geometry creation
         );
 
+        let lod_sys = globe::LodSystem::new(
+            &self.root_log,
+            0.05, // Seconds between geometry creation
+        );
+
         self.with_systems(
             |_logger: &slog::Logger,
              _world: &mut specs::World,
@@ -149,12 +238,15 @@ impl AppBuilder {
                     // to avoid another frame of lag.
                     .with(movement_sys, "cd_movement", &[])
                     .with(mining_sys, "cd_mining", &["cd_movement"])
+                    .with(placement_sys, "cd_placement", &["cd_movement"])
                     .with_barrier()
                     .with(cd_physics_sys, "cd_physics", &[])
                     .with(chunk_sys, "chunk", &[])
                     // Don't depend on chunk system; chunk view can lag happily, so we'd prefer
                     // to be able to run it in parallel.
                     .with(chunk_view_sys, "chunk_view", &[])
+                    // Hide distant terrain wherever chunk views have been made.
+                    .with(lod_sys, "lod", &["chunk_view"])
             },
         )
     }
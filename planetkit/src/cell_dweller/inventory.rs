@@ -0,0 +1,166 @@
+use specs;
+
+use crate::globe::Material;
+
+/// Number of stacks an `Inventory` can hold if you don't say otherwise.
+pub const DEFAULT_INVENTORY_CAPACITY: usize = 10;
+
+/// Most things of any one kind that can be piled into a single stack.
+pub const MAX_STACK_SIZE: u32 = 64;
+
+/// Something that can be carried around in an `Inventory`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum ItemKind {
+    /// A block dug out of a globe, that could be put back down somewhere else.
+    Block(Material),
+    /// Anything else. PlanetKit doesn't know what these are;
+    /// it's up to each game to decide what its item IDs mean.
+    Item(u16),
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Stack {
+    pub kind: ItemKind,
+    pub count: u32,
+}
+
+/// Everything a `CellDweller` is carrying.
+///
+/// The master is the source of truth for every inventory; other
+/// peers only hear about changes to the inventories of their own
+/// `CellDweller`s. See `SetInventoryMessage`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Inventory {
+    stacks: Vec<Stack>,
+    capacity: usize,
+}
+
+impl Inventory {
+    pub fn new(capacity: usize) -> Inventory {
+        Inventory {
+            stacks: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn stacks(&self) -> &[Stack] {
+        &self.stacks
+    }
+
+    /// Maximum number of stacks.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many of the given kind of thing are in the inventory, across all stacks.
+    pub fn count(&self, kind: ItemKind) -> u32 {
+        self.stacks
+            .iter()
+            .filter(|stack| stack.kind == kind)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// How many more of the given kind of thing would fit.
+    pub fn room_for(&self, kind: ItemKind) -> u32 {
+        let room_in_existing_stacks: u32 = self
+            .stacks
+            .iter()
+            .filter(|stack| stack.kind == kind)
+            .map(|stack| MAX_STACK_SIZE - stack.count)
+            .sum();
+        let free_stacks = self.capacity.saturating_sub(self.stacks.len()) as u32;
+        room_in_existing_stacks + free_stacks * MAX_STACK_SIZE
+    }
+
+    /// Add as many of `count` as will fit, topping up existing
+    /// stacks before starting new ones.
+    ///
+    /// Returns how many didn't fit.
+    pub fn add(&mut self, kind: ItemKind, count: u32) -> u32 {
+        let mut remaining = count;
+        for stack in self.stacks.iter_mut().filter(|stack| stack.kind == kind) {
+            let added = remaining.min(MAX_STACK_SIZE - stack.count);
+            stack.count += added;
+            remaining -= added;
+        }
+        while remaining > 0 && self.stacks.len() < self.capacity {
+            let added = remaining.min(MAX_STACK_SIZE);
+            self.stacks.push(Stack { kind, count: added });
+            remaining -= added;
+        }
+        remaining
+    }
+
+    /// Take `count` of the given kind of thing out of the inventory,
+    /// emptying the last stacks first.
+    ///
+    /// Returns `false` without taking anything if there aren't enough.
+    pub fn remove(&mut self, kind: ItemKind, count: u32) -> bool {
+        if self.count(kind) < count {
+            return false;
+        }
+        let mut remaining = count;
+        for stack in self
+            .stacks
+            .iter_mut()
+            .rev()
+            .filter(|stack| stack.kind == kind)
+        {
+            let removed = remaining.min(stack.count);
+            stack.count -= removed;
+            remaining -= removed;
+        }
+        self.stacks.retain(|stack| stack.count > 0);
+        true
+    }
+
+    /// The material of the first stack of blocks, if there are any.
+    pub fn first_block_material(&self) -> Option<Material> {
+        self.stacks.iter().find_map(|stack| match stack.kind {
+            ItemKind::Block(material) => Some(material),
+            _ => None,
+        })
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Inventory {
+        Inventory::new(DEFAULT_INVENTORY_CAPACITY)
+    }
+}
+
+impl specs::Component for Inventory {
+    type Storage = specs::HashMapStorage<Inventory>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacks_fill_up_and_empty_out() {
+        let dirt = ItemKind::Block(Material::DIRT);
+        let gem = ItemKind::Item(7);
+        let mut inventory = Inventory::new(3);
+
+        assert_eq!(inventory.add(dirt, MAX_STACK_SIZE + 1), 0);
+        assert_eq!(inventory.stacks().len(), 2);
+        assert_eq!(inventory.add(gem, 1), 0);
+        assert_eq!(inventory.room_for(gem), MAX_STACK_SIZE - 1);
+        assert_eq!(inventory.room_for(dirt), MAX_STACK_SIZE - 1);
+
+        // Only what fits gets added.
+        assert_eq!(inventory.add(dirt, MAX_STACK_SIZE), 1);
+        assert_eq!(inventory.count(dirt), MAX_STACK_SIZE * 2);
+
+        // Removing is all or nothing.
+        assert!(!inventory.remove(gem, 2));
+        assert_eq!(inventory.count(gem), 1);
+        assert!(inventory.remove(gem, 1));
+        assert!(inventory.remove(dirt, MAX_STACK_SIZE + 3));
+        assert_eq!(inventory.count(dirt), MAX_STACK_SIZE - 3);
+        assert_eq!(inventory.stacks().len(), 1);
+        assert_eq!(inventory.first_block_material(), Some(Material::DIRT));
+    }
+}
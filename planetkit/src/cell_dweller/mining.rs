@@ -1,15 +1,15 @@
-use super::CellDweller;
+use slog::Logger;
+
+use super::{CellDweller, Inventory, ItemKind};
 use crate::globe::chunk::{Cell, Material};
 use crate::globe::Globe;
-use crate::grid::PosInOwningRoot;
+use crate::grid::{Point3, PosInOwningRoot};
 use crate::movement::*;
 
 /// Assumes that the given CellDweller is indeed attached to the given globe.
 /// May panick if this is not true.
 pub fn can_pick_up(cd: &mut CellDweller, globe: &mut Globe) -> bool {
     // Only allow picking stuff up if you're sitting above solid ground.
-    // (Or, rather, the stuff we consider to be solid for now,
-    // which is anything other than air.)
     //
     // TODO: abstract this whole thing... you need some kind of
     // utilities for a globe.
@@ -25,7 +25,7 @@ pub fn can_pick_up(cd: &mut CellDweller, globe: &mut Globe) -> bool {
             // Chunk not loaded; wait until it is before attempting to pick up.
             Err(_) => return false,
         };
-        if under_cell.material != Material::Dirt {
+        if !globe.materials().is_solid(under_cell.material) {
             return false;
         }
     }
@@ -39,10 +39,10 @@ pub fn can_pick_up(cd: &mut CellDweller, globe: &mut Globe) -> bool {
         // Chunk might not be loaded; in that case assume nothing to pick up.
         globe
             .maybe_non_authoritative_cell(new_pos)
-            .map(|cell| cell.material == Material::Dirt)
+            .map(|cell| globe.materials().is_mineable(cell.material))
             .unwrap_or(false)
     };
-    // Also require that there's air above the block;
+    // Also require that there's air (or similar) above the block;
     // in my initial use case I don't want to allow mining below
     // the surface.
     let air_above_target = {
@@ -50,7 +50,7 @@ pub fn can_pick_up(cd: &mut CellDweller, globe: &mut Globe) -> bool {
         let above_new_pos = new_pos.with_z(new_pos.z + 1);
         globe
             .maybe_non_authoritative_cell(above_new_pos)
-            .map(|cell| cell.material == Material::Air)
+            .map(|cell| globe.materials().is_gas(cell.material))
             .unwrap_or(false)
     };
     anything_to_pick_up && air_above_target
@@ -58,9 +58,14 @@ pub fn can_pick_up(cd: &mut CellDweller, globe: &mut Globe) -> bool {
 
 // If anything was picked up, then return the position we picked up,
 // and what was in it.
+//
+// If given an inventory, then what was picked up goes into it,
+// and nothing will be picked up if there's no room for it.
+// Otherwise it just disappears.
 pub fn pick_up_if_possible(
     cd: &mut CellDweller,
     globe: &mut Globe,
+    maybe_inventory: Option<&mut Inventory>,
 ) -> Option<(PosInOwningRoot, Cell)> {
     if !can_pick_up(cd, globe) {
         return None;
@@ -78,6 +83,14 @@ pub fn pick_up_if_possible(
     // ends up both concise and legible.
     let new_pos_in_owning_root = PosInOwningRoot::new(new_pos, globe.spec().root_resolution);
 
+    let kind = ItemKind::Block(globe.authoritative_cell(new_pos_in_owning_root).material);
+    if let Some(inventory) = maybe_inventory {
+        if inventory.room_for(kind) == 0 {
+            return None;
+        }
+        inventory.add(kind, 1);
+    }
+
     let removed_cell = remove_block(globe, new_pos_in_owning_root);
 
     // We picked something up.
@@ -85,16 +98,133 @@ pub fn pick_up_if_possible(
 }
 
 pub fn remove_block(globe: &mut Globe, pos_in_owning_root: PosInOwningRoot) -> Cell {
-    use crate::globe::is_point_shared;
-
     // Keep for later, so we can return what was in it.
-    let cloned_cell = {
-        let cell = globe.authoritative_cell_mut(pos_in_owning_root);
-        let cs = *cell;
-        cell.material = Material::Air;
-        cs
+    let cloned_cell = *globe.authoritative_cell(pos_in_owning_root);
+    replace_cell(
+        globe,
+        pos_in_owning_root,
+        Cell {
+            material: Material::AIR,
+            ..cloned_cell
+        },
+    );
+    cloned_cell
+}
+
+/// Work out where the given CellDweller would put a block if it tried to place one.
+///
+/// If there's nothing solid beneath the CellDweller, e.g., because it's swimming,
+/// then that's where the block goes. Otherwise it goes in the cell in front,
+/// or if that's sitting over a hole, then into that hole instead.
+///
+/// Returns `None` if there's nowhere to place a block, or the chunks
+/// involved aren't loaded.
+///
+/// Assumes that the given CellDweller is indeed attached to the given globe.
+/// May panick if this is not true.
+pub fn placement_target(cd: &CellDweller, globe: &Globe) -> Option<Point3> {
+    let is_free = |pos: Point3| -> bool {
+        // Chunk might not be loaded; in that case assume there's no room.
+        pos.z >= 0
+            && globe
+                .maybe_non_authoritative_cell(pos)
+                .map(|cell| !globe.materials().is_solid(cell.material))
+                .unwrap_or(false)
     };
 
+    let under_pos = cd.pos.with_z(cd.pos.z - 1);
+    if is_free(under_pos) {
+        return Some(under_pos);
+    }
+
+    let mut front_pos = cd.pos;
+    let mut front_dir = cd.dir;
+    move_forward(&mut front_pos, &mut front_dir, globe.spec().root_resolution)
+        .expect("CellDweller should have been in good state.");
+    if !is_free(front_pos) {
+        return None;
+    }
+    let under_front_pos = front_pos.with_z(front_pos.z - 1);
+    if is_free(under_front_pos) {
+        Some(under_front_pos)
+    } else {
+        Some(front_pos)
+    }
+}
+
+/// If a block of the given material could be placed, then take it
+/// out of the given inventory, place it, and return the position
+/// it was placed at.
+///
+/// Blocks won't be placed on top of anything in `occupied`,
+/// e.g., the positions of other CellDwellers.
+///
+/// The material may have come from a peer, so if the globe doesn't
+/// know about it, then this logs a warning and places nothing.
+///
+/// Assumes that the given CellDweller is indeed attached to the given globe.
+/// May panick if this is not true.
+pub fn place_if_possible(
+    log: &Logger,
+    cd: &CellDweller,
+    globe: &mut Globe,
+    material: Material,
+    occupied: &[PosInOwningRoot],
+    inventory: &mut Inventory,
+) -> Option<PosInOwningRoot> {
+    let material_def = match globe.materials().try_get(material) {
+        Some(material_def) => material_def,
+        None => {
+            warn!(log, "Tried to place a block of unknown material"; "material" => format!("{:?}", material));
+            return None;
+        }
+    };
+    // Only solid things make sense to build with.
+    if !material_def.solid {
+        return None;
+    }
+    let kind = ItemKind::Block(material);
+    if inventory.count(kind) == 0 {
+        return None;
+    }
+    let target_pos = placement_target(cd, globe)?;
+    let target_pos_in_owning_root = PosInOwningRoot::new(target_pos, globe.spec().root_resolution);
+    if occupied.contains(&target_pos_in_owning_root) {
+        return None;
+    }
+    // We need to be the one with the real data to change it.
+    let chunk_origin = globe.origin_of_chunk_owning(target_pos_in_owning_root);
+    globe.chunk_at(chunk_origin)?;
+    let took_block = inventory.remove(kind, 1);
+    debug_assert!(took_block, "Just checked there was a block to take");
+    place_block(globe, target_pos_in_owning_root, material);
+    Some(target_pos_in_owning_root)
+}
+
+/// Put a block of the given material in a cell,
+/// and return what was there before.
+pub fn place_block(
+    globe: &mut Globe,
+    pos_in_owning_root: PosInOwningRoot,
+    material: Material,
+) -> Cell {
+    let cloned_cell = *globe.authoritative_cell(pos_in_owning_root);
+    let mut new_cell = Cell {
+        material,
+        ..cloned_cell
+    };
+    // Make it look like it belongs with blocks from world gen.
+    crate::globe::shade_cell(globe.spec().seed, pos_in_owning_root.into(), &mut new_cell);
+    replace_cell(globe, pos_in_owning_root, new_cell);
+    cloned_cell
+}
+
+// Change a cell, and make sure everything that depends on it finds out.
+fn replace_cell(globe: &mut Globe, pos_in_owning_root: PosInOwningRoot, new_cell: Cell) {
+    use crate::globe::is_point_shared;
+
+    globe.set_authoritative_cell(pos_in_owning_root, new_cell);
+
     // Some extra stuff is only relevant if the cell is shared
     // with another chunk (horizontal edges).
     if is_point_shared(*pos_in_owning_root.pos(), globe.spec().chunk_resolution) {
@@ -110,8 +240,4 @@ pub fn remove_block(globe: &mut Globe, pos_in_owning_root: PosInOwningRoot) -> C
     // in a closure you get back that has a reference to it?
     // Or contains a _wrapper_ around it so it knows if you mutated it? Ooooh.
     globe.mark_chunk_views_affected_by_cell_as_dirty(pos_in_owning_root.into());
-    // TODO: remember on the cell-dweller that it's carrying something?
-    // Or should that be a different kind of component?
-
-    cloned_cell
 }
@@ -9,35 +9,33 @@ use super::{
 };
 use crate::globe::Globe;
 use crate::input_adapter;
+use crate::input_adapter::SharedKeyBindings;
 use crate::net::{Destination, NetMarker, SendMessage, Transport};
 
 // TODO: own file?
 pub struct MiningInputAdapter {
     sender: mpsc::Sender<MiningEvent>,
+    key_bindings: SharedKeyBindings,
 }
 
 impl MiningInputAdapter {
-    pub fn new(sender: mpsc::Sender<MiningEvent>) -> MiningInputAdapter {
-        MiningInputAdapter { sender }
+    pub fn new(
+        sender: mpsc::Sender<MiningEvent>,
+        key_bindings: SharedKeyBindings,
+    ) -> MiningInputAdapter {
+        MiningInputAdapter {
+            sender,
+            key_bindings,
+        }
     }
 }
 
 impl input_adapter::InputAdapter for MiningInputAdapter {
     fn handle(&self, input_event: &Input) {
-        use piston::input::keyboard::Key;
-        use piston::input::{Button, ButtonState};
+        use crate::input_adapter::Action;
 
-        if let Input::Button(button_args) = *input_event {
-            if let Button::Keyboard(key) = button_args.button {
-                let is_down = match button_args.state {
-                    ButtonState::Press => true,
-                    ButtonState::Release => false,
-                };
-                match key {
-                    Key::U => self.sender.send(MiningEvent::PickUp(is_down)).unwrap(),
-                    _ => (),
-                }
-            }
+        if let Some((Action::PickUp, is_down)) = self.key_bindings.action_for_input(input_event) {
+            self.sender.send(MiningEvent::PickUp(is_down)).unwrap();
         }
     }
 }
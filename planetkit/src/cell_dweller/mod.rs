@@ -5,21 +5,29 @@
 // but I don't want to be bugged about it for now.)
 #[allow(clippy::module_inception)]
 mod cell_dweller;
+mod inventory;
 mod mining;
 mod mining_system;
 mod movement_system;
+mod pathfinding;
 mod physics_system;
+mod placement_system;
 mod recv_system;
 
+use crate::globe::Material;
 use crate::grid::{Dir, Point3};
 use crate::movement::TurnDir;
 use crate::net::{RecvMessage, SendMessage};
 use std::collections::vec_deque::VecDeque;
 
 pub use self::cell_dweller::CellDweller;
+pub use self::inventory::{Inventory, ItemKind, Stack, DEFAULT_INVENTORY_CAPACITY, MAX_STACK_SIZE};
+pub use self::mining::{place_block, remove_block};
 pub use self::mining_system::{MiningEvent, MiningInputAdapter, MiningSystem};
 pub use self::movement_system::{MovementEvent, MovementInputAdapter, MovementSystem};
+pub use self::pathfinding::{PathAction, Pathfinder, DEFAULT_MAX_SEARCH_NODES};
 pub use self::physics_system::PhysicsSystem;
+pub use self::placement_system::{PlacementEvent, PlacementInputAdapter, PlacementSystem};
 pub use self::recv_system::RecvSystem;
 
 use specs;
@@ -38,6 +46,9 @@ pub enum CellDwellerMessage {
     SetPos(SetPosMessage),
     TryPickUpBlock(TryPickUpBlockMessage),
     RemoveBlock(RemoveBlockMessage),
+    TryPlaceBlock(TryPlaceBlockMessage),
+    PlaceBlock(PlaceBlockMessage),
+    SetInventory(SetInventoryMessage),
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
@@ -84,6 +95,32 @@ pub struct RemoveBlockMessage {
     pub pos: Point3,
 }
 
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct TryPlaceBlockMessage {
+    // TODO: identify the globe; see `TryPickUpBlockMessage`.
+    pub cd_entity_id: u64,
+    // Like `TryPickUpBlockMessage`, we just use wherever the server thinks
+    // the block should go. But we do need to know what to build with.
+    pub material: Material,
+}
+
+// TODO: see remarks on `RemoveBlockMessage`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct PlaceBlockMessage {
+    // TODO: identify the globe; see `RemoveBlockMessage`.
+    pub pos: Point3,
+    pub material: Material,
+}
+
+/// Sent by the master to the peer that controls a cell dweller
+/// whenever its inventory changes.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct SetInventoryMessage {
+    pub cd_entity_id: u64,
+    // Just send the whole thing; they're not very big.
+    pub inventory: Inventory,
+}
+
 /// `World`-global resource for outbound cell-dweller network messages.
 #[derive(Default)]
 pub struct SendMessageQueue {
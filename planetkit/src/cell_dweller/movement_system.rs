@@ -5,9 +5,10 @@ use specs::{Read, ReadStorage, Write, WriteStorage};
 use std::sync::mpsc;
 
 use super::{ActiveCellDweller, CellDweller, CellDwellerMessage, SendMessageQueue, SetPosMessage};
-use crate::globe::chunk::Material;
 use crate::globe::Globe;
+use crate::grid::{Dir, Point3};
 use crate::input_adapter;
+use crate::input_adapter::SharedKeyBindings;
 use crate::movement::*;
 use crate::net::{Destination, NetMarker, SendMessage, Transport};
 use crate::types::*;
@@ -16,63 +17,37 @@ use crate::Spatial;
 // TODO: own file?
 pub struct MovementInputAdapter {
     sender: mpsc::Sender<MovementEvent>,
+    key_bindings: SharedKeyBindings,
 }
 
 impl MovementInputAdapter {
-    pub fn new(sender: mpsc::Sender<MovementEvent>) -> MovementInputAdapter {
-        MovementInputAdapter { sender }
+    pub fn new(
+        sender: mpsc::Sender<MovementEvent>,
+        key_bindings: SharedKeyBindings,
+    ) -> MovementInputAdapter {
+        MovementInputAdapter {
+            sender,
+            key_bindings,
+        }
     }
 }
 
 impl input_adapter::InputAdapter for MovementInputAdapter {
     fn handle(&self, input_event: &Input) {
-        use piston::input::keyboard::Key;
-        use piston::input::{Button, ButtonState};
+        use crate::input_adapter::Action;
 
-        if let Input::Button(button_args) = *input_event {
-            if let Button::Keyboard(key) = button_args.button {
-                let is_down = match button_args.state {
-                    ButtonState::Press => true,
-                    ButtonState::Release => false,
-                };
-                match key {
-                    // Arrow keys.
-                    Key::Up => self
-                        .sender
-                        .send(MovementEvent::StepForward(is_down))
-                        .unwrap(),
-                    Key::Down => self
-                        .sender
-                        .send(MovementEvent::StepBackward(is_down))
-                        .unwrap(),
-                    Key::Left => self.sender.send(MovementEvent::TurnLeft(is_down)).unwrap(),
-                    Key::Right => self.sender.send(MovementEvent::TurnRight(is_down)).unwrap(),
-                    // IJKL keys.
-                    Key::I => self
-                        .sender
-                        .send(MovementEvent::StepForward(is_down))
-                        .unwrap(),
-                    Key::K => self
-                        .sender
-                        .send(MovementEvent::StepBackward(is_down))
-                        .unwrap(),
-                    Key::J => self.sender.send(MovementEvent::TurnLeft(is_down)).unwrap(),
-                    Key::L => self.sender.send(MovementEvent::TurnRight(is_down)).unwrap(),
-                    // WASD keys.
-                    Key::W => self
-                        .sender
-                        .send(MovementEvent::StepForward(is_down))
-                        .unwrap(),
-                    Key::S => self
-                        .sender
-                        .send(MovementEvent::StepBackward(is_down))
-                        .unwrap(),
-                    Key::A => self.sender.send(MovementEvent::TurnLeft(is_down)).unwrap(),
-                    Key::D => self.sender.send(MovementEvent::TurnRight(is_down)).unwrap(),
-                    _ => (),
-                }
-            }
-        }
+        let (action, is_down) = match self.key_bindings.action_for_input(input_event) {
+            Some(action_and_is_down) => action_and_is_down,
+            None => return,
+        };
+        let event = match action {
+            Action::StepForward => MovementEvent::StepForward(is_down),
+            Action::StepBackward => MovementEvent::StepBackward(is_down),
+            Action::TurnLeft => MovementEvent::TurnLeft(is_down),
+            Action::TurnRight => MovementEvent::TurnRight(is_down),
+            _ => return,
+        };
+        self.sender.send(event).unwrap();
     }
 }
 
@@ -93,7 +68,7 @@ pub struct MovementSystem {
     max_step_height: u8,
 }
 
-enum ForwardOrBackward {
+pub(crate) enum ForwardOrBackward {
     Forward,
     Backward,
 }
@@ -137,71 +112,94 @@ impl MovementSystem {
         globe: &Globe,
         forward_or_backward: ForwardOrBackward,
     ) {
-        // Only allow movement if you're sitting above solid ground.
-        // (Or, rather, the stuff we consider to be solid for now,
-        // which is anything other than air.)
-        //
-        // TODO: Fix to be <= 0 and log error.
-        if cd.pos.z < 0 {
-            // There's nothing below; someone built a silly globe.
+        if globe.is_waiting_for_chunks_near(cd.pos) {
+            // Chunks we might step into are still being built;
+            // wait until they're ready before attempting to move.
             return;
         }
-        let under_pos = cd.pos.with_z(cd.pos.z - 1);
-        let under_cell = match globe.maybe_non_authoritative_cell(under_pos) {
-            Ok(cell) => cell,
-            // Chunk not loaded; wait until it is before attempting to move.
-            Err(_) => return,
-        };
-        if under_cell.material != Material::Dirt {
-            return;
-        }
-
-        // Find out whether we're actually allowed to step there.
-        let mut new_pos = cd.pos;
-        let mut new_dir = cd.dir;
-        let mut new_last_turn_bias = cd.last_turn_bias;
-
-        match forward_or_backward {
-            ForwardOrBackward::Forward => step_forward_and_face_neighbor(
-                &mut new_pos,
-                &mut new_dir,
-                globe.spec().root_resolution,
-                &mut new_last_turn_bias,
-            ),
-            ForwardOrBackward::Backward => step_backward_and_face_neighbor(
-                &mut new_pos,
-                &mut new_dir,
-                globe.spec().root_resolution,
-                &mut new_last_turn_bias,
-            ),
-        }
-        .expect("CellDweller should have been in good state.");
-
-        // Ask the globe if we can go there, attempting to climb up if there is a hil/cliff.
-        // Usually we'll allow climbing a maximum of one block, but especially in certain tests
-        // we want to let you climb higher!
-        for _ in 0..=self.max_step_height {
-            let cell = match globe.maybe_non_authoritative_cell(new_pos) {
-                Ok(cell) => cell,
-                // Chunk not loaded; wait until it is before attempting to move.
-                Err(_) => return,
-            };
-            let can_move_to_cell = cell.material != Material::Dirt;
-
-            if !can_move_to_cell {
-                // Try again one higher.
-                new_pos.z += 1;
-                continue;
-            }
 
+        let maybe_destination = step_destination(
+            globe,
+            (cd.pos, cd.dir, cd.last_turn_bias),
+            forward_or_backward,
+            self.max_step_height,
+        );
+        if let Some((new_pos, new_dir, new_last_turn_bias)) = maybe_destination {
             cd.set_cell_transform(new_pos, new_dir, new_last_turn_bias);
             // REVISIT: += ?
             cd.seconds_until_next_move = cd.seconds_between_moves;
             trace!(self.log, "Stepped"; "new_pos" => format!("{:?}", cd.pos()), "new_dir" => format!("{:?}", cd.dir()));
+        }
+    }
+}
 
-            break;
+/// Work out where a `CellDweller` at the given position, facing
+/// the given direction, would end up if it tried to take a step.
+///
+/// Returns `None` if it can't step from there, e.g., because there's
+/// no solid ground to step off, or a cliff higher than `max_step_height`
+/// in the way, or because the chunks involved aren't loaded.
+///
+/// This doesn't account for falling after the step;
+/// see `PhysicsSystem` for that.
+pub(crate) fn step_destination(
+    globe: &Globe,
+    (pos, dir, last_turn_bias): (Point3, Dir, TurnDir),
+    forward_or_backward: ForwardOrBackward,
+    max_step_height: u8,
+) -> Option<(Point3, Dir, TurnDir)> {
+    // Only allow movement if you're sitting above solid ground.
+    //
+    // TODO: Fix to be <= 0 and log error.
+    if pos.z < 0 {
+        // There's nothing below; someone built a silly globe.
+        return None;
+    }
+    let under_pos = pos.with_z(pos.z - 1);
+    // Chunk not loaded; wait until it is before attempting to move.
+    let under_cell = globe.maybe_non_authoritative_cell(under_pos).ok()?;
+    if !globe.materials().is_solid(under_cell.material) {
+        return None;
+    }
+
+    // Find out whether we're actually allowed to step there.
+    let mut new_pos = pos;
+    let mut new_dir = dir;
+    let mut new_last_turn_bias = last_turn_bias;
+
+    match forward_or_backward {
+        ForwardOrBackward::Forward => step_forward_and_face_neighbor(
+            &mut new_pos,
+            &mut new_dir,
+            globe.spec().root_resolution,
+            &mut new_last_turn_bias,
+        ),
+        ForwardOrBackward::Backward => step_backward_and_face_neighbor(
+            &mut new_pos,
+            &mut new_dir,
+            globe.spec().root_resolution,
+            &mut new_last_turn_bias,
+        ),
+    }
+    .expect("CellDweller should have been in good state.");
+
+    // Ask the globe if we can go there, attempting to climb up if there is a hil/cliff.
+    // Usually we'll allow climbing a maximum of one block, but especially in certain tests
+    // we want to let you climb higher!
+    for _ in 0..=max_step_height {
+        // Chunk not loaded; wait until it is before attempting to move.
+        let cell = globe.maybe_non_authoritative_cell(new_pos).ok()?;
+        let can_move_to_cell = !globe.materials().is_solid(cell.material);
+
+        if !can_move_to_cell {
+            // Try again one higher.
+            new_pos.z += 1;
+            continue;
         }
+
+        return Some((new_pos, new_dir, new_last_turn_bias));
     }
+    None
 }
 
 impl<'a> specs::System<'a> for MovementSystem {
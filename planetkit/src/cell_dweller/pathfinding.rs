@@ -0,0 +1,484 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::movement_system::{step_destination, ForwardOrBackward};
+use super::CellDweller;
+use crate::globe::Globe;
+use crate::grid::{Dir, GridCoord, Point2, Point3, PosInOwningRoot};
+use crate::movement::{turn_by_one_hex_edge, TurnDir};
+
+/// Give up on finding a path after considering this many
+/// positions, unless told otherwise.
+pub const DEFAULT_MAX_SEARCH_NODES: usize = 20_000;
+
+/// Something a `CellDweller` can do to follow a path;
+/// see `Pathfinder::find_path`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathAction {
+    /// Step forward like `MovementSystem` would, then fall
+    /// like `PhysicsSystem` would if there's nothing to stand on.
+    StepForward,
+    /// Turn like `CellDweller::turn`.
+    Turn(TurnDir),
+}
+
+// Where a `CellDweller` is, and which way it's facing.
+// Turning on pentagons depends on the last turn bias,
+// so that has to be part of the state, too.
+type State = (Point3, Dir, TurnDir);
+
+/// Finds ways for `CellDweller`s to get from where they are to somewhere
+/// else by walking, climbing, and falling by the same rules as
+/// `MovementSystem` and `PhysicsSystem`.
+///
+/// Only cells in loaded chunks are considered, so paths will never leave
+/// the area that is currently loaded.
+pub struct Pathfinder {
+    max_step_height: u8,
+    max_fall_height: Option<GridCoord>,
+    max_search_nodes: usize,
+}
+
+impl Pathfinder {
+    pub fn new() -> Pathfinder {
+        Pathfinder {
+            // Same as `MovementSystem`.
+            max_step_height: 1,
+            max_fall_height: None,
+            max_search_nodes: DEFAULT_MAX_SEARCH_NODES,
+        }
+    }
+
+    /// Should match whatever was given to `MovementSystem::set_step_height`.
+    pub fn set_step_height(&mut self, new_max_step_height: u8) {
+        self.max_step_height = new_max_step_height;
+    }
+
+    /// Avoid paths that fall further than this in one go,
+    /// or `None` to allow falling any distance.
+    pub fn set_max_fall_height(&mut self, new_max_fall_height: Option<GridCoord>) {
+        self.max_fall_height = new_max_fall_height;
+    }
+
+    /// Limit how much work to do before giving up on finding a path.
+    pub fn set_max_search_nodes(&mut self, new_max_search_nodes: usize) {
+        self.max_search_nodes = new_max_search_nodes;
+    }
+
+    /// Find a short sequence of actions that will get the given `CellDweller`
+    /// to `goal` on the given globe, if there is one.
+    ///
+    /// `goal` is the cell the `CellDweller` would be standing in once it
+    /// got there, not the cell it would be standing on; i.e. `goal.z` should
+    /// usually be one above the top of the land. Otherwise the goal can
+    /// never be reached, so we'll consider as many positions as we're
+    /// allowed to (see `set_max_search_nodes`) before returning `None`.
+    ///
+    /// Turns and steps are counted as equally costly.
+    pub fn find_path(
+        &self,
+        globe: &Globe,
+        cd: &CellDweller,
+        goal: Point3,
+    ) -> Option<Vec<PathAction>> {
+        self.find_path_from(globe, (cd.pos, cd.dir, cd.last_turn_bias), goal)
+    }
+
+    fn find_path_from(&self, globe: &Globe, start: State, goal: Point3) -> Option<Vec<PathAction>> {
+        let spec = globe.spec();
+        let goal = PosInOwningRoot::new(goal, spec.root_resolution);
+        let goal_on_unit_sphere = spec.cell_center_on_unit_sphere(goal.pos().rxy);
+        // Be generous; cells near pentagons are a bit bigger than those
+        // in the middle of a root, and we'd rather overestimate the distance
+        // one step covers than underestimate it.
+        let step_angle = {
+            let middle = Point2::new(
+                goal.pos().root,
+                spec.root_resolution[0] / 2,
+                spec.root_resolution[1] / 2,
+            );
+            let a = spec.cell_center_on_unit_sphere(middle);
+            let b = spec.cell_center_on_unit_sphere(middle.with_x(middle.x + 1));
+            angle_between(a, b) * 1.5
+        };
+        let estimate_cost_to_goal = |pos: Point3| -> f64 {
+            angle_between(
+                spec.cell_center_on_unit_sphere(pos.rxy),
+                goal_on_unit_sphere,
+            ) / step_angle
+        };
+
+        // Everywhere we've been, and how we got there.
+        let mut nodes: Vec<Node> = vec![Node {
+            state: start,
+            parent: None,
+        }];
+        let mut best_costs: HashMap<State, u32> = HashMap::new();
+        best_costs.insert(start, 0);
+        let mut frontier = BinaryHeap::new();
+        frontier.push(FrontierEntry {
+            estimated_total_cost: estimate_cost_to_goal(start.0),
+            cost: 0,
+            node_index: 0,
+        });
+
+        while let Some(entry) = frontier.pop() {
+            let state = nodes[entry.node_index].state;
+            if PosInOwningRoot::new(state.0, spec.root_resolution) == goal {
+                return Some(actions_to(&nodes, entry.node_index));
+            }
+            if best_costs
+                .get(&state)
+                .is_some_and(|&cost| cost < entry.cost)
+            {
+                // We've already found a better way here.
+                continue;
+            }
+            if nodes.len() >= self.max_search_nodes {
+                return None;
+            }
+
+            for &action in &[
+                PathAction::StepForward,
+                PathAction::Turn(TurnDir::Left),
+                PathAction::Turn(TurnDir::Right),
+            ] {
+                let next_state = match self.apply(globe, state, action) {
+                    Some(next_state) => next_state,
+                    None => continue,
+                };
+                let cost = entry.cost + 1;
+                if best_costs
+                    .get(&next_state)
+                    .is_some_and(|&best_cost| best_cost <= cost)
+                {
+                    continue;
+                }
+                best_costs.insert(next_state, cost);
+                nodes.push(Node {
+                    state: next_state,
+                    parent: Some((entry.node_index, action)),
+                });
+                frontier.push(FrontierEntry {
+                    estimated_total_cost: f64::from(cost) + estimate_cost_to_goal(next_state.0),
+                    cost,
+                    node_index: nodes.len() - 1,
+                });
+            }
+        }
+        None
+    }
+
+    // Where we'd end up after taking the given action, if it's possible.
+    fn apply(&self, globe: &Globe, state: State, action: PathAction) -> Option<State> {
+        match action {
+            PathAction::Turn(turn_dir) => {
+                let (mut pos, mut dir, last_turn_bias) = state;
+                turn_by_one_hex_edge(&mut pos, &mut dir, globe.spec().root_resolution, turn_dir)
+                    .expect("This suggests a bug in `movement` code.");
+                Some((pos, dir, last_turn_bias))
+            }
+            PathAction::StepForward => {
+                let (mut pos, dir, last_turn_bias) = step_destination(
+                    globe,
+                    state,
+                    ForwardOrBackward::Forward,
+                    self.max_step_height,
+                )?;
+                // Fall until we land on something.
+                let mut fallen = 0;
+                loop {
+                    if pos.z <= 0 {
+                        // There's nothing to land on.
+                        return None;
+                    }
+                    let under_cell = globe
+                        .maybe_non_authoritative_cell(pos.with_z(pos.z - 1))
+                        .ok()?;
+                    if globe.materials().is_solid(under_cell.material) {
+                        break;
+                    }
+                    pos.z -= 1;
+                    fallen += 1;
+                    if self.max_fall_height.is_some_and(|max| fallen > max) {
+                        return None;
+                    }
+                }
+                Some((pos, dir, last_turn_bias))
+            }
+        }
+    }
+}
+
+impl Default for Pathfinder {
+    fn default() -> Pathfinder {
+        Pathfinder::new()
+    }
+}
+
+struct Node {
+    state: State,
+    // How we got here, if this isn't where we started.
+    parent: Option<(usize, PathAction)>,
+}
+
+fn actions_to(nodes: &[Node], mut node_index: usize) -> Vec<PathAction> {
+    let mut actions = Vec::new();
+    while let Some((parent_index, action)) = nodes[node_index].parent {
+        actions.push(action);
+        node_index = parent_index;
+    }
+    actions.reverse();
+    actions
+}
+
+fn angle_between(a: crate::types::Pt3, b: crate::types::Pt3) -> f64 {
+    a.coords
+        .cross(&b.coords)
+        .norm()
+        .atan2(a.coords.dot(&b.coords))
+}
+
+// Ordered so that `BinaryHeap` gives us the cheapest first.
+struct FrontierEntry {
+    estimated_total_cost: f64,
+    cost: u32,
+    node_index: usize,
+}
+
+impl PartialEq for FrontierEntry {
+    fn eq(&self, other: &FrontierEntry) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FrontierEntry {}
+
+impl PartialOrd for FrontierEntry {
+    fn partial_cmp(&self, other: &FrontierEntry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FrontierEntry {
+    fn cmp(&self, other: &FrontierEntry) -> Ordering {
+        other
+            .estimated_total_cost
+            .partial_cmp(&self.estimated_total_cost)
+            .unwrap_or(Ordering::Equal)
+            // Prefer paths that have got further.
+            .then_with(|| self.cost.cmp(&other.cost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256StarStar;
+
+    use super::*;
+    use crate::globe::chunk::Material;
+    use crate::globe::ChunkOrigin;
+    use crate::grid::{Root, ROOTS};
+    use crate::test_util::{flat_example_globe, FLAT_SURFACE_Z};
+
+    #[test]
+    fn find_path_to_somewhere_reachable() {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(42);
+        let mut globe = Globe::new_example();
+        let spec = globe.spec();
+        let start_pos = globe
+            .air_above_random_surface_dry_land(&mut rng, 2, 5, 20)
+            .expect("Should have found somewhere to start");
+
+        // Load everything nearby.
+        let res = spec.root_resolution;
+        let chunk_res = spec.chunk_resolution;
+        let chunk_origin = globe.origin_of_chunk_in_same_root_containing(start_pos);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let origin = chunk_origin.pos();
+                    let x = origin.x + dx * chunk_res[0];
+                    let y = origin.y + dy * chunk_res[1];
+                    let z = origin.z + dz * chunk_res[2];
+                    if x < 0 || y < 0 || x >= res[0] || y >= res[1] || z < 0 {
+                        continue;
+                    }
+                    globe
+                        .ensure_chunk_present(ChunkOrigin::new(
+                            Point3::new(origin.root, x, y, z),
+                            res,
+                            chunk_res,
+                        ))
+                        .unwrap();
+                }
+            }
+        }
+
+        // Wander around a bit to find somewhere we know we can get to.
+        let pathfinder = Pathfinder::new();
+        let start = (start_pos, Dir::default(), TurnDir::Left);
+        let mut state = start;
+        let mut wander_actions = 0;
+        for _ in 0..30 {
+            let action = match rng.gen_range(0, 4) {
+                0 => PathAction::Turn(TurnDir::Left),
+                1 => PathAction::Turn(TurnDir::Right),
+                _ => PathAction::StepForward,
+            };
+            if let Some(next_state) = pathfinder.apply(&globe, state, action) {
+                state = next_state;
+                wander_actions += 1;
+            }
+        }
+        let goal = state.0;
+
+        let path = pathfinder
+            .find_path_from(&globe, start, goal)
+            .expect("Should have found a path");
+        assert!(path.len() <= wander_actions);
+
+        // Following the path should actually get us there.
+        let mut state = start;
+        for action in path {
+            state = pathfinder
+                .apply(&globe, state, action)
+                .expect("Path should only include possible actions");
+        }
+        assert_eq!(
+            PosInOwningRoot::new(state.0, res),
+            PosInOwningRoot::new(goal, res)
+        );
+    }
+
+    // Load every chunk around the surface of a `flat_example_globe`.
+    fn flat_globe_with_surface_loaded() -> Globe {
+        let mut globe = flat_example_globe();
+        let spec = globe.spec();
+        let res = spec.root_resolution;
+        let chunk_res = spec.chunk_resolution;
+        let z = FLAT_SURFACE_Z / chunk_res[2] * chunk_res[2];
+        for root in &ROOTS {
+            for x in (0..res[0]).step_by(chunk_res[0] as usize) {
+                for y in (0..res[1]).step_by(chunk_res[1] as usize) {
+                    globe
+                        .ensure_chunk_present(ChunkOrigin::new(
+                            Point3::new(*root, x, y, z),
+                            res,
+                            chunk_res,
+                        ))
+                        .unwrap();
+                }
+            }
+        }
+        globe
+    }
+
+    // Follow the path, and return everywhere it took us.
+    fn follow(
+        pathfinder: &Pathfinder,
+        globe: &Globe,
+        start: State,
+        path: &[PathAction],
+    ) -> Vec<State> {
+        let mut states = vec![start];
+        for &action in path {
+            let state = pathfinder
+                .apply(globe, *states.last().unwrap(), action)
+                .expect("Path should only include possible actions");
+            states.push(state);
+        }
+        states
+    }
+
+    #[test]
+    fn find_path_across_root_seam() {
+        let globe = flat_globe_with_surface_loaded();
+        let res = globe.spec().root_resolution;
+        let pathfinder = Pathfinder::new();
+
+        // The far edge of root 1 is the near edge of root 0.
+        let start_pos = Point3::new(Root::new(1), 62, 30, FLAT_SURFACE_Z);
+        let goal = Point3::new(Root::new(0), 3, 94, FLAT_SURFACE_Z);
+        let start = (start_pos, Dir::default(), TurnDir::Left);
+
+        let path = pathfinder
+            .find_path_from(&globe, start, goal)
+            .expect("Should have found a path");
+        // Five steps, and a few turns to face the right way.
+        assert!(path.len() <= 10);
+        let end = follow(&pathfinder, &globe, start, &path).last().unwrap().0;
+        assert_eq!(
+            PosInOwningRoot::new(end, res),
+            PosInOwningRoot::new(goal, res)
+        );
+    }
+
+    #[test]
+    fn find_path_around_pentagon() {
+        let mut globe = flat_globe_with_surface_loaded();
+        let res = globe.spec().root_resolution;
+        let pathfinder = Pathfinder::new();
+
+        // Corners of root quads are pentagons.
+        let pentagon = PosInOwningRoot::new(Point3::new(Root::new(1), 0, 64, FLAT_SURFACE_Z), res);
+        let start_pos = Point3::new(Root::new(1), 1, 64, FLAT_SURFACE_Z);
+
+        // Find the way that leads straight over the pentagon,
+        // and put the goal a couple of steps past it.
+        let mut start = (start_pos, Dir::default(), TurnDir::Left);
+        while PosInOwningRoot::new(
+            pathfinder
+                .apply(&globe, start, PathAction::StepForward)
+                .unwrap()
+                .0,
+            res,
+        ) != pentagon
+        {
+            start = pathfinder
+                .apply(&globe, start, PathAction::Turn(TurnDir::Left))
+                .unwrap();
+        }
+        let straight_over = [PathAction::StepForward; 3];
+        let goal = follow(&pathfinder, &globe, start, &straight_over)
+            .last()
+            .unwrap()
+            .0;
+
+        // Then build a wall on the pentagon that's too high to climb.
+        for z in FLAT_SURFACE_Z..(FLAT_SURFACE_Z + 2) {
+            let wall = PosInOwningRoot::new(pentagon.pos().with_z(z), res);
+            crate::cell_dweller::place_block(&mut globe, wall, Material::DIRT);
+        }
+        assert!(pathfinder
+            .apply(&globe, start, PathAction::StepForward)
+            .is_none());
+
+        let path = pathfinder
+            .find_path_from(&globe, start, goal)
+            .expect("Should have found a path");
+        let states = follow(&pathfinder, &globe, start, &path);
+        assert!(states
+            .iter()
+            .all(|state| PosInOwningRoot::new(state.0.with_z(FLAT_SURFACE_Z), res) != pentagon));
+        assert_eq!(
+            PosInOwningRoot::new(states.last().unwrap().0, res),
+            PosInOwningRoot::new(goal, res)
+        );
+    }
+
+    #[test]
+    fn goal_in_the_ground_is_never_reached() {
+        let globe = flat_globe_with_surface_loaded();
+        let mut pathfinder = Pathfinder::new();
+        pathfinder.set_max_search_nodes(500);
+
+        let start_pos = Point3::new(Root::new(1), 30, 30, FLAT_SURFACE_Z);
+        let start = (start_pos, Dir::default(), TurnDir::Left);
+        let underground_goal = Point3::new(Root::new(1), 32, 30, FLAT_SURFACE_Z - 1);
+        assert!(pathfinder
+            .find_path_from(&globe, start, underground_goal)
+            .is_none());
+    }
+}
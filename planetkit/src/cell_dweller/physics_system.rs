@@ -3,7 +3,6 @@ use specs;
 use specs::{Read, ReadStorage, WriteStorage};
 
 use super::CellDweller;
-use crate::globe::chunk::Material;
 use crate::globe::Globe;
 use crate::types::*;
 use crate::Spatial;
@@ -25,11 +24,16 @@ impl PhysicsSystem {
     // Note that "gravity" moves you down at a constant speed;
     // i.e. it doesn't accelerate you like in the real world.
     fn maybe_fall(&self, cd: &mut CellDweller, globe: &Globe, dt: TimeDelta) {
-        // Only make you fall if there's air below you.
+        // Only make you fall if there's nothing solid below you.
         if cd.pos.z <= 0 {
             // There's nothing below; someone built a silly globe.
             return;
         }
+        if globe.is_waiting_for_chunks_near(cd.pos) {
+            // Chunks we might fall into are still being built;
+            // wait until they're ready before attempting to fall.
+            return;
+        }
         let under_pos = cd.pos.with_z(cd.pos.z - 1);
         let under_cell = match globe.maybe_non_authoritative_cell(under_pos) {
             Ok(cell) => cell,
@@ -37,7 +41,7 @@ impl PhysicsSystem {
             Err(_) => return,
         };
 
-        if under_cell.material == Material::Dirt {
+        if globe.materials().is_solid(under_cell.material) {
             // Reset time until we can fall to the time
             // between falls; we don't want to instantly
             // fall down every step of size 1.
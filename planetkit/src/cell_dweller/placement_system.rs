@@ -0,0 +1,177 @@
+use piston::input::Input;
+use slog::Logger;
+use specs;
+use specs::{Read, ReadStorage, Write};
+use std::sync::mpsc;
+
+use super::{
+    ActiveCellDweller, CellDweller, CellDwellerMessage, Inventory, ItemKind, SendMessageQueue,
+    TryPlaceBlockMessage,
+};
+use crate::globe::{Globe, Material};
+use crate::input_adapter;
+use crate::input_adapter::SharedKeyBindings;
+use crate::net::{Destination, NetMarker, SendMessage, Transport};
+
+pub struct PlacementInputAdapter {
+    sender: mpsc::Sender<PlacementEvent>,
+    key_bindings: SharedKeyBindings,
+}
+
+impl PlacementInputAdapter {
+    pub fn new(
+        sender: mpsc::Sender<PlacementEvent>,
+        key_bindings: SharedKeyBindings,
+    ) -> PlacementInputAdapter {
+        PlacementInputAdapter {
+            sender,
+            key_bindings,
+        }
+    }
+}
+
+impl input_adapter::InputAdapter for PlacementInputAdapter {
+    fn handle(&self, input_event: &Input) {
+        use crate::input_adapter::Action;
+
+        if let Some((Action::Place, is_down)) = self.key_bindings.action_for_input(input_event) {
+            self.sender.send(PlacementEvent::Place(is_down)).unwrap();
+        }
+    }
+}
+
+pub enum PlacementEvent {
+    Place(bool),
+}
+
+/// Counterpart to `MiningSystem`; lets the active `CellDweller`
+/// put blocks down from its `Inventory`. See `mining::placement_target`
+/// for where they go.
+pub struct PlacementSystem {
+    input_receiver: mpsc::Receiver<PlacementEvent>,
+    log: Logger,
+    // TODO: same problem with one-off events as `MiningSystem`.
+    place: bool,
+    // What to build with. If not chosen, then just use
+    // the first blocks in the inventory.
+    material: Option<Material>,
+}
+
+impl PlacementSystem {
+    pub fn new(
+        input_receiver: mpsc::Receiver<PlacementEvent>,
+        parent_log: &Logger,
+    ) -> PlacementSystem {
+        PlacementSystem {
+            input_receiver,
+            log: parent_log.new(o!()),
+            place: false,
+            material: None,
+        }
+    }
+
+    /// Choose what material blocks will be made of,
+    /// or `None` to use whatever the `CellDweller` has on hand.
+    pub fn set_material(&mut self, material: Option<Material>) {
+        self.material = material;
+    }
+
+    fn consume_input(&mut self) {
+        loop {
+            match self.input_receiver.try_recv() {
+                Ok(PlacementEvent::Place(b)) => self.place = b,
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+impl<'a> specs::System<'a> for PlacementSystem {
+    type SystemData = (
+        ReadStorage<'a, CellDweller>,
+        ReadStorage<'a, Globe>,
+        ReadStorage<'a, Inventory>,
+        Read<'a, ActiveCellDweller>,
+        Write<'a, SendMessageQueue>,
+        ReadStorage<'a, NetMarker>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        self.consume_input();
+
+        let (
+            cell_dwellers,
+            globes,
+            inventories,
+            active_cell_dweller_resource,
+            mut send_message_queue,
+            net_markers,
+        ) = data;
+        let active_cell_dweller_entity = match active_cell_dweller_resource.maybe_entity {
+            Some(entity) => entity,
+            None => return,
+        };
+        let cd = cell_dwellers
+            .get(active_cell_dweller_entity)
+            .expect("Someone deleted the controlled entity's CellDweller");
+
+        // Get the associated globe, complaining loudly if we fail.
+        let globe_entity = match cd.globe_entity {
+            Some(globe_entity) => globe_entity,
+            None => {
+                warn!(
+                    self.log,
+                    "There was no associated globe entity or it wasn't actually a Globe! Can't proceed!"
+                );
+                return;
+            }
+        };
+        let globe = match globes.get(globe_entity) {
+            Some(globe) => globe,
+            None => {
+                warn!(
+                    self.log,
+                    "The globe associated with this CellDweller is not alive! Can't proceed!"
+                );
+                return;
+            }
+        };
+
+        // Find something to build with.
+        let inventory = match inventories.get(active_cell_dweller_entity) {
+            Some(inventory) => inventory,
+            None => return,
+        };
+        let material = match self.material.or_else(|| inventory.first_block_material()) {
+            Some(material) => material,
+            None => return,
+        };
+        let has_material = inventory.count(ItemKind::Block(material)) > 0;
+
+        // If we're trying to place a block, and from our perspective (we might not be the server)
+        // we have one and there's somewhere to put it, then request to the server to place the block.
+        if self.place && has_material && super::mining::placement_target(cd, globe).is_some() {
+            // Post a message to the server (even if that's us)
+            // requesting to place the block.
+            debug!(self.log, "Requesting to place a block");
+
+            if send_message_queue.has_consumer {
+                // If there's a network consumer, then presumably
+                // the entity has been given a global ID.
+                let cd_entity_id = net_markers
+                    .get(active_cell_dweller_entity)
+                    .expect("Shouldn't be trying to tell peers about entities that don't have global IDs!")
+                    .id;
+                send_message_queue.queue.push_back(SendMessage {
+                    // Send the request to the master node, including when that's us.
+                    destination: Destination::Master,
+                    game_message: CellDwellerMessage::TryPlaceBlock(TryPlaceBlockMessage {
+                        cd_entity_id,
+                        material,
+                    }),
+                    transport: Transport::TCP,
+                })
+            }
+        }
+    }
+}
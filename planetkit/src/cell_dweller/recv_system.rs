@@ -3,12 +3,12 @@ use specs;
 use specs::{Read, Write, WriteStorage};
 
 use super::{
-    CellDweller, CellDwellerMessage, RecvMessageQueue, RemoveBlockMessage, SendMessage,
-    SendMessageQueue,
+    CellDweller, CellDwellerMessage, Inventory, PlaceBlockMessage, RecvMessageQueue,
+    RemoveBlockMessage, SendMessage, SendMessageQueue, SetInventoryMessage,
 };
 use crate::globe::Globe;
 use crate::grid::PosInOwningRoot;
-use crate::net::{Destination, EntityIds, NodeResource, Transport};
+use crate::net::{Destination, EntityIds, NodeResource, PeerId, Transport};
 use crate::Spatial;
 
 pub struct RecvSystem {
@@ -28,6 +28,7 @@ impl<'a> specs::System<'a> for RecvSystem {
         WriteStorage<'a, Globe>,
         WriteStorage<'a, CellDweller>,
         WriteStorage<'a, Spatial>,
+        WriteStorage<'a, Inventory>,
         Write<'a, RecvMessageQueue>,
         Write<'a, SendMessageQueue>,
         Read<'a, EntityIds>,
@@ -39,6 +40,7 @@ impl<'a> specs::System<'a> for RecvSystem {
             mut globes,
             mut cell_dwellers,
             mut spatials,
+            mut inventories,
             mut recv_message_queue,
             mut send_message_queue,
             entity_ids,
@@ -114,7 +116,7 @@ impl<'a> specs::System<'a> for RecvSystem {
                         .mapping
                         .get(&try_pick_up_block_message.cd_entity_id)
                     {
-                        Some(ent) => ent,
+                        Some(ent) => *ent,
                         // We probably just don't know about it yet.
                         None => {
                             // TODO: demote to trace
@@ -123,7 +125,7 @@ impl<'a> specs::System<'a> for RecvSystem {
                         }
                     };
                     let cd = cell_dwellers
-                        .get_mut(*cell_dweller_entity)
+                        .get_mut(cell_dweller_entity)
                         .expect("Missing CellDweller");
 
                     // Get the associated globe, complaining loudly if we fail.
@@ -152,7 +154,10 @@ impl<'a> specs::System<'a> for RecvSystem {
                     // TODO: validate that peer is allowed to remove the block.
                     // TODO: handle their source position and target pickup spot.
                     // Initially just trust the client is honest.
-                    let maybe_cell_info = super::mining::pick_up_if_possible(cd, globe);
+                    let maybe_inventory = inventories.get_mut(cell_dweller_entity);
+                    let has_inventory = maybe_inventory.is_some();
+                    let maybe_cell_info =
+                        super::mining::pick_up_if_possible(cd, globe, maybe_inventory);
                     if let Some((new_pos_in_owning_root, cell)) = maybe_cell_info {
                         debug!(self.log, "Removed a block because a peer asked"; "pos" => format!("{:?}", new_pos_in_owning_root), "cell" => format!("{:?}", cell));
 
@@ -177,6 +182,16 @@ impl<'a> specs::System<'a> for RecvSystem {
                             game_message: CellDwellerMessage::RemoveBlock(remove_block_message),
                             transport: Transport::TCP,
                         });
+
+                        // And let whoever asked know what they're carrying now.
+                        if has_inventory {
+                            send_message_queue.queue.push_back(set_inventory_message(
+                                &inventories,
+                                cell_dweller_entity,
+                                try_pick_up_block_message.cd_entity_id,
+                                message.source,
+                            ));
+                        }
                     }
                 }
                 CellDwellerMessage::RemoveBlock(remove_block_message) => {
@@ -199,7 +214,245 @@ impl<'a> specs::System<'a> for RecvSystem {
 
                     debug!(self.log, "Removed a block master told me to"; "pos" => format!("{:?}", remove_block_message.pos), "cell" => format!("{:?}", removed_cell));
                 }
+                CellDwellerMessage::TryPlaceBlock(try_place_block_message) => {
+                    // TODO: validate that we are the server.
+
+                    // Look up the entity from its global ID.
+                    let cell_dweller_entity = match entity_ids
+                        .mapping
+                        .get(&try_place_block_message.cd_entity_id)
+                    {
+                        Some(ent) => *ent,
+                        // We probably just don't know about it yet.
+                        None => {
+                            // TODO: demote to trace
+                            info!(self.log, "Heard about cell dweller we don't know about yet"; "entity_id" => try_place_block_message.cd_entity_id);
+                            continue;
+                        }
+                    };
+                    let cd = cell_dwellers
+                        .get(cell_dweller_entity)
+                        .expect("Missing CellDweller");
+
+                    // Get the associated globe, complaining loudly if we fail.
+                    let globe_entity = match cd.globe_entity {
+                        Some(globe_entity) => globe_entity,
+                        None => {
+                            warn!(
+                                self.log,
+                                "There was no associated globe entity or it wasn't actually a Globe! Can't proceed!"
+                            );
+                            continue;
+                        }
+                    };
+                    let globe = match globes.get_mut(globe_entity) {
+                        Some(globe) => globe,
+                        None => {
+                            warn!(
+                                self.log,
+                                "The globe associated with this CellDweller is not alive! Can't proceed!"
+                            );
+                            continue;
+                        }
+                    };
+
+                    // Don't bury anyone standing on the same globe.
+                    let root_resolution = globe.spec().root_resolution;
+                    let occupied: Vec<PosInOwningRoot> = {
+                        use specs::Join;
+                        (&cell_dwellers)
+                            .join()
+                            .filter(|other_cd| other_cd.globe_entity == Some(globe_entity))
+                            .map(|other_cd| PosInOwningRoot::new(other_cd.pos, root_resolution))
+                            .collect()
+                    };
+
+                    // They need something to build with.
+                    let inventory = match inventories.get_mut(cell_dweller_entity) {
+                        Some(inventory) => inventory,
+                        None => {
+                            debug!(self.log, "Cell dweller without an inventory tried to place a block"; "entity_id" => try_place_block_message.cd_entity_id);
+                            continue;
+                        }
+                    };
+
+                    // TODO: validate that peer is allowed to place the block.
+                    // Initially just trust the client is honest.
+                    let maybe_pos = super::mining::place_if_possible(
+                        &self.log,
+                        cd,
+                        globe,
+                        try_place_block_message.material,
+                        &occupied,
+                        inventory,
+                    );
+                    if let Some(pos_in_owning_root) = maybe_pos {
+                        debug!(self.log, "Placed a block because a peer asked"; "pos" => format!("{:?}", pos_in_owning_root), "material" => format!("{:?}", try_place_block_message.material));
+
+                        // Tell everyone else what happened.
+                        // TODO: same shortcomings as for removing blocks; see above.
+                        let place_block_message = PlaceBlockMessage {
+                            // TODO: identify the globe; see above.
+                            pos: pos_in_owning_root.into(),
+                            material: try_place_block_message.material,
+                        };
+                        send_message_queue.queue.push_back(SendMessage {
+                            destination: Destination::EveryoneElse,
+                            game_message: CellDwellerMessage::PlaceBlock(place_block_message),
+                            transport: Transport::TCP,
+                        });
+                        send_message_queue.queue.push_back(set_inventory_message(
+                            &inventories,
+                            cell_dweller_entity,
+                            try_place_block_message.cd_entity_id,
+                            message.source,
+                        ));
+                    }
+                }
+                CellDwellerMessage::PlaceBlock(place_block_message) => {
+                    // For now just find the first globe, and assume that's
+                    // the one we're supposed to be working with.
+                    use specs::Join;
+                    let globe = (&mut globes)
+                        .join()
+                        .next()
+                        .expect("Should've been at least one globe.");
+
+                    // TODO: validate that position makes sense. Don't want the client
+                    // to be able to punk us.
+
+                    if globe
+                        .materials()
+                        .try_get(place_block_message.material)
+                        .is_none()
+                    {
+                        warn!(self.log, "Ignoring placed block of unknown material"; "material" => format!("{:?}", place_block_message.material));
+                        continue;
+                    }
+
+                    let pos_in_owning_root =
+                        PosInOwningRoot::new(place_block_message.pos, globe.spec().root_resolution);
+                    // We'll get the right cell from the master if we ever load it.
+                    // TODO: make sure that's actually true once chunks are sent over the network.
+                    let chunk_origin = globe.origin_of_chunk_owning(pos_in_owning_root);
+                    if globe.chunk_at(chunk_origin).is_none() {
+                        trace!(self.log, "Ignoring placed block in a chunk we don't have loaded"; "pos" => format!("{:?}", place_block_message.pos));
+                        continue;
+                    }
+                    let replaced_cell = super::mining::place_block(
+                        globe,
+                        pos_in_owning_root,
+                        place_block_message.material,
+                    );
+
+                    debug!(self.log, "Placed a block master told me to"; "pos" => format!("{:?}", place_block_message.pos), "cell" => format!("{:?}", replaced_cell));
+                }
+                CellDwellerMessage::SetInventory(set_inventory_message) => {
+                    if !node_resource.is_from_master(message.source) {
+                        warn!(self.log, "Ignoring inventory from a peer that isn't the master"; "peer_id" => message.source.0);
+                        continue;
+                    }
+
+                    // Look up the entity from its global ID.
+                    let cell_dweller_entity = match entity_ids
+                        .mapping
+                        .get(&set_inventory_message.cd_entity_id)
+                    {
+                        Some(ent) => *ent,
+                        // We probably just don't know about it yet.
+                        None => {
+                            // TODO: demote to trace
+                            info!(self.log, "Heard about cell dweller we don't know about yet"; "entity_id" => set_inventory_message.cd_entity_id);
+                            continue;
+                        }
+                    };
+
+                    debug!(self.log, "Updating inventory because master told me to"; "inventory" => format!("{:?}", set_inventory_message.inventory));
+
+                    if let Err(err) =
+                        inventories.insert(cell_dweller_entity, set_inventory_message.inventory)
+                    {
+                        warn!(self.log, "Couldn't update inventory of dead cell dweller"; "entity_id" => set_inventory_message.cd_entity_id, "err" => format!("{:?}", err));
+                    }
+                }
             }
         }
     }
 }
+
+// Tell the peer controlling a cell dweller what's in its inventory.
+fn set_inventory_message(
+    inventories: &WriteStorage<Inventory>,
+    cd_entity: specs::Entity,
+    cd_entity_id: u64,
+    owner: PeerId,
+) -> SendMessage<CellDwellerMessage> {
+    let inventory = inventories
+        .get(cd_entity)
+        .expect("Cell dweller should have had an inventory")
+        .clone();
+    SendMessage {
+        destination: Destination::One(owner),
+        game_message: CellDwellerMessage::SetInventory(SetInventoryMessage {
+            cd_entity_id,
+            inventory,
+        }),
+        transport: Transport::TCP,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, RunNow};
+
+    use super::*;
+    use crate::net::RecvMessage;
+
+    #[test]
+    fn only_the_master_sets_inventories() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let mut recv_system = RecvSystem::new(&log);
+        recv_system.setup(&mut world.res);
+        world.write_resource::<NodeResource>().is_master = true;
+
+        let cd_entity = world.create_entity().with(Inventory::new(4)).build();
+        world
+            .write_resource::<EntityIds>()
+            .mapping
+            .insert(7, cd_entity);
+
+        let mut set_inventory = |world: &mut specs::World, source, capacity| {
+            world
+                .write_resource::<RecvMessageQueue>()
+                .queue
+                .push_back(RecvMessage {
+                    source: PeerId(source),
+                    game_message: CellDwellerMessage::SetInventory(SetInventoryMessage {
+                        cd_entity_id: 7,
+                        inventory: Inventory::new(capacity),
+                    }),
+                });
+            recv_system.run_now(&world.res);
+        };
+        let capacity = |world: &specs::World| {
+            world
+                .read_storage::<Inventory>()
+                .get(cd_entity)
+                .unwrap()
+                .capacity()
+        };
+
+        // Clients don't get to fill their own pockets.
+        set_inventory(&mut world, 1, 9);
+        assert_eq!(capacity(&world), 4);
+
+        set_inventory(&mut world, 0, 9);
+        assert_eq!(capacity(&world), 9);
+
+        // A late update for a cell dweller that has since died is dropped.
+        world.delete_entity(cd_entity).unwrap();
+        world.maintain();
+        set_inventory(&mut world, 0, 3);
+    }
+}
@@ -1,15 +1,11 @@
+use crate::globe::chunk_cells::ChunkCells;
 use crate::globe::chunk_pair::PointPair;
 use crate::globe::ChunkOrigin;
 use crate::globe::{chunks_containing_point, origin_of_chunk_owning};
 use crate::grid::{GridCoord, Point3, PosInOwningRoot};
 use specs;
 
-#[derive(PartialEq, Eq, Copy, Clone, Debug)]
-pub enum Material {
-    Air,
-    Dirt,
-    Water,
-}
+pub use crate::globe::material::Material;
 
 // TODO: we should actually have multiple different
 // kinds of Voxmaps. "Chunk" should refer to the coarse
@@ -17,7 +13,7 @@ pub enum Material {
 // sized partition of the world that would be loaded and
 // unloaded into the world as a unit.
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Cell {
     pub material: Material,
     pub shade: f32,
@@ -32,7 +28,7 @@ pub struct Chunk {
     pub origin: ChunkOrigin,
     pub chunk_resolution: [GridCoord; 3],
     // Sorted by (z, y, x).
-    pub cells: Vec<Cell>,
+    pub cells: ChunkCells,
     pub view_entity: Option<specs::Entity>,
     // Incremented whenever authoritative data in this chunk that is shared with other chunks is updated.
     // This way even if a neighboring chunk was not loaded when we update this chunk,
@@ -53,6 +49,11 @@ pub struct Chunk {
     // The chunk shouldn't be keeping track of anything like this;
     // who sets it? It should probably be the globe tracking it.
     pub is_view_dirty: bool,
+    // Set when this chunk contains data that differs from what
+    // the globe's generator would produce, so it needs to be
+    // saved when it is unloaded. Chunks loaded from disk start
+    // out with this set, because they were saved for a reason.
+    pub is_modified: bool,
     // Chunks that are directly accessible from the given chunk via a single,
     // step between cells, including this chunk itself.
     //
@@ -75,7 +76,7 @@ pub struct Chunk {
 impl Chunk {
     pub fn new(
         origin: ChunkOrigin,
-        cells: Vec<Cell>,
+        cells: ChunkCells,
         root_resolution: [GridCoord; 2],
         chunk_resolution: [GridCoord; 3],
     ) -> Chunk {
@@ -88,6 +89,7 @@ impl Chunk {
             upstream_neighbors: Vec::new(),
             downstream_neighbors: Vec::new(),
             is_view_dirty: true,
+            is_modified: false,
             accessible_chunks: Self::list_accessible_chunks(
                 origin,
                 root_resolution,
@@ -242,6 +244,12 @@ impl Chunk {
         self.is_view_dirty = false;
     }
 
+    /// Indicate that this chunk differs from what the globe's generator
+    /// would produce, and so should be saved before being unloaded.
+    pub fn mark_as_modified(&mut self) {
+        self.is_modified = true;
+    }
+
     fn list_accessible_chunks(
         origin: ChunkOrigin,
         root_resolution: [GridCoord; 2],
@@ -298,13 +306,22 @@ impl<'a> Chunk {
     // Panics if given coordinates of a cell we don't have data for.
     pub fn cell(&'a self, pos: Point3) -> &'a Cell {
         let cell_i = self.cell_index(pos);
-        &self.cells[cell_i]
+        self.cells.get(cell_i)
     }
 
     // Panics if given coordinates of a cell we don't have data for.
+    //
+    // NOTE: this forces the chunk's cells into their uncompressed form;
+    // prefer `set_cell` wherever possible.
     pub fn cell_mut(&'a mut self, pos: Point3) -> &'a mut Cell {
         let cell_i = self.cell_index(pos);
-        &mut self.cells[cell_i]
+        self.cells.get_mut(cell_i)
+    }
+
+    // Panics if given coordinates of a cell we don't have data for.
+    pub fn set_cell(&mut self, pos: Point3, cell: Cell) {
+        let cell_i = self.cell_index(pos);
+        self.cells.set(cell_i, cell);
     }
 }
 
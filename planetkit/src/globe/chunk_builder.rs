@@ -0,0 +1,156 @@
+//! Generating chunks on background threads, so that building
+//! lots of them at once doesn't stall the game loop.
+
+use std::io;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::chunk::{Cell, Chunk};
+use super::chunk_cells::ChunkCells;
+use super::chunk_storage::ChunkStorage;
+use super::gen::Gen;
+use super::spec::Spec;
+use super::ChunkOrigin;
+use crate::grid::Point3;
+
+/// A chunk that is ready to be added to a `Globe`.
+pub struct BuiltChunk {
+    pub chunk: Chunk,
+    // Last known edge versions of each upstream neighbor, if the chunk
+    // was loaded from storage; see `StoredChunk`.
+    pub upstream_edge_versions: Vec<(Point3, u64)>,
+    // Set if the chunk had been saved, but couldn't be read back,
+    // so it was built fresh instead.
+    pub load_error: Option<io::Error>,
+}
+
+/// Load a chunk from storage if it has ever been saved there,
+/// or otherwise generate it from scratch.
+pub fn load_or_build_chunk(
+    spec: &Spec,
+    gen: &dyn Gen,
+    storage: Option<&ChunkStorage>,
+    origin: ChunkOrigin,
+) -> BuiltChunk {
+    let (maybe_stored_chunk, load_error) = match storage.map(|storage| storage.load(origin)) {
+        Some(Ok(maybe_stored_chunk)) => (maybe_stored_chunk, None),
+        Some(Err(err)) => (None, Some(err)),
+        None => (None, None),
+    };
+    match maybe_stored_chunk {
+        Some(stored_chunk) => {
+            let mut chunk = Chunk::new(
+                origin,
+                stored_chunk.cells,
+                spec.root_resolution,
+                spec.chunk_resolution,
+            );
+            chunk.owned_edge_version = stored_chunk.owned_edge_version;
+            chunk.mark_as_modified();
+            BuiltChunk {
+                chunk,
+                upstream_edge_versions: stored_chunk.upstream_edge_versions,
+                load_error,
+            }
+        }
+        None => BuiltChunk {
+            chunk: build_chunk(spec, gen, origin),
+            upstream_edge_versions: Vec::new(),
+            load_error,
+        },
+    }
+}
+
+/// Generate a new chunk from scratch, including working out
+/// its neighbors. This is the expensive bit of adding a chunk
+/// to a `Globe`; nothing here depends on any other chunks.
+pub fn build_chunk(spec: &Spec, gen: &dyn Gen, origin: ChunkOrigin) -> Chunk {
+    let chunk_res = spec.chunk_resolution;
+    let mut cells: Vec<Cell> =
+        Vec::with_capacity(chunk_res[0] as usize * chunk_res[1] as usize * chunk_res[2] as usize);
+    gen.populate_cells(origin, &mut cells);
+    Chunk::new(
+        origin,
+        ChunkCells::from(cells),
+        spec.root_resolution,
+        chunk_res,
+    )
+}
+
+/// A pool of worker threads that load or build chunks for a single `Globe`.
+///
+/// Chunks are built in the order they are requested, and can be
+/// collected once they are ready. The worker threads shut down when
+/// the `ChunkBuilder` is dropped.
+pub struct ChunkBuilder {
+    // Channels aren't `Sync`, but `Globe`s need to be.
+    job_sender: Mutex<mpsc::Sender<Job>>,
+    built_receiver: Mutex<mpsc::Receiver<BuiltChunk>>,
+}
+
+struct Job {
+    origin: ChunkOrigin,
+    storage: Option<Arc<ChunkStorage>>,
+}
+
+impl ChunkBuilder {
+    pub fn new(spec: Spec, gen: Arc<dyn Gen>, num_threads: usize) -> ChunkBuilder {
+        assert!(num_threads > 0, "Need at least one thread to build chunks");
+
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (built_sender, built_receiver) = mpsc::channel::<BuiltChunk>();
+        for i in 0..num_threads {
+            let job_receiver = job_receiver.clone();
+            let built_sender = built_sender.clone();
+            let gen = gen.clone();
+            thread::Builder::new()
+                .name(format!("chunk_builder_{}", i))
+                .spawn(move || loop {
+                    // Only hold the lock for as long as it takes to get the next job.
+                    let next_job = job_receiver
+                        .lock()
+                        .expect("Another chunk builder thread panicked")
+                        .recv();
+                    let job = match next_job {
+                        Ok(job) => job,
+                        // The `ChunkBuilder` was dropped.
+                        Err(_) => return,
+                    };
+                    let built_chunk =
+                        load_or_build_chunk(&spec, &*gen, job.storage.as_deref(), job.origin);
+                    if built_sender.send(built_chunk).is_err() {
+                        // The `ChunkBuilder` was dropped.
+                        return;
+                    }
+                })
+                .expect("Failed to spawn chunk builder thread");
+        }
+
+        ChunkBuilder {
+            job_sender: Mutex::new(job_sender),
+            built_receiver: Mutex::new(built_receiver),
+        }
+    }
+
+    /// Queue a chunk to be loaded from `storage`, or built if it was never
+    /// saved there. It's up to the caller to avoid requesting the same chunk
+    /// more than once.
+    pub fn request(&self, origin: ChunkOrigin, storage: Option<Arc<ChunkStorage>>) {
+        self.job_sender
+            .lock()
+            .expect("Chunk builder lock was poisoned")
+            .send(Job { origin, storage })
+            .expect("All chunk builder threads have died");
+    }
+
+    /// Take all the chunks that have been built since this was last called.
+    pub fn built_chunks(&self) -> Vec<BuiltChunk> {
+        self.built_receiver
+            .lock()
+            .expect("Chunk builder lock was poisoned")
+            .try_iter()
+            .collect()
+    }
+}
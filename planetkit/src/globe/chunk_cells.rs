@@ -0,0 +1,312 @@
+//! Compact storage for the cells of a single chunk.
+
+use super::chunk::Cell;
+
+// Palette indices are stored as bytes, so we can't
+// have more distinct cells than this in a palette.
+const MAX_PALETTE_LEN: usize = 256;
+
+/// The cells of a chunk, sorted by (z, y, x).
+///
+/// Most chunks are made up of only a handful of distinct cells,
+/// e.g., all air, or all dirt, so rather than storing every cell
+/// in full, this stores a palette of the distinct cells in the chunk,
+/// and a small index into that palette for each cell. Chunks made up
+/// entirely of a single kind of cell store only that one cell.
+///
+/// If a chunk has too many distinct cells to fit in a palette,
+/// then we give up and store them all in full.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChunkCells {
+    storage: Storage,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Storage {
+    Uniform {
+        cell: Cell,
+        len: usize,
+    },
+    Palette {
+        palette: Vec<Cell>,
+        indices: Vec<u8>,
+    },
+    Flat(Vec<Cell>),
+}
+
+impl ChunkCells {
+    /// Number of cells, including any that are
+    /// duplicated on the edges of neighboring chunks.
+    pub fn len(&self) -> usize {
+        match self.storage {
+            Storage::Uniform { len, .. } => len,
+            Storage::Palette { ref indices, .. } => indices.len(),
+            Storage::Flat(ref cells) => cells.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if every cell is the same.
+    pub fn is_uniform(&self) -> bool {
+        matches!(self.storage, Storage::Uniform { .. })
+    }
+
+    // Panics if `i` is out of bounds.
+    pub fn get(&self, i: usize) -> &Cell {
+        match self.storage {
+            Storage::Uniform { ref cell, len } => {
+                assert!(i < len, "Cell index out of bounds");
+                cell
+            }
+            Storage::Palette {
+                ref palette,
+                ref indices,
+            } => &palette[indices[i] as usize],
+            Storage::Flat(ref cells) => &cells[i],
+        }
+    }
+
+    /// Get mutable access to a single cell.
+    ///
+    /// There's no way to do this without storing every cell separately,
+    /// so this expands the whole chunk's cells to their uncompressed form.
+    /// Prefer `set` wherever possible, and call `compact` after making
+    /// changes this way.
+    //
+    // Panics if `i` is out of bounds.
+    pub fn get_mut(&mut self, i: usize) -> &mut Cell {
+        self.expand();
+        match self.storage {
+            Storage::Flat(ref mut cells) => &mut cells[i],
+            _ => unreachable!("We just expanded the cells"),
+        }
+    }
+
+    /// Replace the cell at index `i`, keeping storage compressed
+    /// for as long as there are few enough distinct cells.
+    //
+    // Panics if `i` is out of bounds.
+    pub fn set(&mut self, i: usize, new_cell: Cell) {
+        if let Storage::Uniform { cell, len } = self.storage {
+            assert!(i < len, "Cell index out of bounds");
+            if cell == new_cell {
+                return;
+            }
+            self.storage = Storage::Palette {
+                palette: vec![cell],
+                indices: vec![0; len],
+            };
+        }
+
+        let needs_expanding = match self.storage {
+            Storage::Palette {
+                ref mut palette,
+                ref mut indices,
+            } => {
+                let existing_index = palette.iter().position(|cell| *cell == new_cell);
+                if existing_index.is_none() && palette.len() == MAX_PALETTE_LEN {
+                    // Cells that have been replaced may have left
+                    // unused entries behind; make room by dropping them.
+                    remove_unused_palette_entries(palette, indices, i);
+                }
+                match existing_index {
+                    Some(palette_i) => {
+                        indices[i] = palette_i as u8;
+                        false
+                    }
+                    None if palette.len() < MAX_PALETTE_LEN => {
+                        indices[i] = palette.len() as u8;
+                        palette.push(new_cell);
+                        false
+                    }
+                    None => true,
+                }
+            }
+            Storage::Flat(ref mut cells) => {
+                cells[i] = new_cell;
+                false
+            }
+            Storage::Uniform { .. } => unreachable!("Uniform storage was converted above"),
+        };
+
+        if needs_expanding {
+            *self.get_mut(i) = new_cell;
+        }
+    }
+
+    /// Re-compress the cells as much as possible.
+    ///
+    /// This is relatively expensive; avoid doing it
+    /// after every change to a single cell.
+    pub fn compact(&mut self) {
+        let cells = self.to_vec();
+        *self = ChunkCells::from(cells);
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a Cell> + 'a {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    pub fn to_vec(&self) -> Vec<Cell> {
+        self.iter().cloned().collect()
+    }
+
+    fn expand(&mut self) {
+        if let Storage::Flat(_) = self.storage {
+            return;
+        }
+        self.storage = Storage::Flat(self.to_vec());
+    }
+}
+
+// Remove every palette entry that isn't used by any cell other than the one
+// at `ignored_i` (which is about to be replaced), and renumber the indices
+// to match, keeping the remaining entries in the same order.
+fn remove_unused_palette_entries(palette: &mut Vec<Cell>, indices: &mut [u8], ignored_i: usize) {
+    let mut is_used = [false; MAX_PALETTE_LEN];
+    for (i, &palette_i) in indices.iter().enumerate() {
+        if i != ignored_i {
+            is_used[palette_i as usize] = true;
+        }
+    }
+    let mut new_palette_indices = [0u8; MAX_PALETTE_LEN];
+    let mut new_len = 0;
+    for old_palette_i in 0..palette.len() {
+        if is_used[old_palette_i] {
+            palette[new_len] = palette[old_palette_i];
+            new_palette_indices[old_palette_i] = new_len as u8;
+            new_len += 1;
+        }
+    }
+    palette.truncate(new_len);
+    for (i, palette_i) in indices.iter_mut().enumerate() {
+        if i != ignored_i {
+            *palette_i = new_palette_indices[*palette_i as usize];
+        }
+    }
+}
+
+impl From<Vec<Cell>> for ChunkCells {
+    fn from(cells: Vec<Cell>) -> ChunkCells {
+        let mut palette: Vec<Cell> = Vec::new();
+        let mut indices: Vec<u8> = Vec::with_capacity(cells.len());
+        for cell in &cells {
+            let palette_i = match palette.iter().position(|existing| existing == cell) {
+                Some(palette_i) => palette_i,
+                None if palette.len() < MAX_PALETTE_LEN => {
+                    palette.push(*cell);
+                    palette.len() - 1
+                }
+                None => {
+                    // Too many distinct cells; not worth compressing.
+                    return ChunkCells {
+                        storage: Storage::Flat(cells),
+                    };
+                }
+            };
+            indices.push(palette_i as u8);
+        }
+
+        let storage = if palette.len() == 1 {
+            Storage::Uniform {
+                cell: palette[0],
+                len: indices.len(),
+            }
+        } else {
+            Storage::Palette { palette, indices }
+        };
+        ChunkCells { storage }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::chunk::Material;
+    use super::*;
+
+    fn cell(material: Material, shade: f32) -> Cell {
+        Cell { material, shade }
+    }
+
+    #[test]
+    fn uniform_cells_collapse_to_single_value() {
+        let cells = ChunkCells::from(vec![cell(Material::AIR, 1.0); 100]);
+        assert!(cells.is_uniform());
+        assert_eq!(cells.len(), 100);
+        assert_eq!(cells.get(99).material, Material::AIR);
+    }
+
+    #[test]
+    fn set_keeps_cells_compressed() {
+        let mut cells = ChunkCells::from(vec![cell(Material::DIRT, 1.0); 100]);
+        cells.set(42, cell(Material::AIR, 1.0));
+        match cells.storage {
+            Storage::Palette { ref palette, .. } => assert_eq!(palette.len(), 2),
+            _ => panic!("Expected palette storage"),
+        }
+        assert_eq!(cells.get(41).material, Material::DIRT);
+        assert_eq!(cells.get(42).material, Material::AIR);
+        assert_eq!(cells.get(43).material, Material::DIRT);
+
+        // Setting it back doesn't re-collapse to uniform storage by itself...
+        cells.set(42, cell(Material::DIRT, 1.0));
+        assert!(!cells.is_uniform());
+        // ...but compacting does.
+        cells.compact();
+        assert!(cells.is_uniform());
+    }
+
+    #[test]
+    fn too_many_distinct_cells_falls_back_to_flat() {
+        let original: Vec<Cell> = (0..1000)
+            .map(|i| cell(Material::DIRT, i as f32 / 1000.0))
+            .collect();
+        let cells = ChunkCells::from(original.clone());
+        match cells.storage {
+            Storage::Flat(_) => (),
+            _ => panic!("Expected flat storage"),
+        }
+        assert_eq!(cells.to_vec(), original);
+
+        // Overflowing a palette one cell at a time should have the same result.
+        let mut cells = ChunkCells::from(vec![cell(Material::AIR, 1.0); 1000]);
+        for (i, c) in original.iter().enumerate() {
+            cells.set(i, *c);
+        }
+        assert_eq!(cells.to_vec(), original);
+    }
+
+    #[test]
+    fn replaced_cells_dont_fill_up_palette() {
+        // Keep changing the same few cells to something new, so that
+        // far more distinct cells come and go than fit in a palette,
+        // but there are never more than a handful at once.
+        let mut cells = ChunkCells::from(vec![cell(Material::DIRT, 1.0); 100]);
+        for n in 0..1000 {
+            cells.set(n % 3, cell(Material::DIRT, n as f32 / 1000.0));
+        }
+        match cells.storage {
+            Storage::Palette { .. } => (),
+            _ => panic!("Expected palette storage"),
+        }
+        assert_eq!(cells.get(0).shade, 999.0 / 1000.0);
+        assert_eq!(cells.get(2).shade, 998.0 / 1000.0);
+        assert_eq!(cells.get(3).shade, 1.0);
+    }
+
+    #[test]
+    fn get_mut_expands_cells() {
+        let mut cells = ChunkCells::from(vec![cell(Material::DIRT, 1.0); 10]);
+        cells.get_mut(3).material = Material::WATER;
+        assert_eq!(cells.get(3).material, Material::WATER);
+        assert_eq!(cells.get(4).material, Material::DIRT);
+        cells.compact();
+        match cells.storage {
+            Storage::Palette { ref palette, .. } => assert_eq!(palette.len(), 2),
+            _ => panic!("Expected palette storage"),
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Saving chunks to disk, and loading them back again.
+//!
+//! Chunks are grouped into "region files", each of which holds
+//! every saved chunk within a block of `REGION_SIDE_IN_CHUNKS` chunks
+//! along each axis of a single root. This keeps the number of files
+//! manageable for large globes without needing to rewrite the whole
+//! world whenever a single chunk changes.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use serde_json;
+
+use super::chunk::Chunk;
+use super::chunk_cells::ChunkCells;
+use super::ChunkOrigin;
+use crate::grid::{GridCoord, Point3};
+
+/// Number of chunks along each axis of a region.
+const REGION_SIDE_IN_CHUNKS: GridCoord = 8;
+
+/// Everything we need to reconstruct a `Chunk` that differs
+/// from what we could derive from its origin and the globe's `Spec`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredChunk {
+    pub origin: Point3,
+    pub owned_edge_version: u64,
+    // Last known edge versions of each upstream neighbor,
+    // as of when the cells below were copied from them.
+    pub upstream_edge_versions: Vec<(Point3, u64)>,
+    // Sorted by (z, y, x), exactly as in `Chunk`.
+    pub cells: ChunkCells,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Region {
+    chunks: Vec<StoredChunk>,
+}
+
+/// Region-file storage for the chunks of a single `Globe`.
+///
+/// Each region file is only read once; after that, its contents are
+/// kept in memory and updated as chunks are saved. Region files only
+/// hold chunks that have been modified, so this is usually small.
+///
+/// Can be shared between threads, e.g., to load chunks in the background.
+pub struct ChunkStorage {
+    dir: PathBuf,
+    chunk_resolution: [GridCoord; 3],
+    // Regions we've already read, by path.
+    regions: Mutex<HashMap<PathBuf, Region>>,
+}
+
+impl ChunkStorage {
+    /// Store chunks in region files inside `dir`,
+    /// creating the directory if it doesn't already exist.
+    pub fn new<P: AsRef<Path>>(
+        dir: P,
+        chunk_resolution: [GridCoord; 3],
+    ) -> io::Result<ChunkStorage> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(ChunkStorage {
+            dir,
+            chunk_resolution,
+            regions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn lock_regions(&self) -> MutexGuard<'_, HashMap<PathBuf, Region>> {
+        self.regions
+            .lock()
+            .expect("Something panicked while holding the region cache")
+    }
+
+    // Get the region at `path` from the cache, reading it first if necessary.
+    fn cached_region<'a>(
+        &self,
+        regions: &'a mut HashMap<PathBuf, Region>,
+        path: &Path,
+    ) -> io::Result<&'a mut Region> {
+        if !regions.contains_key(path) {
+            let region = self.read_region(path)?;
+            regions.insert(path.to_path_buf(), region);
+        }
+        Ok(regions
+            .get_mut(path)
+            .expect("Just made sure region was cached"))
+    }
+
+    fn region_path(&self, pos: &Point3) -> PathBuf {
+        let region_x = pos.x / (self.chunk_resolution[0] * REGION_SIDE_IN_CHUNKS);
+        let region_y = pos.y / (self.chunk_resolution[1] * REGION_SIDE_IN_CHUNKS);
+        let region_z = pos.z / (self.chunk_resolution[2] * REGION_SIDE_IN_CHUNKS);
+        self.dir.join(format!(
+            "r.{}.{}.{}.{}.json",
+            pos.root.index, region_x, region_y, region_z
+        ))
+    }
+
+    fn read_region(&self, path: &Path) -> io::Result<Region> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Region::default()),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_reader(BufReader::new(file)).or_else(|err| {
+            // Move it out of the way so we don't trip over it every time we
+            // look in this region, but keep it around in case anyone wants
+            // to try to recover it.
+            let corrupt_path = path.with_extension("json.corrupt");
+            fs::rename(path, &corrupt_path)?;
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Couldn't read region file ({}); moved it to {}",
+                    err,
+                    corrupt_path.display()
+                ),
+            ))
+        })
+    }
+
+    fn write_region(&self, path: &Path, region: &Region) -> io::Result<()> {
+        // Write to a temporary file first and then move it into place,
+        // so that we never leave a half-written region file behind.
+        let tmp_path = path.with_extension("json.tmp");
+        {
+            let file = File::create(&tmp_path)?;
+            serde_json::to_writer(BufWriter::new(file), region)?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Load the chunk at `origin`, if it has ever been saved.
+    ///
+    /// If the region file it would be in can't be parsed, then it is
+    /// renamed to end in `.corrupt` and this returns an error;
+    /// after that, it is as if no chunks in that region had ever been saved.
+    pub fn load(&self, origin: ChunkOrigin) -> io::Result<Option<StoredChunk>> {
+        let mut regions = self.lock_regions();
+        let region = self.cached_region(&mut regions, &self.region_path(origin.pos()))?;
+        Ok(region
+            .chunks
+            .iter()
+            .find(|stored_chunk| stored_chunk.origin == *origin.pos())
+            .cloned())
+    }
+
+    /// Save the chunk, replacing any previously saved copy.
+    ///
+    /// This rewrites the whole region file the chunk belongs in;
+    /// use `save_all` to save many chunks at once.
+    pub fn save(&self, stored_chunk: StoredChunk) -> io::Result<()> {
+        self.save_all(vec![stored_chunk])
+    }
+
+    /// Save all the given chunks, replacing any previously saved copies,
+    /// and writing each affected region file only once.
+    ///
+    /// Returns the first error encountered, if any,
+    /// but still attempts to save all other regions.
+    pub fn save_all(&self, stored_chunks: Vec<StoredChunk>) -> io::Result<()> {
+        let mut chunks_by_region: HashMap<PathBuf, Vec<StoredChunk>> = HashMap::new();
+        for stored_chunk in stored_chunks {
+            chunks_by_region
+                .entry(self.region_path(&stored_chunk.origin))
+                .or_default()
+                .push(stored_chunk);
+        }
+
+        let mut regions = self.lock_regions();
+        let mut result = Ok(());
+        for (path, stored_chunks) in chunks_by_region {
+            let region_result = self.cached_region(&mut regions, &path).and_then(|region| {
+                for stored_chunk in stored_chunks {
+                    region
+                        .chunks
+                        .retain(|existing| existing.origin != stored_chunk.origin);
+                    region.chunks.push(stored_chunk);
+                }
+                self.write_region(&path, region)
+            });
+            if result.is_ok() {
+                result = region_result;
+            }
+        }
+        result
+    }
+}
+
+impl StoredChunk {
+    /// Capture everything about `chunk` that needs to be saved,
+    /// given the edge versions it last knew about for each upstream neighbor.
+    pub fn from_chunk(chunk: &Chunk, upstream_edge_versions: Vec<(Point3, u64)>) -> StoredChunk {
+        // Cells may have been expanded while being edited.
+        let mut cells = chunk.cells.clone();
+        cells.compact();
+        StoredChunk {
+            origin: *chunk.origin.pos(),
+            owned_edge_version: chunk.owned_edge_version,
+            upstream_edge_versions,
+            cells,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globe::chunk::{Cell, Material};
+    use crate::grid::Root;
+    use crate::test_util::TempPath;
+
+    fn stored_chunk(pos: Point3, material: Material) -> StoredChunk {
+        StoredChunk {
+            origin: pos,
+            owned_edge_version: 3,
+            upstream_edge_versions: vec![(Point3::new(Root::new(0), 0, 0, 4), 2)],
+            cells: ChunkCells::from(vec![
+                Cell {
+                    material,
+                    shade: 0.5,
+                };
+                17 * 17 * 4
+            ]),
+        }
+    }
+
+    #[test]
+    fn unsaved_chunk_is_not_found() {
+        let dir = TempPath::new("chunk-storage-unsaved");
+        let storage = ChunkStorage::new(&dir, [16, 16, 4]).unwrap();
+        let origin = ChunkOrigin::new(Point3::new(Root::new(1), 16, 32, 8), [64, 128], [16, 16, 4]);
+        assert!(storage.load(origin).unwrap().is_none());
+    }
+
+    #[test]
+    fn save_all_across_regions() {
+        let dir = TempPath::new("chunk-storage-save-all");
+        let a = Point3::new(Root::new(1), 16, 32, 8);
+        // Same region as `a`.
+        let b = Point3::new(Root::new(1), 0, 32, 8);
+        // Different root, so different region.
+        let c = Point3::new(Root::new(2), 16, 32, 8);
+        {
+            let storage = ChunkStorage::new(&dir, [16, 16, 4]).unwrap();
+            storage
+                .save_all(vec![
+                    stored_chunk(a, Material::DIRT),
+                    stored_chunk(b, Material::WATER),
+                    stored_chunk(c, Material::AIR),
+                ])
+                .unwrap();
+        }
+        assert_eq!(fs::read_dir(&*dir).unwrap().count(), 2);
+
+        // Make sure they were written out, rather than just cached.
+        let storage = ChunkStorage::new(&dir, [16, 16, 4]).unwrap();
+        for &(pos, material) in &[
+            (a, Material::DIRT),
+            (b, Material::WATER),
+            (c, Material::AIR),
+        ] {
+            let loaded = storage
+                .load(ChunkOrigin::new(pos, [64, 128], [16, 16, 4]))
+                .unwrap()
+                .expect("Chunk should have been saved");
+            assert_eq!(loaded.cells.get(0).material, material);
+        }
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = TempPath::new("chunk-storage-round-trip");
+        let storage = ChunkStorage::new(&dir, [16, 16, 4]).unwrap();
+        let a = Point3::new(Root::new(1), 16, 32, 8);
+        // Same region as `a`.
+        let b = Point3::new(Root::new(1), 0, 32, 8);
+        storage.save(stored_chunk(a, Material::DIRT)).unwrap();
+        storage.save(stored_chunk(b, Material::WATER)).unwrap();
+        // Overwrite `a`.
+        storage.save(stored_chunk(a, Material::AIR)).unwrap();
+
+        let loaded_a = storage
+            .load(ChunkOrigin::new(a, [64, 128], [16, 16, 4]))
+            .unwrap()
+            .expect("Chunk should have been saved");
+        assert_eq!(loaded_a.origin, a);
+        assert_eq!(loaded_a.owned_edge_version, 3);
+        assert_eq!(loaded_a.upstream_edge_versions.len(), 1);
+        assert_eq!(loaded_a.cells.get(0).material, Material::AIR);
+
+        let loaded_b = storage
+            .load(ChunkOrigin::new(b, [64, 128], [16, 16, 4]))
+            .unwrap()
+            .expect("Chunk should have been saved");
+        assert_eq!(loaded_b.cells.get(0).material, Material::WATER);
+    }
+}
@@ -1,223 +1,448 @@
+use std::collections::HashSet;
+
 use slog::Logger;
 use specs;
 use specs::Entities;
-use specs::{ReadStorage, WriteStorage};
+use specs::{Read, ReadStorage, WriteStorage};
 
-use super::{ChunkOrigin, Globe};
+use super::{ChunkOrigin, Globe, Spec};
+use crate::camera::DefaultCamera;
 use crate::cell_dweller::CellDweller;
-use crate::grid::PosInOwningRoot;
+use crate::grid::{Point3, PosInOwningRoot};
+use crate::types::*;
+use crate::Spatial;
 
-// NOTE: this is currently all pretty awful. See comments throughout.
+/// How many chunks `ChunkSystem` will keep loaded for each globe
+/// unless told otherwise.
+///
+/// Chunks on the example globes take somewhere in the order of
+/// 10KB each when they can't be compressed, so this is a few megabytes.
+pub const DEFAULT_CHUNK_BUDGET: usize = 300;
 
 /// Loads and unloads `Chunk`s for a `Globe`.
 ///
 /// The `Chunk`s may be loaded from disk, or generated fresh if
-/// they have never existed before.
+/// they have never existed before. Modified chunks are saved
+/// as they are unloaded if the `Globe` has been given somewhere
+/// to store them; see `Globe::set_storage`.
+///
+/// If the `Globe` builds chunks in the background (see
+/// `Globe::build_chunks_in_background`), then chunks requested
+/// here are loaded or built there, and added on later frames,
+/// as they become ready.
+///
+/// Each globe has a budget for how many chunks it may have loaded.
+/// The chunks that each `CellDweller` could move into next are essential,
+/// and are always loaded, even if that means going over budget. Before
+/// loading any new chunks, other chunks are unloaded to make room for them,
+/// starting with those farthest from any `CellDweller` or the default camera.
+/// Because this never loads anything that isn't essential, it never loads
+/// a chunk only to unload it again next frame.
 pub struct ChunkSystem {
     log: Logger,
-    // When we go higher than this many chunks loaded...
-    max_chunks_loaded_per_globe: usize,
-    // ...we will unload chunks to leave only this many.
-    // This is a kind of hysteresis. I haven't yet validated
-    // that this actually improves performance _at all_.
-    //
-    // TODO: instead have a budget for chunks, have the chunk
-    // creation logic know what it is and _target_ it when creating
-    // new chunks, so that we never end up with thrashing by
-    // creating too many then immediately deleting them repeatedly
-    // each frame. (You should only go over if you won't do it again
-    // immediately after cleaning up.) Then also complain loudly
-    // if there's not enough budget left for the really essential chunks
-    // for a given CellDweller.
-    cull_chunks_down_to: usize,
+    chunk_budget: usize,
+    // Globes for which we've already complained about the budget
+    // being too small, so we don't complain again every frame.
+    globes_over_budget: HashSet<specs::Entity>,
 }
 
 impl ChunkSystem {
     pub fn new(parent_log: &Logger) -> ChunkSystem {
         ChunkSystem {
             log: parent_log.new(o!()),
-            // TODO: accept as arguments.
-            //
-            // There appears to be at least ~110
-            // loaded at a minimum the way I have it at the moment;
-            // have to be super careful to get these numbers right
-            // so we don't unnecessarily churn chunks.
-            //
-            // TODO: how to make sure this is automatically right?
-            // (See comments above; have a budget, work to that.)
-            // These values were originally 200 and 150 before I bumped
-            // it up to allow for 3 players. This solution really
-            // isn't going to fly very long. It's time to decouple what
-            // chunk _views_ exist from what chunks exist, so we can
-            // load the essential chunks for each player character,
-            // and the desirable views for each client. (Etc.)
-            max_chunks_loaded_per_globe: 300,
-            cull_chunks_down_to: 250,
+            chunk_budget: DEFAULT_CHUNK_BUDGET,
+            globes_over_budget: HashSet::new(),
         }
     }
 
-    fn unload_excess_chunks_if_necessary<'a>(
-        &mut self,
-        globe: &mut Globe,
-        globe_entity: specs::Entity,
-        cds: &specs::ReadStorage<'a, CellDweller>,
-    ) {
+    /// Set the maximum number of chunks to keep loaded for each globe,
+    /// including those still being built in the background.
+    pub fn set_chunk_budget(&mut self, chunk_budget: usize) {
+        self.chunk_budget = chunk_budget;
+    }
+
+    // Make sure the chunk each CellDweller is in is present right now,
+    // and then list all the chunks that we could possibly try
+    // to move into from that chunk within two steps.
+    //
+    // Takes into account that a single user action could lead to
+    // multiple cell jumps, e.g., stepping up a small ledge.
+    //
+    // TODO: this is all a bit finicky and fragile.
+    //
+    // TODO: see remarks in `Chunk::list_accessible_chunks`
+    // about this actually being an inappropriate way to approach
+    // this problem; we'll load a bunch of chunks we don't need to yet
+    // in a desperate attempt to not miss the ones we do need.
+    //
+    // TODO: this is also just plain wrong.
+    // You don't need the neighbouring chunks of the neighbouring chunks.
+    // You just need all the chunks containing neighbouring cells of
+    // neighbouring cells. No wonder there are so many chunks loaded
+    // at the moment. :)
+    fn essential_chunks(&self, globe: &mut Globe, cds: &[&CellDweller]) -> Vec<ChunkOrigin> {
         use super::globe::GlobeGuts;
 
-        if globe.chunks().len() <= self.max_chunks_loaded_per_globe {
-            // We're under the limit; nothing to do.
+        let mut essential_chunks: Vec<ChunkOrigin> = Vec::new();
+        let mut seen: HashSet<ChunkOrigin> = HashSet::new();
+
+        // Each CellDweller's own chunk first; we can't wait for those.
+        let own_chunks: Vec<ChunkOrigin> = cds
+            .iter()
+            .map(|cd| {
+                let cd_pos_in_owning_root =
+                    PosInOwningRoot::new(cd.pos, globe.spec().root_resolution);
+                globe.origin_of_chunk_owning(cd_pos_in_owning_root)
+            })
+            .collect();
+        for chunk_origin in &own_chunks {
+            if let Err(e) = globe.ensure_chunk_present(*chunk_origin) {
+                self.warn_chunk_not_loaded(*chunk_origin, &e);
+            }
+            if seen.insert(*chunk_origin) {
+                essential_chunks.push(*chunk_origin);
+            }
+        }
+
+        // Then everything accessible from those. If a chunk isn't loaded yet,
+        // then we can't see what's accessible from it; we'll get to that
+        // on a later frame once it's ready.
+        let mut level_start = 0;
+        for _level in 0..2 {
+            let level_end = essential_chunks.len();
+            for i in level_start..level_end {
+                let accessible_chunks = match globe.chunks().get(&essential_chunks[i]) {
+                    // TODO: Gah, such slow!
+                    Some(chunk) => chunk.accessible_chunks.clone(),
+                    None => continue,
+                };
+                for accessible_chunk_origin in accessible_chunks {
+                    if seen.insert(accessible_chunk_origin) {
+                        essential_chunks.push(accessible_chunk_origin);
+                    }
+                }
+            }
+            level_start = level_end;
+        }
+        essential_chunks
+    }
+
+    fn warn_if_budget_too_small(&mut self, globe_entity: specs::Entity, essential_count: usize) {
+        if essential_count <= self.chunk_budget {
+            if self.globes_over_budget.remove(&globe_entity) {
+                info!(
+                    self.log,
+                    "Essential chunks fit within chunk budget again";
+                    "chunk_budget" => self.chunk_budget,
+                    "essential_chunks" => essential_count
+                );
+            }
             return;
         }
+        if self.globes_over_budget.insert(globe_entity) {
+            warn!(
+                self.log,
+                "CHUNK BUDGET IS TOO SMALL! Loading essential chunks anyway; increase it with `ChunkSystem::set_chunk_budget`";
+                "chunk_budget" => self.chunk_budget,
+                "essential_chunks" => essential_count
+            );
+        }
+    }
 
-        // Get all the CellDweller positions relative to the globe.
-        // We don't care which CellDweller is which, so just store
-        // them as a Vec of points.
-        use specs::Join;
-        let cd_positions: Vec<_> = cds
-            .join()
-            // Only consider CellDwellers from this globe.
-            .filter(|cd| cd.globe_entity == Some(globe_entity))
-            .map(|cd| cd.real_transform_without_setting_clean().translation.vector)
-            .collect();
+    fn warn_chunk_not_loaded(&self, chunk_origin: ChunkOrigin, e: &std::io::Error) {
+        warn!(
+            self.log,
+            "Failed to load saved chunk; built it fresh instead";
+            "chunk_origin" => format!("{:?}", chunk_origin),
+            "error" => format!("{}", e)
+        );
+    }
+
+    // Unload the least important chunks that aren't essential until
+    // there will be room for `chunks_to_add` more without going over budget.
+    fn make_room_for_chunks(
+        &mut self,
+        globe: &mut Globe,
+        essential_chunks: &HashSet<ChunkOrigin>,
+        focus_points: &[Vec3],
+        chunks_to_add: usize,
+    ) {
+        use super::globe::GlobeGuts;
 
-        // There are no cell dwellers, so no interesting terrain.
-        // (If a tree falls in a forest...)
-        if cd_positions.is_empty() {
+        let chunks_after_adding =
+            globe.chunks().len() + globe.pending_chunk_count() + chunks_to_add;
+        if chunks_after_adding <= self.chunk_budget {
+            // We're under budget; nothing to do.
             return;
         }
+        let chunks_to_remove = chunks_after_adding - self.chunk_budget;
 
-        // Unload the chunks that are most distant from their nearest CellDweller.
+        // Unload the chunks that are most distant from their nearest focus point.
         //
         // TODO: Don't allocate memory all the time here.
         // At very least use a persistent scratch buffer instead
         // of allocating every time!
+        let spec = globe.spec();
         let mut chunk_distances: Vec<(ChunkOrigin, f64)> = globe
             .chunks()
             .keys()
+            .filter(|chunk_origin| !essential_chunks.contains(chunk_origin))
             .map(|chunk_origin| {
-                // TODO: don't use chunk origin; use the middle cell,
-                // or otherwise whatever the closest corner is.
-                // Or even a bounding sphere.
-                // (Cache this per Chunk).
-                let chunk_origin_pos = globe.spec().cell_bottom_center(*chunk_origin.pos());
-                let distance_from_closest_cd = cd_positions
-                    .iter()
-                    // TODO: norm_squared; it'll be quicker.
-                    .map(|cd_pos| (cd_pos - chunk_origin_pos.coords).norm())
-                    .min_by(|a, b| {
-                        a.partial_cmp(b)
-                            .expect("Really shouldn't be possible to get NaN etc. here")
-                    })
-                    .expect("We already ensured there is at least one CellDweller");
-                (*chunk_origin, distance_from_closest_cd)
+                let distance = distance_to_nearest(&spec, *chunk_origin, focus_points);
+                (*chunk_origin, distance)
             })
             .collect();
         // Farthest away chunks come first.
         chunk_distances.sort_by(|a, b| {
-            b.1.partial_cmp(&a.1).expect(
-                "All chunk origins and CellDwellers should be real distances from each other!",
-            )
+            b.1.partial_cmp(&a.1)
+                .expect("All chunks and focus points should be real distances from each other!")
         });
-        let chunks_to_remove = self.max_chunks_loaded_per_globe - self.cull_chunks_down_to;
         chunk_distances.truncate(chunks_to_remove);
 
         for (chunk_origin, _distance) in chunk_distances {
-            globe.remove_chunk(chunk_origin);
-        }
-    }
-
-    fn ensure_essential_chunks_for_cell_dweller_present<'a>(
-        &mut self,
-        cd: &CellDweller,
-        globes: &mut specs::WriteStorage<'a, Globe>,
-    ) {
-        if let Some(globe_entity) = cd.globe_entity {
-            // Get the associated globe, complaining loudly if we fail.
-            // TODO: this is becoming a common pattern; factor out.
-            let globe = match globes.get_mut(globe_entity) {
-                Some(globe) => globe,
-                None => {
-                    warn!(
-                        self.log,
-                        "The globe associated with this CellDweller is not alive! Can't proceed!"
-                    );
-                    return;
-                }
-            };
-
-            // TODO: throttle, and do in background.
-            // (Except that the truly essential chunks really do need to be loaded _now_.)
-
-            // TODO: see remarks in `Chunk::list_accessible_chunks`
-            // about this actually being an inappropriate way to approach
-            // this problem; we'll load a bunch of chunks we don't need to yet
-            // in a desperate attempt to not miss the ones we do need.
-
-            // Load all the chunks that we could possibly try
-            // to move into from this chunk within two steps.
-            //
-            // Takes into account that a single user action could lead to
-            // multiple cell jumps, e.g., stepping up a small ledge.
-            //
-            // TODO: this is all a bit finicky and fragile.
-
-            // TODO: this is also just plain wrong.
-            // You don't need the neighbouring chunks of the neighbouring chunks.
-            // You just need all the chunks containing neighbouring cells of
-            // neighbouring cells. No wonder there are so many chunks loaded
-            // at the moment. :)
-            let cd_pos_in_owning_root = PosInOwningRoot::new(cd.pos, globe.spec().root_resolution);
-            let chunk_origin = globe.origin_of_chunk_owning(cd_pos_in_owning_root);
-            globe.ensure_chunk_present(chunk_origin);
-            let accessible_chunks = {
-                use super::globe::GlobeGuts;
-                let chunk = globe
-                    .chunks()
-                    .get(&chunk_origin)
-                    .expect("We just ensured this chunk is loaded.");
-                // TODO: Gah, such slow!
-                chunk.accessible_chunks.clone()
-            };
-            for accessible_chunk_origin in accessible_chunks {
-                globe.ensure_chunk_present(accessible_chunk_origin);
-
-                // Repeat this from each immediately accessible chunk.
-                let next_level_accessible_chunks = {
-                    use super::globe::GlobeGuts;
-                    let chunk = globe
-                        .chunks()
-                        .get(&accessible_chunk_origin)
-                        .expect("We just ensured this chunk is loaded.");
-                    // TODO: Gah, such slow!
-                    chunk.accessible_chunks.clone()
-                };
-                for next_level_accessible_chunk_origin in next_level_accessible_chunks {
-                    globe.ensure_chunk_present(next_level_accessible_chunk_origin);
-                }
+            if let Err(e) = globe.unload_chunk(chunk_origin) {
+                warn!(
+                    self.log,
+                    "Failed to save modified chunk; changes to it have been lost";
+                    "chunk_origin" => format!("{:?}", chunk_origin),
+                    "error" => format!("{}", e)
+                );
             }
         }
     }
 }
 
+// Position of the cell in the middle of a chunk, relative to the globe.
+pub(crate) fn chunk_middle(spec: &Spec, chunk_origin: ChunkOrigin) -> Pt3 {
+    // TODO: Cache this per Chunk.
+    let origin = chunk_origin.pos();
+    let chunk_res = spec.chunk_resolution;
+    let middle = Point3::new(
+        origin.root,
+        origin.x + chunk_res[0] / 2,
+        origin.y + chunk_res[1] / 2,
+        origin.z + chunk_res[2] / 2,
+    );
+    spec.cell_center_center(middle)
+}
+
+// Distance from the middle of the chunk to whichever of the given points is closest.
+// Returns zero if there are no points; all chunks are equally interesting then.
+fn distance_to_nearest(spec: &Spec, chunk_origin: ChunkOrigin, points: &[Vec3]) -> f64 {
+    let middle_pos = chunk_middle(spec, chunk_origin);
+    points
+        .iter()
+        .map(|point| (point - middle_pos.coords).norm_squared())
+        .min_by(|a, b| {
+            a.partial_cmp(b)
+                .expect("Really shouldn't be possible to get NaN etc. here")
+        })
+        .map(f64::sqrt)
+        .unwrap_or(0.0)
+}
+
+// Sort chunks so those nearest to any of the given points come first.
+// Chunks the same distance away keep their relative order.
+fn sort_nearest_first(spec: &Spec, chunks: &mut Vec<ChunkOrigin>, points: &[Vec3]) {
+    let mut chunk_distances: Vec<(ChunkOrigin, f64)> = chunks
+        .iter()
+        .map(|chunk_origin| {
+            let distance = distance_to_nearest(spec, *chunk_origin, points);
+            (*chunk_origin, distance)
+        })
+        .collect();
+    chunk_distances.sort_by(|a, b| {
+        a.1.partial_cmp(&b.1)
+            .expect("All chunks and focus points should be real distances from each other!")
+    });
+    chunks.clear();
+    chunks.extend(
+        chunk_distances
+            .into_iter()
+            .map(|(chunk_origin, _)| chunk_origin),
+    );
+}
+
 impl<'a> specs::System<'a> for ChunkSystem {
     type SystemData = (
         Entities<'a>,
         WriteStorage<'a, Globe>,
         ReadStorage<'a, CellDweller>,
+        ReadStorage<'a, Spatial>,
+        Read<'a, DefaultCamera>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
+        use crate::spatial::SpatialStorage;
         use specs::Join;
 
-        let (entities, mut globes, cds) = data;
+        let (entities, mut globes, cds, spatials, default_camera) = data;
+
+        for (globe, globe_entity) in (&mut globes, &*entities).join() {
+            // Pick up anything that has been built in the background since last time.
+            if let Err(e) = globe.add_built_chunks() {
+                warn!(
+                    self.log,
+                    "Failed to load saved chunk in the background; built it fresh instead";
+                    "error" => format!("{}", e)
+                );
+            }
+
+            // Only consider CellDwellers from this globe.
+            let globe_cds: Vec<&CellDweller> = cds
+                .join()
+                .filter(|cd| cd.globe_entity == Some(globe_entity))
+                .collect();
+
+            // Find everything that chunks should be near, relative to the globe.
+            // We don't care which CellDweller is which, so just store
+            // them as a Vec of points.
+            let mut focus_points: Vec<Vec3> = globe_cds
+                .iter()
+                .map(|cd| cd.real_transform_without_setting_clean().translation.vector)
+                .collect();
+            if let Some(camera_entity) = default_camera.camera_entity {
+                let camera_and_globe_are_related = spatials.get(camera_entity).is_some()
+                    && spatials.get(globe_entity).is_some()
+                    && spatials.have_common_ancestor(camera_entity, globe_entity);
+                if camera_and_globe_are_related {
+                    let camera_transform = spatials.a_relative_to_b(camera_entity, globe_entity);
+                    focus_points.push(camera_transform.translation.vector);
+                }
+            }
 
-        // If we have too many chunks loaded, then unload some of them.
-        for (mut globe, globe_entity) in (&mut globes, &*entities).join() {
-            self.unload_excess_chunks_if_necessary(&mut globe, globe_entity, &cds);
+            let mut essential_chunks = self.essential_chunks(globe, &globe_cds);
+            sort_nearest_first(&globe.spec(), &mut essential_chunks, &focus_points);
+            self.warn_if_budget_too_small(globe_entity, essential_chunks.len());
+
+            let chunks_to_add: Vec<ChunkOrigin> = essential_chunks
+                .iter()
+                .cloned()
+                .filter(|chunk_origin| {
+                    globe.chunk_at(*chunk_origin).is_none()
+                        && !globe.is_chunk_pending(*chunk_origin)
+                })
+                .collect();
+            if !focus_points.is_empty() {
+                let essential_set: HashSet<ChunkOrigin> =
+                    essential_chunks.iter().cloned().collect();
+                self.make_room_for_chunks(
+                    globe,
+                    &essential_set,
+                    &focus_points,
+                    chunks_to_add.len(),
+                );
+            }
+            // Nearest chunks are requested first, so they'll be ready first.
+            for chunk_origin in chunks_to_add {
+                if let Err(e) = globe.request_chunk(chunk_origin) {
+                    self.warn_chunk_not_loaded(chunk_origin, &e);
+                }
+            }
         }
-        // Make sure the chunks under/near the player are present.
-        for cd in cds.join() {
-            self.ensure_essential_chunks_for_cell_dweller_present(cd, &mut globes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Dir;
+
+    #[test]
+    fn stays_within_budget_and_keeps_essential_chunks() {
+        use specs::{Builder, RunNow};
+
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        world.register::<CellDweller>();
+        world.register::<Spatial>();
+        world.register::<Globe>();
+        world.add_resource(DefaultCamera::default());
+
+        let globe = Globe::new_example();
+        let spec = globe.spec();
+        let globe_entity = world.create_entity().with(globe).build();
+        let cd_pos = Point3::new(crate::grid::Root::new(0), 20, 36, 61);
+        world
+            .create_entity()
+            .with(CellDweller::new(
+                cd_pos,
+                Dir::default(),
+                spec,
+                Some(globe_entity),
+            ))
+            .with(Spatial::new_root())
+            .build();
+
+        // Load a bunch of other chunks on the far side of the globe.
+        {
+            let mut globes = world.write_storage::<Globe>();
+            let globe = globes.get_mut(globe_entity).unwrap();
+            for z in 0..20 {
+                globe
+                    .ensure_chunk_present(ChunkOrigin::new(
+                        Point3::new(crate::grid::Root::new(3), 32, 64, z * 4),
+                        spec.root_resolution,
+                        spec.chunk_resolution,
+                    ))
+                    .unwrap();
+            }
+        }
+
+        let mut chunk_system = ChunkSystem::new(&log);
+        // It takes a few runs to discover all the essential chunks,
+        // because we can only see what's accessible from loaded chunks.
+        for _ in 0..3 {
+            chunk_system.run_now(&world.res);
+        }
+        let essential_count = {
+            use crate::globe::globe::GlobeGuts;
+            let globes = world.read_storage::<Globe>();
+            // Nothing should have been unloaded yet.
+            globes.get(globe_entity).unwrap().chunks().len() - 20
+        };
+
+        // Now squeeze the budget so that only the essential chunks fit.
+        chunk_system.set_chunk_budget(essential_count);
+        for _ in 0..3 {
+            chunk_system.run_now(&world.res);
         }
+        let globes = world.read_storage::<Globe>();
+        let globe = globes.get(globe_entity).unwrap();
+        let own_chunk =
+            globe.origin_of_chunk_owning(PosInOwningRoot::new(cd_pos, spec.root_resolution));
+        assert!(globe.chunk_at(own_chunk).is_some());
+        for z in 0..20 {
+            let far_chunk = ChunkOrigin::new(
+                Point3::new(crate::grid::Root::new(3), 32, 64, z * 4),
+                spec.root_resolution,
+                spec.chunk_resolution,
+            );
+            assert!(globe.chunk_at(far_chunk).is_none());
+        }
+        assert!(!chunk_system.globes_over_budget.contains(&globe_entity));
+    }
+
+    #[test]
+    fn sorts_chunks_by_distance_to_nearest_point() {
+        let spec = Globe::new_example().spec();
+        let chunk_origin = |root: u8, x, y, z| {
+            ChunkOrigin::new(
+                Point3::new(crate::grid::Root::new(root), x, y, z),
+                spec.root_resolution,
+                spec.chunk_resolution,
+            )
+        };
+        let near_a = chunk_origin(0, 0, 0, 0);
+        let near_b = chunk_origin(3, 32, 64, 0);
+        // Stacked above `near_a`.
+        let farther_a = chunk_origin(0, 0, 0, 4);
+        let farthest = chunk_origin(0, 0, 0, 12);
+        let point_a = chunk_middle(&spec, near_a).coords;
+        let point_b = chunk_middle(&spec, near_b).coords;
+
+        let mut chunks = vec![farthest, farther_a, near_b, near_a];
+        sort_nearest_first(&spec, &mut chunks, &[point_a, point_b]);
+        assert_eq!(chunks, vec![near_b, near_a, farther_a, farthest]);
     }
 }
@@ -2,14 +2,28 @@ use crate::na;
 use slog::Logger;
 use specs;
 use specs::Entities;
-use specs::{Read, Write, WriteStorage};
+use specs::{Read, ReadStorage, Write, WriteStorage};
 
-use crate::globe::{ChunkView, Globe, View};
+use super::chunk_system::chunk_middle;
+use super::viewer::viewers_of_globe;
+use crate::globe::{ChunkOrigin, ChunkView, Globe, Spec, View, Viewer};
 use crate::physics::{Collider, RemoveColliderQueue, WorldResource};
 use crate::render::{ProtoMesh, Vertex, Visual};
 use crate::types::*;
 use crate::Spatial;
 
+/// Once a chunk view is made, we keep it until its chunk is this much
+/// farther away than a `Viewer`'s view distance, so that views aren't
+/// thrown away and rebuilt over and over as a viewer moves back
+/// and forth across the edge of a chunk.
+const VIEW_DISTANCE_SLACK: Real = 1.2;
+
+/// Makes `ChunkView`s for loaded chunks near any `Viewer`,
+/// and builds their geometry.
+///
+/// Which chunks are loaded is up to `ChunkSystem`; this only decides
+/// which of those get drawn. If there are no `Viewer`s, then no
+/// views are made at all.
 // For now, just creates up to 1 chunk view per tick,
 // until we have created views for all chunks.
 pub struct ChunkViewSystem {
@@ -199,7 +213,29 @@ impl ChunkViewSystem {
         }
     }
 
-    pub fn remove_views_for_dead_chunks<'a>(
+    /// Detach views from any chunks that have moved out of range of all `Viewer`s.
+    /// The views themselves are cleaned up by `remove_orphaned_views`.
+    pub fn forget_views_out_of_range(&mut self, globe: &mut Globe, viewers: &[(Pt3, Real)]) {
+        use crate::globe::globe::GlobeGuts;
+        let globe_spec = globe.spec();
+        for chunk in globe.chunks_mut().values_mut() {
+            if chunk.view_entity.is_none() {
+                continue;
+            }
+            if is_in_view(&globe_spec, chunk.origin, viewers, VIEW_DISTANCE_SLACK) {
+                continue;
+            }
+            debug!(self.log, "Forgetting a chunk view that is out of range"; "origin" => format!("{:?}", chunk.origin));
+            // Make sure it gets a fresh view with new geometry
+            // if it comes back into range.
+            chunk.view_entity = None;
+            chunk.mark_view_as_dirty();
+        }
+    }
+
+    /// Destroy views for chunks that are no longer loaded,
+    /// or that no longer consider the view to be theirs.
+    pub fn remove_orphaned_views<'a>(
         &mut self,
         entities: &Entities<'a>,
         globe: &mut Globe,
@@ -219,29 +255,24 @@ impl ChunkViewSystem {
                 continue;
             }
 
-            if globe.chunk_at(chunk_view.origin).is_none() {
+            let is_orphaned = match globe.chunk_at(chunk_view.origin) {
+                Some(chunk) => chunk.view_entity != Some(chunk_view_ent),
+                None => true,
+            };
+            if is_orphaned {
                 debug!(self.log, "Removing a chunk view"; "origin" => format!("{:?}", chunk_view.origin));
                 entities_to_remove.push(chunk_view_ent);
             }
         }
 
         for chunk_view_ent in entities_to_remove {
-            // TODO: don't forget to remove the MESH.
-            // TODO: don't forget to remove the MESH.
-            // TODO: don't forget to remove the MESH.
-            // TODO: don't forget to remove the MESH.
-            // TODO: don't forget to remove the MESH.
-            //
-            // If you don't do that, then we'll slowly leak VBOs etc.
-            //
-            // But you can get away with not doing that for now because
-            // the tests don't start the render system, and so will never
-            // make those meshes to begin with.
-
             // Remove Visual and ChunkView components (to prevent accidentally
             // iterating over them later within the same frame) and then queue
-            // the entity itself up for deletion.
-            visuals.remove(chunk_view_ent);
+            // the entity itself up for deletion. Releasing the visual's mesh
+            // lets the mesh repository destroy it the next time it collects garbage.
+            if let Some(mut visual) = visuals.remove(chunk_view_ent) {
+                visual.release_mesh();
+            }
             chunk_views.remove(chunk_view_ent);
             entities
                 .delete(chunk_view_ent)
@@ -268,11 +299,14 @@ impl ChunkViewSystem {
         }
     }
 
+    // TODO: bundle up the storages we need here so this doesn't take so many arguments.
+    #[allow(clippy::too_many_arguments)]
     pub fn ensure_chunk_view_entities<'a>(
         &mut self,
         entities: &Entities<'a>,
         globe: &mut Globe,
         globe_entity: specs::Entity,
+        viewers: &[(Pt3, Real)],
         chunk_views: &mut WriteStorage<'a, ChunkView>,
         visuals: &mut WriteStorage<'a, Visual>,
         spatials: &mut WriteStorage<'a, Spatial>,
@@ -283,6 +317,9 @@ impl ChunkViewSystem {
             if chunk.view_entity.is_some() {
                 continue;
             }
+            if !is_in_view(&globe_spec, chunk.origin, viewers, 1.0) {
+                continue;
+            }
             trace!(self.log, "Making a chunk view"; "origin" => format!("{:?}", chunk.origin));
             let chunk_view = ChunkView::new(globe_entity, chunk.origin);
 
@@ -321,6 +358,7 @@ impl<'a> specs::System<'a> for ChunkViewSystem {
         Write<'a, WorldResource>,
         Write<'a, crate::physics::RemoveColliderQueue>,
         WriteStorage<'a, crate::physics::Collider>,
+        ReadStorage<'a, Viewer>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -335,13 +373,19 @@ impl<'a> specs::System<'a> for ChunkViewSystem {
             mut world_resource,
             mut remove_collider_queue_resource,
             mut colliders,
+            viewers,
         ) = data;
 
         self.seconds_since_last_geometry_creation += dt.0;
 
-        // Destroy views for any chunks that are no longer loaded.
         for (globe, globe_entity) in (&mut globes, &*entities).join() {
-            self.remove_views_for_dead_chunks(
+            // Find everyone who might be looking at this globe.
+            let globe_viewers = viewers_of_globe(&entities, &viewers, &spatials, globe_entity);
+
+            // Destroy views for any chunks that are no longer loaded,
+            // or that nobody can see any more.
+            self.forget_views_out_of_range(globe, &globe_viewers);
+            self.remove_orphaned_views(
                 &entities,
                 globe,
                 globe_entity,
@@ -351,19 +395,13 @@ impl<'a> specs::System<'a> for ChunkViewSystem {
                 &colliders,
             );
 
-            // Ensure that there is a visual for
-            // every chunk currently loaded in the globe.
-            //
-            // TODO: we don't actually want to do this
-            // long-term; it's just a first step in migrating
-            // to systems-based view creation. Eventually we'll
-            // be selective about what views to have; i.e. we might
-            // have 1000 chunks loaded, and only render 200 of them
-            // on this client.
+            // Ensure that there is a visual for every chunk
+            // currently loaded in the globe that a viewer can see.
             self.ensure_chunk_view_entities(
                 &entities,
                 globe,
                 globe_entity,
+                &globe_viewers,
                 &mut chunk_views,
                 &mut visuals,
                 &mut spatials,
@@ -383,3 +421,90 @@ impl<'a> specs::System<'a> for ChunkViewSystem {
         );
     }
 }
+
+// Whether the middle of the chunk is within `slack` times
+// the view distance of any of the given viewers.
+fn is_in_view(spec: &Spec, origin: ChunkOrigin, viewers: &[(Pt3, Real)], slack: Real) -> bool {
+    let middle = chunk_middle(spec, origin);
+    viewers
+        .iter()
+        .any(|(position, view_distance)| na::distance(position, &middle) <= view_distance * slack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Point3, Root};
+
+    #[test]
+    fn only_makes_views_near_viewers() {
+        use specs::{Builder, Join, RunNow};
+
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let mut chunk_view_system = ChunkViewSystem::new(&log, 0.05);
+        chunk_view_system.setup(&mut world.res);
+
+        let mut globe = Globe::new_example();
+        let spec = globe.spec();
+        let near_chunk = ChunkOrigin::new(
+            Point3::new(Root::new(0), 16, 32, 60),
+            spec.root_resolution,
+            spec.chunk_resolution,
+        );
+        let far_chunk = ChunkOrigin::new(
+            Point3::new(Root::new(3), 32, 64, 60),
+            spec.root_resolution,
+            spec.chunk_resolution,
+        );
+        globe.ensure_chunk_present(near_chunk).unwrap();
+        globe.ensure_chunk_present(far_chunk).unwrap();
+        let globe_entity = world
+            .create_entity()
+            .with(globe)
+            .with(Spatial::new_root())
+            .build();
+
+        let view_origins = |world: &specs::World| -> Vec<ChunkOrigin> {
+            world
+                .read_storage::<ChunkView>()
+                .join()
+                .map(|chunk_view| chunk_view.origin)
+                .collect()
+        };
+
+        // Nobody is looking, so there shouldn't be any views,
+        // even though there are chunks loaded.
+        chunk_view_system.run_now(&world.res);
+        world.maintain();
+        assert!(view_origins(&world).is_empty());
+
+        // Put a viewer right next to one of the chunks.
+        let viewer_transform =
+            |origin: ChunkOrigin| Iso3::new(chunk_middle(&spec, origin).coords, na::zero());
+        let viewer_entity = world
+            .create_entity()
+            .with(Spatial::new(globe_entity, viewer_transform(near_chunk)))
+            .with(Viewer::new(10.0))
+            .build();
+        chunk_view_system.run_now(&world.res);
+        world.maintain();
+        assert_eq!(view_origins(&world), vec![near_chunk]);
+
+        // Move the viewer over to the other chunk; the view
+        // should follow it, even though both chunks stay loaded.
+        world
+            .write_storage::<Spatial>()
+            .get_mut(viewer_entity)
+            .unwrap()
+            .set_local_transform(viewer_transform(far_chunk));
+        chunk_view_system.run_now(&world.res);
+        world.maintain();
+        assert_eq!(view_origins(&world), vec![far_chunk]);
+        let globes = world.read_storage::<Globe>();
+        let globe = globes.get(globe_entity).unwrap();
+        assert!(globe.chunk_at(near_chunk).is_some());
+        use crate::globe::globe::GlobeGuts;
+        assert!(globe.chunks()[&near_chunk].view_entity.is_none());
+    }
+}
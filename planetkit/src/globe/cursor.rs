@@ -174,10 +174,14 @@ impl<'a> Cursor<'a> {
 
 impl<'a> CursorMut<'a> {
     // See `Globe::ensure_chunk_present`.
+    //
+    // The chunk is present either way, so there's nothing we can
+    // usefully do about an error from loading it. The `ChunkSystem`
+    // is the one that reports those.
     pub fn ensure_chunk_present(&mut self) {
         let chunk_origin: ChunkOrigin =
             self.globe.origin_of_chunk_in_same_root_containing(self.pos);
-        self.globe.ensure_chunk_present(chunk_origin);
+        let _ = self.globe.ensure_chunk_present(chunk_origin);
     }
 
     /// Only use this if you don't care about which chunk
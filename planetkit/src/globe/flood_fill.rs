@@ -0,0 +1,93 @@
+//! Finding connected regions of cells; useful for finding
+//! pits, caves, bodies of water, etc.
+
+use std::collections::{HashSet, VecDeque};
+
+use super::chunk::Cell;
+use super::globe::Globe;
+use super::CursorMut;
+use crate::grid::{Neighbors, Point3, PosInOwningRoot};
+
+/// A connected group of cells found by `Globe::flood_fill`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Region {
+    /// Every cell found, in the order they were reached;
+    /// nearest to where the search started first.
+    pub cells: Vec<PosInOwningRoot>,
+    /// Whether there were more matching cells connected to the region
+    /// than the search was allowed to visit. If so, then `cells` is only
+    /// part of the region.
+    ///
+    /// E.g., if you're looking for a hole in the ground by searching for
+    /// connected cells of air, then this tells you that the hole is open
+    /// to the sky, or joins up with a cave bigger than you care about.
+    pub escaped_bound: bool,
+}
+
+impl Region {
+    /// Number of cells in the region.
+    pub fn volume(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn contains(&self, pos: PosInOwningRoot) -> bool {
+        self.cells.contains(&pos)
+    }
+}
+
+impl Globe {
+    /// Find all cells connected to `start` for which `predicate` returns `true`,
+    /// stepping between cells that share a face, including across chunk
+    /// and root boundaries.
+    ///
+    /// Visits at most `max_cells` cells, loading any chunks it needs
+    /// along the way; see `Region::escaped_bound`.
+    ///
+    /// If `start` itself doesn't match, then the region will be empty.
+    pub fn flood_fill<P>(&mut self, start: Point3, max_cells: usize, mut predicate: P) -> Region
+    where
+        P: FnMut(&Cell) -> bool,
+    {
+        let resolution = self.spec().root_resolution;
+        let start = PosInOwningRoot::new(start, resolution);
+        let mut cells = Vec::new();
+        let mut seen: HashSet<PosInOwningRoot> = HashSet::new();
+        let mut to_visit: VecDeque<PosInOwningRoot> = VecDeque::new();
+        seen.insert(start);
+        to_visit.push_back(start);
+
+        let chunk_origin = self.origin_of_chunk_owning(start);
+        let mut cursor = CursorMut::new_in_chunk(self, chunk_origin);
+        while let Some(pos) = to_visit.pop_front() {
+            cursor.set_pos(pos.into());
+            cursor.ensure_chunk_present();
+            let matches = {
+                let cell = cursor
+                    .cell()
+                    .expect("We just ensured the chunk is present, but apparently it's not.");
+                predicate(cell)
+            };
+            if !matches {
+                continue;
+            }
+            if cells.len() == max_cells {
+                return Region {
+                    cells,
+                    escaped_bound: true,
+                };
+            }
+            cells.push(pos);
+
+            for neighbor in Neighbors::new(pos.into(), resolution) {
+                let neighbor = PosInOwningRoot::new(neighbor, resolution);
+                if seen.insert(neighbor) {
+                    to_visit.push_back(neighbor);
+                }
+            }
+        }
+        Region {
+            cells,
+            escaped_bound: false,
+        }
+    }
+}
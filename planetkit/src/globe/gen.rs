@@ -4,6 +4,7 @@ use super::chunk::{Cell, Material};
 use super::spec::Spec;
 use crate::globe::ChunkOrigin;
 use crate::grid::{Point2, Point3};
+use crate::types::*;
 
 // TODO: turn this into a component that we can slap onto a Globe
 // or other globe-oid (distant point?).
@@ -19,14 +20,116 @@ use crate::grid::{Point2, Point3};
 /// implementations of globes, e.g., a full voxmap based globe,
 /// a distant blob in the sky, to a shiny dot in the distance.
 pub trait Gen: Send + Sync {
+    /// Approximate distance from the center of the globe to the surface
+    /// of the land in this column.
+    ///
+    /// Generators that carve caves or overhangs should keep the real
+    /// surface close to this, because it's used as a starting point
+    /// when searching for the surface, e.g., to find spawn points.
     fn land_height(&self, column: Point2) -> f64;
+
+    /// How solid the land is at the center of the given cell;
+    /// anything above zero is solid.
+    ///
+    /// By default this is just how far the cell is below `land_height`,
+    /// so there can be only one surface in each column.
+    fn density(&self, grid_point: Point3) -> f64 {
+        let land_height = self.land_height(grid_point.rxy);
+        let cell_pt3 = self.spec().cell_center_center(grid_point);
+        land_height - cell_pt3.coords.norm()
+    }
+
+    fn spec(&self) -> &Spec;
     fn cell_at(&self, grid_point: Point3) -> Cell;
     fn populate_cells(&self, origin: ChunkOrigin, cells: &mut Vec<Cell>);
 }
 
+// Number of distinct shades a generated cell can have.
+const SHADE_LEVELS: u8 = 16;
+
+/// Parameters for generating terrain from 3D noise,
+/// rather than just a height map.
+#[derive(Clone, Copy, Debug)]
+pub struct DensityParams {
+    pub seed: u32,
+    /// Approximate size of overhangs and width of cave networks.
+    pub wavelength: f64,
+    /// How far 3D noise can push the surface up or down
+    /// from `Gen::land_height`, making overhangs and arches.
+    pub overhang_height: f64,
+    /// Larger values make wider cave tunnels; in [0, 1].
+    pub cave_width: f64,
+    /// Caves never come closer to `Gen::land_height` than this, so that
+    /// they don't flood, and so that searching down from the surface finds
+    /// the real surface rather than the roof of a cave.
+    pub cave_min_depth: f64,
+}
+
+impl DensityParams {
+    pub fn from_spec(spec: &Spec) -> DensityParams {
+        DensityParams {
+            // Keep away from the seed used for the height map.
+            seed: (spec.seed as u32).wrapping_add(1),
+            wavelength: spec.block_height * 16.0,
+            overhang_height: spec.block_height * 3.0,
+            cave_width: 0.1,
+            cave_min_depth: spec.block_height * 8.0,
+        }
+    }
+}
+
+struct DensityNoise {
+    params: DensityParams,
+    surface_noise: noise::Perlin,
+    tunnels: TunnelNoise,
+}
+
+/// Winding cave tunnels, shared by the generators that carve caves.
+///
+/// Tunnels are wherever two independent noise fields are both near zero;
+/// that gives long winding tubes rather than isolated blobs.
+pub(crate) struct TunnelNoise {
+    wavelength: f64,
+    width: f64,
+    noises: [noise::Perlin; 2],
+}
+
+impl TunnelNoise {
+    /// `wavelength` is the approximate distance between tunnels, and
+    /// larger values of `width` (in [0, 1]) make wider tunnels.
+    pub fn new(seed: u32, wavelength: f64, width: f64) -> TunnelNoise {
+        use noise::Seedable;
+
+        TunnelNoise {
+            wavelength,
+            width,
+            noises: [
+                noise::Perlin::new().set_seed(seed),
+                noise::Perlin::new().set_seed(seed.wrapping_add(1)),
+            ],
+        }
+    }
+
+    /// Roughly how far `pt3` is from the nearest tunnel wall;
+    /// zero or less inside a tunnel.
+    pub fn distance_outside(&self, pt3: Pt3) -> f64 {
+        use noise::NoiseFn;
+
+        let scaled_pt3 = pt3 / self.wavelength;
+        let noise_distance = self
+            .noises
+            .iter()
+            .map(|noise| noise.get([scaled_pt3.x, scaled_pt3.y, scaled_pt3.z]).abs())
+            .fold(0.0, f64::max);
+        (noise_distance - self.width) * self.wavelength
+    }
+}
+
 pub struct SimpleGen {
     spec: Spec,
     terrain_noise: noise::Fbm,
+    // Only present if 3D density mode is enabled.
+    density_noise: Option<DensityNoise>,
 }
 
 impl SimpleGen {
@@ -56,8 +159,60 @@ impl SimpleGen {
         SimpleGen {
             spec,
             terrain_noise,
+            density_noise: None,
         }
     }
+
+    /// Create a generator that samples 3D noise over the whole volume
+    /// of the globe, making caves and overhangs as well as the
+    /// hills and valleys of the height map.
+    pub fn new_with_density(spec: Spec, params: DensityParams) -> SimpleGen {
+        use noise::Seedable;
+
+        let mut gen = SimpleGen::new(spec);
+        gen.density_noise = Some(DensityNoise {
+            params,
+            surface_noise: noise::Perlin::new().set_seed(params.seed),
+            tunnels: TunnelNoise::new(
+                params.seed.wrapping_add(1),
+                params.wavelength,
+                params.cave_width,
+            ),
+        });
+        gen
+    }
+
+    // Distance below the land surface above which water can fill empty space.
+    // Caves are deeper than this, so they stay dry.
+    fn max_water_depth(&self) -> f64 {
+        self.density_noise
+            .as_ref()
+            .map(|density_noise| density_noise.params.cave_min_depth)
+            .unwrap_or(0.0)
+    }
+
+    // See `Gen::density`; split out so that `cell_at` doesn't have to
+    // calculate the land height twice.
+    fn density_below(&self, land_height: f64, cell_pt3: Pt3) -> f64 {
+        use noise::NoiseFn;
+
+        let depth = land_height - cell_pt3.coords.norm();
+        let density_noise = match self.density_noise {
+            Some(ref density_noise) => density_noise,
+            None => return depth,
+        };
+        let params = &density_noise.params;
+
+        // Nudge the surface up and down to make overhangs.
+        let scaled_pt3 = cell_pt3 / params.wavelength;
+        let scaled_pt3 = [scaled_pt3.x, scaled_pt3.y, scaled_pt3.z];
+        let density = depth + density_noise.surface_noise.get(scaled_pt3) * params.overhang_height;
+        if depth < params.cave_min_depth {
+            return density;
+        }
+
+        density.min(density_noise.tunnels.distance_outside(cell_pt3))
+    }
 }
 
 impl Gen for SimpleGen {
@@ -87,38 +242,39 @@ impl Gen for SimpleGen {
         self.spec.ocean_radius + delta
     }
 
+    fn density(&self, grid_point: Point3) -> f64 {
+        let land_height = self.land_height(grid_point.rxy);
+        let cell_pt3 = self.spec.cell_center_center(grid_point);
+        self.density_below(land_height, cell_pt3)
+    }
+
+    fn spec(&self) -> &Spec {
+        &self.spec
+    }
+
     fn cell_at(&self, grid_point: Point3) -> Cell {
         let land_height = self.land_height(grid_point.rxy);
         let cell_pt3 = self.spec.cell_center_center(grid_point);
         // TEMP: ...
         let cell_height = cell_pt3.coords.norm();
-        let material = if cell_height < land_height {
-            Material::Dirt
-        } else if cell_height < self.spec.ocean_radius {
-            Material::Water
+        let material = if self.density_below(land_height, cell_pt3) > 0.0 {
+            Material::DIRT
+        } else if cell_height < self.spec.ocean_radius
+            && land_height - cell_height <= self.max_water_depth()
+        {
+            Material::WATER
         } else {
-            Material::Air
+            Material::AIR
         };
-        Cell {
+        let mut cell = Cell {
             material,
-            // `Globe` fills this in; it's not really a property
-            // of the naturally generated world, and it's not
-            // deterministic from the world seed, so we don't
-            // want to pollute `Gen` with it.
-            //
-            // TODO: probably remove this? We're just using
-            // temporarily to create some texture across
-            // cells to make them easy to tell apart and look
-            // kinda nice, but this probably isn't a great
-            // long-term solution...
             shade: 1.0,
-        }
+        };
+        shade_cell(self.spec.seed, grid_point, &mut cell);
+        cell
     }
 
     fn populate_cells(&self, origin: ChunkOrigin, cells: &mut Vec<Cell>) {
-        use rand;
-        use rand::Rng;
-
         // We should be passed an empty vector to populate.
         assert!(cells.is_empty());
 
@@ -136,13 +292,48 @@ impl Gen for SimpleGen {
             for cell_y in origin.y..=end_y {
                 for cell_x in origin.x..=end_x {
                     let grid_point = Point3::new(origin.root, cell_x, cell_y, cell_z);
-                    let mut cell = self.cell_at(grid_point);
-                    // Temp hax?
-                    let mut rng = rand::thread_rng();
-                    cell.shade = 1.0 - 0.5 * rng.gen::<f32>();
-                    cells.push(cell);
+                    cells.push(self.cell_at(grid_point));
                 }
             }
         }
     }
 }
+
+// Mix the seed and every coordinate of the point together
+// (SplitMix64) to get a value that looks random, but is the same
+// for any given seed and point on every peer, and every time it
+// is generated.
+pub(crate) fn hash_cell(seed: u64, point: Point3) -> u64 {
+    let mut hash = seed;
+    for &value in &[
+        u64::from(point.root.index),
+        point.x as u64,
+        point.y as u64,
+        point.z as u64,
+    ] {
+        hash = hash.wrapping_add(value).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        hash ^= hash >> 31;
+    }
+    hash
+}
+
+// Mixed into the seed for cell shading, so that shade
+// doesn't line up with anything else derived from the hash.
+const SHADE_SALT: u64 = 0x5A4D_E5A1_7000_0001;
+
+// Give cells some texture, to make them easy to tell
+// apart and look kinda nice. This isn't really a property
+// of the world, but deriving it from the seed means that
+// the same chunk is identical wherever it is generated.
+//
+// Only a few distinct shades, and none at all for air,
+// so that chunks still compress well. (See `ChunkCells`.)
+pub(crate) fn shade_cell(seed: u64, grid_point: Point3, cell: &mut Cell) {
+    if cell.material != Material::AIR {
+        let hash = hash_cell(seed ^ SHADE_SALT, grid_point);
+        let shade_level = (hash % u64::from(SHADE_LEVELS)) as f32;
+        cell.shade = 1.0 - 0.5 * shade_level / SHADE_LEVELS as f32;
+    }
+}
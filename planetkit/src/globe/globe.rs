@@ -1,10 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::Arc;
 
 use specs;
 
 use super::chunk::{Cell, Chunk};
+use super::chunk_builder::{self, BuiltChunk, ChunkBuilder};
 use super::chunk_pair::{ChunkPair, ChunkPairOrigins};
+use super::chunk_storage::{ChunkStorage, StoredChunk};
 use super::gen::{Gen, SimpleGen};
+use super::material::MaterialRegistry;
 use super::spec::Spec;
 use super::ChunkOrigin;
 use super::{origin_of_chunk_in_same_root_containing, origin_of_chunk_owning};
@@ -17,17 +22,34 @@ pub struct Globe {
     spec: Spec,
     // TODO: temporarily making this public because I'm planning to
     // rip it out of `Globe` anyway.
-    pub gen: Box<dyn Gen>,
+    //
+    // Shared with the chunk builder threads, if any; replacing this
+    // won't affect chunks that are built in the background.
+    pub gen: Arc<dyn Gen>,
+    // What each material that cells can contain is like.
+    // Shared, because it's the same for every globe in a game.
+    materials: Arc<MaterialRegistry>,
     // Map chunk origins to chunks.
     //
-    // TODO: you'll probably also want to store some lower-res
-    // pseudo-chunks for rendering planets at a distance.
-    // But maybe you can put that off? Or maybe that's an entirely
-    // different type of Globe-oid?
+    // Parts of the globe without chunks loaded are drawn
+    // at a lower resolution straight from `gen`; see `LodSystem`.
     chunks: HashMap<ChunkOrigin, Chunk>,
     // Track which chunks are up-to-date with authoritative data for cells
     // they share with a neighbor.
     chunk_pairs: HashMap<ChunkPairOrigins, ChunkPair>,
+    // Where to save modified chunks when they are unloaded,
+    // and look for them again before generating them fresh.
+    // If not present, then modified chunks are simply lost
+    // when they are unloaded.
+    //
+    // Shared with the chunk builder threads, if any.
+    storage: Option<Arc<ChunkStorage>>,
+    // Builds chunks on background threads. If not present,
+    // then chunks are built as soon as they are requested.
+    chunk_builder: Option<ChunkBuilder>,
+    // Chunks that have been requested from the chunk builder
+    // but haven't been added to the globe yet.
+    pending_chunks: HashSet<ChunkOrigin>,
 }
 
 // Allowing sibling modules to reach into semi-private parts
@@ -48,12 +70,33 @@ impl<'a> GlobeGuts<'a> for Globe {
 }
 
 impl Globe {
+    /// Create a globe that only knows about the built-in materials.
+    ///
+    /// Any materials a game has registered, e.g., with `AppBuilder::with_material`,
+    /// won't be available; use `new_with_materials` with the world's
+    /// `MaterialRegistry` for those.
     pub fn new(spec: Spec) -> Globe {
+        Globe::new_with_materials(spec, Arc::new(MaterialRegistry::new()))
+    }
+
+    /// Create a globe whose cells can contain any of the given materials,
+    /// rather than just the built-in ones.
+    pub fn new_with_materials(spec: Spec, materials: Arc<MaterialRegistry>) -> Globe {
+        Globe::new_with_gen(spec, materials, Box::new(SimpleGen::new(spec)))
+    }
+
+    /// Create a globe whose content is generated by the given `Gen`,
+    /// e.g., a `LayeredGen`, instead of the default `SimpleGen`.
+    pub fn new_with_gen(spec: Spec, materials: Arc<MaterialRegistry>, gen: Box<dyn Gen>) -> Globe {
         Globe {
             spec,
-            gen: Box::new(SimpleGen::new(spec)),
+            gen: Arc::from(gen),
+            materials,
             chunks: HashMap::new(),
             chunk_pairs: HashMap::new(),
+            storage: None,
+            chunk_builder: None,
+            pending_chunks: HashSet::new(),
         }
     }
 
@@ -78,6 +121,21 @@ impl Globe {
         self.spec
     }
 
+    pub fn materials(&self) -> &MaterialRegistry {
+        &self.materials
+    }
+
+    /// Save modified chunks to, and load them back from, the given storage.
+    pub fn set_storage(&mut self, storage: ChunkStorage) {
+        self.storage = Some(Arc::new(storage));
+    }
+
+    /// Build chunks requested through `request_chunk` on the given number
+    /// of background threads, instead of immediately.
+    pub fn build_chunks_in_background(&mut self, num_threads: usize) {
+        self.chunk_builder = Some(ChunkBuilder::new(self.spec, self.gen.clone(), num_threads));
+    }
+
     /// Copy shared cells owned by a chunk for any loaded downstream chunks
     /// that have an outdated copy.
     ///
@@ -126,15 +184,19 @@ impl Globe {
             // Copy over each cell, one-by-one.
             for point_pair in &chunk_pair.point_pairs {
                 let source_cell = *source_chunk.cell(point_pair.source.into());
-                let target_cell = sink_chunk.cell_mut(point_pair.sink);
                 // Copy source -> target.
-                *target_cell = source_cell;
+                sink_chunk.set_cell(point_pair.sink, source_cell);
             }
 
             // Downstream chunk now has most recent changes from upstream.
             chunk_pair.last_upstream_edge_version_known_downstream =
                 source_chunk.owned_edge_version;
 
+            // Changes made upstream are now part of the sink chunk, too.
+            if source_chunk.is_modified {
+                sink_chunk.mark_as_modified();
+            }
+
             // If we got this far, then it means we needed to update something.
             // So mark the downstream chunk as having its view out-of-date.
             //
@@ -211,15 +273,19 @@ impl Globe {
             // Copy over each cell, one-by-one.
             for point_pair in &chunk_pair.point_pairs {
                 let source_cell = *source_chunk.cell(point_pair.source.into());
-                let target_cell = sink_chunk.cell_mut(point_pair.sink);
                 // Copy source -> target.
-                *target_cell = source_cell;
+                sink_chunk.set_cell(point_pair.sink, source_cell);
             }
 
             // Downstream chunk now has most recent changes from upstream.
             chunk_pair.last_upstream_edge_version_known_downstream =
                 source_chunk.owned_edge_version;
 
+            // Changes made upstream are now part of the sink chunk, too.
+            if source_chunk.is_modified {
+                sink_chunk.mark_as_modified();
+            }
+
             // If we got this far, then it means we needed to update something.
             sink_chunk_view_dirty = true;
         }
@@ -325,22 +391,129 @@ impl Globe {
         chunk
     }
 
+    /// Remove the chunk at the given chunk origin, first saving it
+    /// to storage if it has been modified.
+    ///
+    /// The chunk is removed even if saving it failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there was no chunk loaded at the given chunk origin.
+    pub fn unload_chunk(&mut self, chunk_origin: ChunkOrigin) -> io::Result<()> {
+        let result = self.save_chunk(chunk_origin);
+        self.remove_chunk(chunk_origin);
+        result
+    }
+
+    /// Save every loaded chunk that has been modified.
+    ///
+    /// Chunks remain loaded. Returns the first error encountered, if any,
+    /// but still attempts to save all other chunks.
+    pub fn save_all_chunks(&self) -> io::Result<()> {
+        let storage = match self.storage {
+            Some(ref storage) => storage,
+            None => return Ok(()),
+        };
+        let stored_chunks = self
+            .chunks
+            .values()
+            .filter_map(|chunk| self.stored_chunk_if_modified(chunk))
+            .collect();
+        storage.save_all(stored_chunks)
+    }
+
+    fn save_chunk(&self, chunk_origin: ChunkOrigin) -> io::Result<()> {
+        let storage = match self.storage {
+            Some(ref storage) => storage,
+            // Nowhere to save it; the chunk will be regenerated if needed again.
+            None => return Ok(()),
+        };
+        let chunk = self
+            .chunks
+            .get(&chunk_origin)
+            .expect("Attempted to save a chunk that was not loaded");
+        match self.stored_chunk_if_modified(chunk) {
+            Some(stored_chunk) => storage.save(stored_chunk),
+            None => Ok(()),
+        }
+    }
+
+    fn stored_chunk_if_modified(&self, chunk: &Chunk) -> Option<StoredChunk> {
+        if !chunk.is_modified {
+            // We can generate it again from scratch exactly as it was.
+            return None;
+        }
+
+        // Remember which version of each upstream neighbor's edge cells
+        // our copies came from, so we don't need to copy them all again
+        // after loading this chunk back in.
+        let upstream_edge_versions = chunk
+            .upstream_neighbors
+            .iter()
+            .filter_map(|upstream_neighbor| {
+                let chunk_pair_origins = ChunkPairOrigins {
+                    source: upstream_neighbor.origin,
+                    sink: chunk.origin,
+                };
+                self.chunk_pairs.get(&chunk_pair_origins).map(|chunk_pair| {
+                    (
+                        *upstream_neighbor.origin.pos(),
+                        chunk_pair.last_upstream_edge_version_known_downstream,
+                    )
+                })
+            })
+            .collect();
+        Some(StoredChunk::from_chunk(chunk, upstream_edge_versions))
+    }
+
     // TODO: consider moving `load_or_build_chunk`, `ensure_chunk_present`,
     // and `find_lowest_cell_containing` back out into a smarter component
     // so that `Globe` can be dumber, or move more of `Globe` down into a new
     // dumber component, e.g., `GlobeVoxMap`.
 
-    pub fn load_or_build_chunk(&mut self, origin: ChunkOrigin) {
-        let spec = self.spec();
-        let chunk_res = self.spec.chunk_resolution;
-
-        let mut cells: Vec<Cell> = Vec::with_capacity(
-            chunk_res[0] as usize * chunk_res[1] as usize * chunk_res[2] as usize,
+    /// Load the chunk at the given origin from storage if it has ever been saved,
+    /// or otherwise build it fresh using the globe's generator.
+    ///
+    /// The chunk is present afterwards even if this returns an error;
+    /// if its saved copy couldn't be read back, then it is built fresh
+    /// instead, and whatever changes it held are lost. See `ChunkStorage::load`
+    /// for what happens to the unreadable region file.
+    pub fn load_or_build_chunk(&mut self, origin: ChunkOrigin) -> io::Result<()> {
+        let built_chunk = chunk_builder::load_or_build_chunk(
+            &self.spec,
+            &*self.gen,
+            self.storage.as_deref(),
+            origin,
         );
+        self.add_built_chunk(built_chunk)
+    }
 
-        self.gen.populate_cells(origin, &mut cells);
+    // Add a chunk that was loaded or built, and pass on any error from
+    // trying to load it.
+    fn add_built_chunk(&mut self, built_chunk: BuiltChunk) -> io::Result<()> {
+        let spec = self.spec();
+        let origin = built_chunk.chunk.origin;
+        self.add_chunk(built_chunk.chunk);
+
+        // Restore what we knew about our upstream neighbors' edge versions.
+        for (upstream_origin, version) in built_chunk.upstream_edge_versions {
+            let chunk_pair_origins = ChunkPairOrigins {
+                source: ChunkOrigin::new(
+                    upstream_origin,
+                    spec.root_resolution,
+                    spec.chunk_resolution,
+                ),
+                sink: origin,
+            };
+            if let Some(chunk_pair) = self.chunk_pairs.get_mut(&chunk_pair_origins) {
+                chunk_pair.last_upstream_edge_version_known_downstream = version;
+            }
+        }
 
-        self.add_chunk(Chunk::new(origin, cells, spec.root_resolution, chunk_res));
+        match built_chunk.load_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 
     /// Ensures the specified chunk is present.
@@ -353,16 +526,104 @@ impl Globe {
     /// be loaded, and chunks added through this mechanism may well be unloaded
     /// immediately the next time this system is invoked, making this only suitable
     /// for immediate actions.
+    ///
+    /// As for `load_or_build_chunk`, the chunk is present even if this
+    /// returns an error.
     //
     // TODO: this definitely belongs elsewhere; somewhere that knows about
     // loading things from disk. The simpler version of just making sure the
     // voxmap buffer exists should exist on a dumber struct extracted from `Globe`.
-    pub fn ensure_chunk_present(&mut self, chunk_origin: ChunkOrigin) {
+    pub fn ensure_chunk_present(&mut self, chunk_origin: ChunkOrigin) -> io::Result<()> {
         if self.chunk_at(chunk_origin).is_some() {
-            return;
+            return Ok(());
+        }
+        // If it was being built in the background, we can't wait;
+        // whatever the chunk builder comes up with later will be ignored.
+        self.pending_chunks.remove(&chunk_origin);
+        let result = self.load_or_build_chunk(chunk_origin);
+        self.share_cells_with_neighbors(chunk_origin);
+        result
+    }
+
+    /// Ask for the specified chunk to be made present, without waiting for it.
+    ///
+    /// If the globe is building chunks in the background (see
+    /// `build_chunks_in_background`), then it will be loaded or built there,
+    /// and added by a later call to `add_built_chunks`; until then it is
+    /// listed as pending. Otherwise this is the same as `ensure_chunk_present`,
+    /// including what it returns.
+    pub fn request_chunk(&mut self, chunk_origin: ChunkOrigin) -> io::Result<()> {
+        if self.chunk_at(chunk_origin).is_some() || self.pending_chunks.contains(&chunk_origin) {
+            return Ok(());
+        }
+        let chunk_builder = match self.chunk_builder {
+            Some(ref chunk_builder) => chunk_builder,
+            None => return self.ensure_chunk_present(chunk_origin),
+        };
+        chunk_builder.request(chunk_origin, self.storage.clone());
+        self.pending_chunks.insert(chunk_origin);
+        Ok(())
+    }
+
+    /// Add any chunks that have finished loading or building in the background
+    /// since this was last called.
+    ///
+    /// As for `load_or_build_chunk`, chunks whose saved copies couldn't be read
+    /// back are built fresh instead. Returns the first such error, if any,
+    /// but still adds all the chunks.
+    pub fn add_built_chunks(&mut self) -> io::Result<()> {
+        let built_chunks: Vec<BuiltChunk> = match self.chunk_builder {
+            Some(ref chunk_builder) => chunk_builder.built_chunks(),
+            None => return Ok(()),
+        };
+        let mut result = Ok(());
+        for built_chunk in built_chunks {
+            let chunk_origin = built_chunk.chunk.origin;
+            // Ignore chunks that were built some other way in the meantime.
+            if !self.pending_chunks.remove(&chunk_origin) || self.chunk_at(chunk_origin).is_some() {
+                continue;
+            }
+            let chunk_result = self.add_built_chunk(built_chunk);
+            self.share_cells_with_neighbors(chunk_origin);
+            if result.is_ok() {
+                result = chunk_result;
+            }
+        }
+        result
+    }
+
+    /// Returns `true` if the chunk has been requested, but is still being built.
+    pub fn is_chunk_pending(&self, chunk_origin: ChunkOrigin) -> bool {
+        self.pending_chunks.contains(&chunk_origin)
+    }
+
+    pub fn pending_chunk_count(&self) -> usize {
+        self.pending_chunks.len()
+    }
+
+    /// Returns `true` if any chunk that something at `pos` could move into
+    /// in a single step is still being built.
+    ///
+    /// Systems that move things around should wait until this is `false`,
+    /// rather than deciding what to do based on chunks that aren't there yet.
+    pub fn is_waiting_for_chunks_near(&self, pos: Point3) -> bool {
+        if self.pending_chunks.is_empty() {
+            return false;
+        }
+        let pos_in_owning_root = PosInOwningRoot::new(pos, self.spec.root_resolution);
+        let chunk_origin = self.origin_of_chunk_owning(pos_in_owning_root);
+        match self.chunk_at(chunk_origin) {
+            Some(chunk) => chunk
+                .accessible_chunks
+                .iter()
+                .any(|accessible_chunk_origin| {
+                    self.pending_chunks.contains(accessible_chunk_origin)
+                }),
+            None => self.pending_chunks.contains(&chunk_origin),
         }
-        self.load_or_build_chunk(chunk_origin);
+    }
 
+    fn share_cells_with_neighbors(&mut self, chunk_origin: ChunkOrigin) {
         // Make sure this chunk has up-to-date data for edge cells that it doesn't own.
         self.pull_shared_cells_for_chunk(chunk_origin);
 
@@ -448,9 +709,28 @@ impl<'a> Globe {
             .chunks
             .get_mut(&chunk_origin)
             .expect("Uh oh, I don't know how to handle chunks that aren't loaded yet.");
+        // Assume the caller is going to change the cell.
+        chunk.mark_as_modified();
         chunk.cell_mut(pos.into())
     }
 
+    /// Replace the authoritative copy of the cell at `pos`.
+    ///
+    /// Prefer this over `authoritative_cell_mut`, which forces the
+    /// whole containing chunk's cells into their uncompressed form.
+    ///
+    /// Note that this does not update any copies of the cell
+    /// shared with neighboring chunks.
+    pub fn set_authoritative_cell(&mut self, pos: PosInOwningRoot, cell: Cell) {
+        let chunk_origin = self.origin_of_chunk_owning(pos);
+        let chunk = self
+            .chunks
+            .get_mut(&chunk_origin)
+            .expect("Uh oh, I don't know how to handle chunks that aren't loaded yet.");
+        chunk.mark_as_modified();
+        chunk.set_cell(pos.into(), cell);
+    }
+
     // TODO: proper error type
     // Panic message formerly "Uh oh, I don't know how to handle chunks that aren't loaded yet."
     pub fn maybe_non_authoritative_cell(&'a self, pos: Point3) -> Result<&'a Cell, ()> {
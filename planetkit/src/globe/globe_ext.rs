@@ -1,13 +1,17 @@
 // "Extension" functions for `Globe`. These do not use any private details of `Globe`,
 // and so they are exposed on its _inherent impl_ for convenience only.
 
+use std::collections::HashSet;
+
 use rand::Rng;
 
 use super::chunk::Material;
+use super::chunk_system::chunk_middle;
 use super::globe::Globe;
 use super::CursorMut;
 use crate::grid::random_column;
 use crate::grid::{GridCoord, Point2, Point3, PosInOwningRoot};
+use crate::types::*;
 
 impl Globe {
     /// Attempt to find dry land at surface level. See `find_dry_land`.
@@ -27,8 +31,9 @@ impl Globe {
 
     /// Attempt to find dry land near (above or below) the given `pos`.
     ///
-    /// A land cell position will only be returned if it has at least as many
-    /// contiguous cells of air directly above it as specified by `min_air_cells_above`.
+    /// A land cell position will only be returned if it is solid, and has at least as many
+    /// contiguous cells of air (or anything else that is neither solid nor liquid)
+    /// directly above it as specified by `min_air_cells_above`.
     ///
     /// Returns `None` if no such cell can be found within the maximum distance given, e.g.,
     /// if the highest land was below water, or our guess about where there should be land
@@ -63,10 +68,13 @@ impl Globe {
                 cursor.ensure_chunk_present();
                 {
                     // Non-lexical lifetimes SVP.
-                    let cell = cursor.cell().expect(
-                        "We just ensured the chunk is present, but apparently it's not. Kaboom!",
-                    );
-                    if cell.material != Material::Dirt {
+                    let material = cursor
+                        .cell()
+                        .expect(
+                            "We just ensured the chunk is present, but apparently it's not. Kaboom!",
+                        )
+                        .material;
+                    if !cursor.globe().materials().is_solid(material) {
                         continue;
                     }
                 }
@@ -76,10 +84,13 @@ impl Globe {
                     hopefully_air_pos.z += 1;
                     cursor.set_pos(hopefully_air_pos);
                     cursor.ensure_chunk_present();
-                    let cell = cursor.cell().expect(
-                        "We just ensured the chunk is present, but apparently it's not. Kaboom!",
-                    );
-                    if cell.material != Material::Air {
+                    let material = cursor
+                        .cell()
+                        .expect(
+                            "We just ensured the chunk is present, but apparently it's not. Kaboom!",
+                        )
+                        .material;
+                    if !cursor.globe().materials().is_gas(material) {
                         continue 'candidate_land;
                     }
                 }
@@ -168,4 +179,57 @@ impl Globe {
             cursor.set_pos(new_pos);
         }
     }
+
+    /// Find all cells in loaded chunks whose centers are within `radius`
+    /// of `center`, which is relative to the globe. This is useful for,
+    /// e.g., carving a crater out of the terrain.
+    ///
+    /// Cells in chunks that aren't loaded are silently left out, so if you
+    /// want to be sure of getting every cell then make sure the chunks
+    /// around `center` are loaded first.
+    pub fn loaded_cells_within(&self, center: Pt3, radius: f64) -> Vec<PosInOwningRoot> {
+        use super::globe::GlobeGuts;
+
+        let spec = self.spec();
+        let chunk_res = spec.chunk_resolution;
+        let mut cells: HashSet<PosInOwningRoot> = HashSet::new();
+        for chunk_origin in self.chunks().keys() {
+            // Skip whole chunks that are obviously too far away.
+            let origin = *chunk_origin.pos();
+            let middle = chunk_middle(&spec, *chunk_origin);
+            let far_corner = Point3::new(
+                origin.root,
+                origin.x + chunk_res[0],
+                origin.y + chunk_res[1],
+                origin.z + chunk_res[2],
+            );
+            let chunk_radius = (spec.cell_bottom_center(origin) - middle)
+                .norm()
+                .max((spec.cell_bottom_center(far_corner) - middle).norm());
+            if (middle - center).norm() > radius + chunk_radius {
+                continue;
+            }
+
+            // Include cells on the far edges; they might be owned by
+            // a chunk that isn't loaded, but we can still see them from here.
+            for z in origin.z..(origin.z + chunk_res[2]) {
+                for y in origin.y..=(origin.y + chunk_res[1]) {
+                    for x in origin.x..=(origin.x + chunk_res[0]) {
+                        let pos = Point3::new(origin.root, x, y, z);
+                        if (spec.cell_center_center(pos) - center).norm() > radius {
+                            continue;
+                        }
+                        let pos_in_owning_root = PosInOwningRoot::new(pos, spec.root_resolution);
+                        if self
+                            .chunk_at(self.origin_of_chunk_owning(pos_in_owning_root))
+                            .is_some()
+                        {
+                            cells.insert(pos_in_owning_root);
+                        }
+                    }
+                }
+            }
+        }
+        cells.into_iter().collect()
+    }
 }
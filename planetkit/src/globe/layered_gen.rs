@@ -0,0 +1,835 @@
+//! A world generator built up from a stack of independent passes.
+//!
+//! Each pass is responsible for one aspect of the world, e.g.,
+//! the shape of the continents, or where caves are, and has its own
+//! parameters. Default parameters for every pass are derived from the
+//! globe's `Spec`, but they can be adjusted before building the pass
+//! to produce quite different planets from the same seed.
+//!
+//! Everything here is deterministic given the parameters; nothing
+//! is random beyond what is derived from the seeds they contain.
+
+use noise;
+use noise::{MultiFractal, NoiseFn, Seedable};
+
+use super::chunk::{Cell, Material};
+use super::gen::{hash_cell, shade_cell, Gen, TunnelNoise};
+use super::icosahedron::VERTICES;
+use super::material::{MaterialDef, MaterialRegistry};
+use super::spec::Spec;
+use crate::globe::ChunkOrigin;
+use crate::grid::{Point2, Point3};
+use crate::types::*;
+
+/// Broad classification of a column of the globe,
+/// used by later passes to decide what to put there.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Biome {
+    Ocean,
+    Beach,
+    Grassland,
+    Desert,
+    Tundra,
+}
+
+/// Everything known about a single column of the globe
+/// once all passes have shaped it.
+#[derive(Clone, Copy, Debug)]
+pub struct ColumnInfo {
+    /// Distance from the center of the globe to the top of the land.
+    pub land_height: f64,
+    pub biome: Biome,
+}
+
+/// A single layer of world generation.
+///
+/// All passes first get a chance to shape each column, in order,
+/// and then all passes get a chance to paint each cell, in order.
+pub trait GenPass: Send + Sync {
+    /// Adjust the height or biome of a column.
+    /// `pos_on_unit_sphere` is the center of the column projected onto a unit sphere.
+    fn shape_column(&self, _pos_on_unit_sphere: Pt3, _column: &mut ColumnInfo) {}
+
+    /// Replace the material of a cell, given the column it is in.
+    /// `cell_pt3` is the center of the cell in globe space.
+    fn paint_cell(
+        &self,
+        _grid_point: Point3,
+        _cell_pt3: Pt3,
+        _column: &ColumnInfo,
+        _material: &mut Material,
+    ) {
+    }
+
+    /// Adjust how solid a cell is (see `Gen::density`) to agree with
+    /// `paint_cell`; any pass that carves out ground or fills in air
+    /// should push the density below or above zero to match.
+    ///
+    /// `density` starts out as how far the cell is below the land.
+    fn shape_density(
+        &self,
+        _grid_point: Point3,
+        _cell_pt3: Pt3,
+        _column: &ColumnInfo,
+        _density: &mut f64,
+    ) {
+    }
+}
+
+/// The materials that `LayeredGen` fills the world with.
+#[derive(Clone, Copy, Debug)]
+pub struct GenMaterials {
+    pub dirt: Material,
+    pub stone: Material,
+    pub sand: Material,
+    pub snow: Material,
+}
+
+impl GenMaterials {
+    /// Register the extra materials used by `LayeredGen`
+    /// that aren't built in, if they haven't been already.
+    pub fn register(registry: &mut MaterialRegistry) {
+        let defs = [
+            MaterialDef::solid("stone", [0.35, 0.35, 0.35], 3.0),
+            MaterialDef::solid("sand", [0.75, 0.7, 0.4], 0.5),
+            MaterialDef::solid("snow", [0.9, 0.9, 0.95], 0.3),
+        ];
+        for def in defs.iter() {
+            if registry.find(&def.name).is_none() {
+                registry.register(def.clone());
+            }
+        }
+    }
+
+    /// Look up the materials to use in the given registry.
+    /// Any that haven't been registered (see `GenMaterials::register`)
+    /// are replaced by dirt.
+    pub fn from_registry(registry: &MaterialRegistry) -> GenMaterials {
+        let find = |name| registry.find(name).unwrap_or(Material::DIRT);
+        GenMaterials {
+            dirt: Material::DIRT,
+            stone: find("stone"),
+            sand: find("sand"),
+            snow: find("snow"),
+        }
+    }
+}
+
+// Depth of the crust; most vertical distances are
+// derived from this so that they scale with the globe.
+fn crust_depth(spec: &Spec) -> f64 {
+    spec.ocean_radius - spec.floor_radius
+}
+
+// Number of octaves needed to get from features of size
+// `wavelength` down to features of size `smallest_wavelength`.
+fn octaves_between(wavelength: f64, smallest_wavelength: f64) -> usize {
+    let octaves = (wavelength / smallest_wavelength).log2().ceil() as isize + 1;
+    octaves.clamp(1, 8) as usize
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ContinentsParams {
+    pub seed: u32,
+    /// Approximate width of a continent.
+    pub wavelength: f64,
+    pub octaves: usize,
+    /// Maximum distance of land above or below sea level.
+    pub amplitude: f64,
+}
+
+impl ContinentsParams {
+    pub fn from_spec(spec: &Spec) -> ContinentsParams {
+        let wavelength = (spec.ocean_radius * 0.5).min(crust_depth(spec) * 1000.0);
+        ContinentsParams {
+            // Truncate seed to make it fit what `noise` expects.
+            seed: spec.seed as u32,
+            wavelength,
+            octaves: octaves_between(wavelength, crust_depth(spec) * 10.0),
+            // Keep the deepest ocean floor well clear of bedrock.
+            amplitude: crust_depth(spec) * 0.35,
+        }
+    }
+}
+
+/// Raises and lowers the land around sea level
+/// to make continents and oceans.
+pub struct ContinentsPass {
+    spec: Spec,
+    params: ContinentsParams,
+    noise: noise::Fbm,
+}
+
+impl ContinentsPass {
+    pub fn new(spec: Spec, params: ContinentsParams) -> ContinentsPass {
+        let noise = noise::Fbm::new()
+            .set_octaves(params.octaves)
+            .set_frequency(1.0 / params.wavelength)
+            .set_seed(params.seed);
+        ContinentsPass {
+            spec,
+            params,
+            noise,
+        }
+    }
+}
+
+impl GenPass for ContinentsPass {
+    fn shape_column(&self, pos_on_unit_sphere: Pt3, column: &mut ColumnInfo) {
+        // Sample on a sea-level sphere so that wavelengths
+        // are in the same units as everything else.
+        let pt3 = pos_on_unit_sphere * self.spec.ocean_radius;
+        let noise = self.noise.get([pt3.x, pt3.y, pt3.z]).clamp(-1.0, 1.0);
+        column.land_height += noise * self.params.amplitude;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MountainsParams {
+    pub seed: u32,
+    /// Approximate distance between mountain ridges.
+    pub wavelength: f64,
+    pub octaves: usize,
+    /// Maximum height of mountains above the land around them.
+    pub amplitude: f64,
+    /// How far above sea level the land must be before
+    /// mountains reach their full height.
+    pub foothill_height: f64,
+}
+
+impl MountainsParams {
+    pub fn from_spec(spec: &Spec) -> MountainsParams {
+        let wavelength = crust_depth(spec) * 12.0;
+        MountainsParams {
+            seed: (spec.seed as u32).wrapping_add(1),
+            wavelength,
+            octaves: octaves_between(wavelength, spec.block_height * 4.0),
+            amplitude: crust_depth(spec) * 0.25,
+            foothill_height: crust_depth(spec) * 0.05,
+        }
+    }
+}
+
+/// Adds ridged mountain ranges on land, fading
+/// them out towards the coast.
+pub struct MountainsPass {
+    spec: Spec,
+    params: MountainsParams,
+    noise: noise::RidgedMulti,
+}
+
+impl MountainsPass {
+    pub fn new(spec: Spec, params: MountainsParams) -> MountainsPass {
+        let noise = noise::RidgedMulti::new()
+            .set_octaves(params.octaves)
+            .set_frequency(1.0 / params.wavelength)
+            .set_seed(params.seed);
+        MountainsPass {
+            spec,
+            params,
+            noise,
+        }
+    }
+}
+
+impl GenPass for MountainsPass {
+    fn shape_column(&self, pos_on_unit_sphere: Pt3, column: &mut ColumnInfo) {
+        let height_above_sea = column.land_height - self.spec.ocean_radius;
+        if height_above_sea <= 0.0 {
+            return;
+        }
+        let foothills = (height_above_sea / self.params.foothill_height).min(1.0);
+        let pt3 = pos_on_unit_sphere * self.spec.ocean_radius;
+        // Ridged noise is in roughly [-1, 1]; only keep the peaks.
+        let ridges = self.noise.get([pt3.x, pt3.y, pt3.z]).clamp(0.0, 1.0);
+        column.land_height += ridges * foothills * self.params.amplitude;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BiomesParams {
+    pub seed: u32,
+    pub moisture_wavelength: f64,
+    /// Direction from the center of the globe to the north pole.
+    pub north: Vec3,
+    /// Land less than this far above sea level is beach.
+    pub beach_height: f64,
+    /// Land more than this far above sea level is always tundra.
+    pub snow_line: f64,
+    /// Land further than this from the equator, in radians, is always tundra.
+    pub polar_latitude: f64,
+    /// Land closer than this to the equator, in radians, may be desert.
+    pub tropical_latitude: f64,
+    /// Moisture (in [-1, 1]) below which hot land becomes desert.
+    pub desert_moisture: f64,
+}
+
+impl BiomesParams {
+    pub fn from_spec(spec: &Spec) -> BiomesParams {
+        use std::f64::consts::PI;
+
+        // The first vertex of the icosahedron is the north pole.
+        let north = Vec3::new(VERTICES[0][0], VERTICES[0][1], VERTICES[0][2]).normalize();
+        BiomesParams {
+            seed: (spec.seed as u32).wrapping_add(2),
+            moisture_wavelength: (spec.ocean_radius * 0.25).min(crust_depth(spec) * 500.0),
+            north,
+            beach_height: spec.block_height * 2.0,
+            snow_line: crust_depth(spec) * 0.2,
+            polar_latitude: PI / 3.0,
+            tropical_latitude: PI / 6.0,
+            desert_moisture: -0.2,
+        }
+    }
+}
+
+/// Decides the biome of each column from its
+/// height, latitude, and how wet it is.
+pub struct BiomesPass {
+    spec: Spec,
+    params: BiomesParams,
+    moisture_noise: noise::Fbm,
+}
+
+impl BiomesPass {
+    pub fn new(spec: Spec, params: BiomesParams) -> BiomesPass {
+        let moisture_noise = noise::Fbm::new()
+            .set_octaves(4)
+            .set_frequency(1.0 / params.moisture_wavelength)
+            .set_seed(params.seed);
+        BiomesPass {
+            spec,
+            params,
+            moisture_noise,
+        }
+    }
+}
+
+impl GenPass for BiomesPass {
+    fn shape_column(&self, pos_on_unit_sphere: Pt3, column: &mut ColumnInfo) {
+        let height_above_sea = column.land_height - self.spec.ocean_radius;
+        let latitude = pos_on_unit_sphere
+            .coords
+            .dot(&self.params.north)
+            .clamp(-1.0, 1.0)
+            .asin()
+            .abs();
+        let pt3 = pos_on_unit_sphere * self.spec.ocean_radius;
+        let moisture = self.moisture_noise.get([pt3.x, pt3.y, pt3.z]);
+        column.biome = if height_above_sea < 0.0 {
+            Biome::Ocean
+        } else if height_above_sea < self.params.beach_height {
+            Biome::Beach
+        } else if height_above_sea > self.params.snow_line || latitude > self.params.polar_latitude
+        {
+            Biome::Tundra
+        } else if latitude < self.params.tropical_latitude && moisture < self.params.desert_moisture
+        {
+            Biome::Desert
+        } else {
+            Biome::Grassland
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StrataParams {
+    pub seed: u32,
+    /// Typical depth of the layer of dirt, sand,
+    /// or snow above the bedrock.
+    pub topsoil_depth: f64,
+    /// How much the topsoil depth varies from place to place.
+    pub topsoil_variation: f64,
+    pub variation_wavelength: f64,
+}
+
+impl StrataParams {
+    pub fn from_spec(spec: &Spec) -> StrataParams {
+        StrataParams {
+            seed: (spec.seed as u32).wrapping_add(3),
+            topsoil_depth: spec.block_height * 4.0,
+            topsoil_variation: spec.block_height * 2.0,
+            variation_wavelength: spec.block_height * 32.0,
+        }
+    }
+}
+
+/// Splits the land into a layer of topsoil
+/// appropriate to the biome above a body of stone.
+pub struct StrataPass {
+    params: StrataParams,
+    materials: GenMaterials,
+    noise: noise::Perlin,
+}
+
+impl StrataPass {
+    pub fn new(params: StrataParams, materials: GenMaterials) -> StrataPass {
+        let noise = noise::Perlin::new().set_seed(params.seed);
+        StrataPass {
+            params,
+            materials,
+            noise,
+        }
+    }
+}
+
+impl GenPass for StrataPass {
+    fn paint_cell(
+        &self,
+        _grid_point: Point3,
+        cell_pt3: Pt3,
+        column: &ColumnInfo,
+        material: &mut Material,
+    ) {
+        if *material != self.materials.dirt {
+            return;
+        }
+        let depth = column.land_height - cell_pt3.coords.norm();
+        let scaled_pt3 = cell_pt3 / self.params.variation_wavelength;
+        let topsoil_depth = self.params.topsoil_depth
+            + self.noise.get([scaled_pt3.x, scaled_pt3.y, scaled_pt3.z])
+                * self.params.topsoil_variation;
+        *material = if depth > topsoil_depth {
+            self.materials.stone
+        } else {
+            match column.biome {
+                Biome::Ocean | Biome::Beach | Biome::Desert => self.materials.sand,
+                Biome::Tundra => self.materials.snow,
+                Biome::Grassland => self.materials.dirt,
+            }
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CavesParams {
+    pub seed: u32,
+    /// Approximate distance between cave tunnels.
+    pub wavelength: f64,
+    /// Larger values make wider tunnels; in [0, 1].
+    pub width: f64,
+    /// Caves never come closer to the surface than this,
+    /// so that they don't flood or leave holes in the ground.
+    pub min_depth: f64,
+}
+
+impl CavesParams {
+    pub fn from_spec(spec: &Spec) -> CavesParams {
+        CavesParams {
+            seed: (spec.seed as u32).wrapping_add(4),
+            wavelength: spec.block_height * 24.0,
+            width: 0.12,
+            min_depth: spec.block_height * 6.0,
+        }
+    }
+}
+
+/// Carves tunnels through the ground below the surface.
+pub struct CavesPass {
+    params: CavesParams,
+    tunnels: TunnelNoise,
+}
+
+impl CavesPass {
+    pub fn new(params: CavesParams) -> CavesPass {
+        CavesPass {
+            params,
+            tunnels: TunnelNoise::new(params.seed, params.wavelength, params.width),
+        }
+    }
+
+    // How far the cell is from the nearest tunnel wall (see `TunnelNoise`),
+    // or `None` if it's too close to the surface to be part of a cave.
+    fn distance_outside_tunnel(&self, cell_pt3: Pt3, column: &ColumnInfo) -> Option<f64> {
+        let depth = column.land_height - cell_pt3.coords.norm();
+        if depth < self.params.min_depth {
+            return None;
+        }
+        Some(self.tunnels.distance_outside(cell_pt3))
+    }
+}
+
+impl GenPass for CavesPass {
+    fn paint_cell(
+        &self,
+        _grid_point: Point3,
+        cell_pt3: Pt3,
+        column: &ColumnInfo,
+        material: &mut Material,
+    ) {
+        let in_tunnel = self
+            .distance_outside_tunnel(cell_pt3, column)
+            .is_some_and(|distance| distance <= 0.0);
+        if in_tunnel {
+            *material = Material::AIR;
+        }
+    }
+
+    fn shape_density(
+        &self,
+        _grid_point: Point3,
+        cell_pt3: Pt3,
+        column: &ColumnInfo,
+        density: &mut f64,
+    ) {
+        if let Some(distance) = self.distance_outside_tunnel(cell_pt3, column) {
+            *density = density.min(distance);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DecorationParams {
+    pub seed: u64,
+    /// Chance of any given grassland or tundra
+    /// surface cell having a boulder on it.
+    pub boulder_chance: f64,
+}
+
+impl DecorationParams {
+    pub fn from_spec(spec: &Spec) -> DecorationParams {
+        DecorationParams {
+            seed: spec.seed.wrapping_add(5),
+            boulder_chance: 0.01,
+        }
+    }
+}
+
+/// Scatters small features over the surface.
+///
+/// Currently that's just boulders.
+pub struct DecorationPass {
+    spec: Spec,
+    params: DecorationParams,
+    materials: GenMaterials,
+}
+
+impl DecorationPass {
+    pub fn new(spec: Spec, params: DecorationParams, materials: GenMaterials) -> DecorationPass {
+        DecorationPass {
+            spec,
+            params,
+            materials,
+        }
+    }
+
+    // Whether to put a boulder in the given cell. Only the first cell above
+    // the land is ever decorated, and there's never water there, because
+    // grassland and tundra are above sea level.
+    fn has_boulder(&self, grid_point: Point3, cell_pt3: Pt3, column: &ColumnInfo) -> bool {
+        if column.biome != Biome::Grassland && column.biome != Biome::Tundra {
+            return false;
+        }
+        let height_above_land = cell_pt3.coords.norm() - column.land_height;
+        if height_above_land < 0.0 || height_above_land >= self.spec.block_height {
+            return false;
+        }
+        let roll = hash_cell(self.params.seed, grid_point) as f64 / u64::MAX as f64;
+        roll < self.params.boulder_chance
+    }
+}
+
+impl GenPass for DecorationPass {
+    fn paint_cell(
+        &self,
+        grid_point: Point3,
+        cell_pt3: Pt3,
+        column: &ColumnInfo,
+        material: &mut Material,
+    ) {
+        if *material == Material::AIR && self.has_boulder(grid_point, cell_pt3, column) {
+            *material = self.materials.stone;
+        }
+    }
+
+    fn shape_density(
+        &self,
+        grid_point: Point3,
+        cell_pt3: Pt3,
+        column: &ColumnInfo,
+        density: &mut f64,
+    ) {
+        if *density <= 0.0 && self.has_boulder(grid_point, cell_pt3, column) {
+            // Solid up to the top of the cell.
+            let height_above_land = cell_pt3.coords.norm() - column.land_height;
+            *density = self.spec.block_height - height_above_land;
+        }
+    }
+}
+
+/// World generator made of a stack of `GenPass`es.
+pub struct LayeredGen {
+    spec: Spec,
+    passes: Vec<Box<dyn GenPass>>,
+}
+
+impl LayeredGen {
+    /// Create a generator with the default stack of passes:
+    /// continents, mountains, biomes, strata, caves, and decoration,
+    /// all with parameters derived from `spec`.
+    ///
+    /// To use anything other than dirt, the registry needs to contain
+    /// the materials registered by `GenMaterials::register`.
+    pub fn new(spec: Spec, materials: &MaterialRegistry) -> LayeredGen {
+        LayeredGen::with_passes(spec, LayeredGen::default_passes(spec, materials))
+    }
+
+    /// The stack of passes used by `new`, e.g., as a starting point
+    /// for replacing or adding a pass before calling `with_passes`.
+    pub fn default_passes(spec: Spec, materials: &MaterialRegistry) -> Vec<Box<dyn GenPass>> {
+        let materials = GenMaterials::from_registry(materials);
+        vec![
+            Box::new(ContinentsPass::new(
+                spec,
+                ContinentsParams::from_spec(&spec),
+            )),
+            Box::new(MountainsPass::new(spec, MountainsParams::from_spec(&spec))),
+            Box::new(BiomesPass::new(spec, BiomesParams::from_spec(&spec))),
+            Box::new(StrataPass::new(StrataParams::from_spec(&spec), materials)),
+            Box::new(CavesPass::new(CavesParams::from_spec(&spec))),
+            Box::new(DecorationPass::new(
+                spec,
+                DecorationParams::from_spec(&spec),
+                materials,
+            )),
+        ]
+    }
+
+    /// Create a generator from a custom stack of passes,
+    /// which will be applied in the order given.
+    pub fn with_passes(spec: Spec, passes: Vec<Box<dyn GenPass>>) -> LayeredGen {
+        assert!(spec.is_valid(), "Invalid globe spec!");
+        LayeredGen { spec, passes }
+    }
+
+    pub fn column_info(&self, column: Point2) -> ColumnInfo {
+        let pos_on_unit_sphere = self.spec.cell_center_on_unit_sphere(column);
+        let mut column_info = ColumnInfo {
+            land_height: self.spec.ocean_radius,
+            biome: Biome::Grassland,
+        };
+        for pass in &self.passes {
+            pass.shape_column(pos_on_unit_sphere, &mut column_info);
+        }
+        column_info
+    }
+
+    // See `Gen::density`.
+    fn density_in_column(&self, grid_point: Point3, column: &ColumnInfo) -> f64 {
+        let cell_pt3 = self.spec.cell_center_center(grid_point);
+        let mut density = column.land_height - cell_pt3.coords.norm();
+        for pass in &self.passes {
+            pass.shape_density(grid_point, cell_pt3, column, &mut density);
+        }
+        density
+    }
+
+    fn cell_in_column(&self, grid_point: Point3, column: &ColumnInfo) -> Cell {
+        let cell_pt3 = self.spec.cell_center_center(grid_point);
+        let cell_height = cell_pt3.coords.norm();
+        let mut material = if cell_height < column.land_height {
+            Material::DIRT
+        } else if cell_height < self.spec.ocean_radius {
+            Material::WATER
+        } else {
+            Material::AIR
+        };
+        for pass in &self.passes {
+            pass.paint_cell(grid_point, cell_pt3, column, &mut material);
+        }
+        let mut cell = Cell {
+            material,
+            shade: 1.0,
+        };
+        shade_cell(self.spec.seed, grid_point, &mut cell);
+        cell
+    }
+}
+
+impl Gen for LayeredGen {
+    fn land_height(&self, column: Point2) -> f64 {
+        self.column_info(column).land_height
+    }
+
+    fn density(&self, grid_point: Point3) -> f64 {
+        let column = self.column_info(grid_point.rxy);
+        self.density_in_column(grid_point, &column)
+    }
+
+    fn spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    fn cell_at(&self, grid_point: Point3) -> Cell {
+        let column = self.column_info(grid_point.rxy);
+        self.cell_in_column(grid_point, &column)
+    }
+
+    fn populate_cells(&self, origin: ChunkOrigin, cells: &mut Vec<Cell>) {
+        // We should be passed an empty vector to populate.
+        assert!(cells.is_empty());
+
+        let chunk_res = &self.spec.chunk_resolution;
+        let origin = origin.pos();
+
+        // See `SimpleGen::populate_cells` for which cells are included.
+        let end_x = origin.x + chunk_res[0];
+        let end_y = origin.y + chunk_res[1];
+        let end_z = origin.z + chunk_res[2] - 1;
+
+        // Shaping columns is much more expensive than painting cells,
+        // so only do it once for each column.
+        let mut columns = Vec::new();
+        for cell_y in origin.y..=end_y {
+            for cell_x in origin.x..=end_x {
+                columns.push(self.column_info(Point2::new(origin.root, cell_x, cell_y)));
+            }
+        }
+
+        for cell_z in origin.z..=end_z {
+            let mut column_i = 0;
+            for cell_y in origin.y..=end_y {
+                for cell_x in origin.x..=end_x {
+                    let grid_point = Point3::new(origin.root, cell_x, cell_y, cell_z);
+                    cells.push(self.cell_in_column(grid_point, &columns[column_i]));
+                    column_i += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::globe::{DensityParams, Globe, SimpleGen};
+    use crate::grid::Root;
+
+    fn example_gen(spec: Spec) -> LayeredGen {
+        let mut materials = MaterialRegistry::new();
+        GenMaterials::register(&mut materials);
+        LayeredGen::new(spec, &materials)
+    }
+
+    fn materials_in_chunk(gen: &LayeredGen, origin: ChunkOrigin) -> Vec<Material> {
+        let mut cells = Vec::new();
+        gen.populate_cells(origin, &mut cells);
+        cells.iter().map(|cell| cell.material).collect()
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let spec = Spec::new_earth_scale_example();
+        let origin = ChunkOrigin::new(
+            Point3::new(Root::new(1), 1024, 2048, 80),
+            spec.root_resolution,
+            spec.chunk_resolution,
+        );
+        let first = materials_in_chunk(&example_gen(spec), origin);
+        let second = materials_in_chunk(&example_gen(spec), origin);
+        assert_eq!(first, second);
+
+        // Generating a whole chunk should agree with generating one cell at a time.
+        let gen = example_gen(spec);
+        let grid_point = Point3::new(Root::new(1), 1024 + 3, 2048 + 5, 80 + 2);
+        let chunk_res = spec.chunk_resolution;
+        let i = (2 * (chunk_res[1] + 1) * (chunk_res[0] + 1) + 5 * (chunk_res[0] + 1) + 3) as usize;
+        assert_eq!(first[i], gen.cell_at(grid_point).material);
+    }
+
+    #[test]
+    fn land_stays_above_bedrock() {
+        let spec = Spec::new_earth_scale_example();
+        let gen = example_gen(spec);
+        for i in 0..1000 {
+            let column = Point2::new(
+                Root::new((i % 5) as u8),
+                i * 7919 % spec.root_resolution[0],
+                i * 104_729 % spec.root_resolution[1],
+            );
+            let land_height = gen.land_height(column);
+            assert!(land_height > spec.floor_radius);
+            assert_eq!(land_height, gen.column_info(column).land_height);
+        }
+    }
+
+    #[test]
+    fn params_change_the_world() {
+        let spec = Spec::new_earth_scale_example();
+        let default_gen = example_gen(spec);
+
+        // Same passes, but with taller continents.
+        let mut materials = MaterialRegistry::new();
+        GenMaterials::register(&mut materials);
+        let mut passes = LayeredGen::default_passes(spec, &materials);
+        let mut params = ContinentsParams::from_spec(&spec);
+        params.amplitude *= 1.5;
+        passes[0] = Box::new(ContinentsPass::new(spec, params));
+        let other_gen = LayeredGen::with_passes(spec, passes);
+
+        let differs = (0..100).any(|i| {
+            let column = Point2::new(
+                Root::new((i % 5) as u8),
+                i * 7919 % spec.root_resolution[0],
+                i * 104_729 % spec.root_resolution[1],
+            );
+            default_gen.land_height(column) != other_gen.land_height(column)
+        });
+        assert!(differs);
+    }
+
+    // Check that `density` says a cell is solid exactly when `cell_at` fills it
+    // with something solid, in cells near the surface, and return how many
+    // cells of cave were found below the land.
+    fn check_density_agrees_with_cells(gen: &dyn Gen, materials: &MaterialRegistry) -> usize {
+        let spec = *gen.spec();
+        let mut cave_cells = 0;
+        for i in 0..200 {
+            let column = Point2::new(
+                Root::new((i % 5) as u8),
+                i * 7919 % spec.root_resolution[0],
+                i * 104_729 % spec.root_resolution[1],
+            );
+            let land_height = gen.land_height(column);
+            let surface_z = ((land_height - spec.floor_radius) / spec.block_height) as i64;
+            for z in (surface_z - 30).max(0)..=surface_z + 2 {
+                let grid_point = column.with_z(z);
+                let is_solid = materials.is_solid(gen.cell_at(grid_point).material);
+                assert_eq!(gen.density(grid_point) > 0.0, is_solid, "{:?}", grid_point);
+                if !is_solid && z < surface_z - 10 {
+                    cave_cells += 1;
+                }
+            }
+        }
+        cave_cells
+    }
+
+    #[test]
+    fn density_agrees_with_cells() {
+        let spec = Spec::new_earth_scale_example();
+        let mut materials = MaterialRegistry::new();
+        GenMaterials::register(&mut materials);
+        let gen = LayeredGen::new(spec, &materials);
+        assert!(check_density_agrees_with_cells(&gen, &materials) > 0);
+
+        let gen = SimpleGen::new_with_density(spec, DensityParams::from_spec(&spec));
+        assert!(check_density_agrees_with_cells(&gen, &materials) > 0);
+    }
+
+    #[test]
+    fn can_find_spawn_points() {
+        use rand::SeedableRng;
+        use rand_xoshiro::Xoshiro256StarStar;
+
+        let spec = Spec::new_earth_scale_example();
+        let mut materials = MaterialRegistry::new();
+        GenMaterials::register(&mut materials);
+        let gen = LayeredGen::new(spec, &materials);
+        let mut globe = Globe::new_with_gen(spec, Arc::new(materials), Box::new(gen));
+        let mut rng = Xoshiro256StarStar::seed_from_u64(spec.seed);
+        let pos = globe.air_above_random_surface_dry_land(&mut rng, 2, 5, 100);
+        assert!(pos.is_some());
+    }
+}
@@ -0,0 +1,196 @@
+//! Low-detail geometry for parts of a globe too far away to
+//! draw from chunks; see `LodSystem`.
+
+use specs;
+
+use super::spec::Spec;
+use crate::grid::{GridCoord, Point2, Root, ROOTS};
+use crate::types::*;
+
+/// How many times wider than itself a patch must be from all viewers
+/// before we stop splitting it into smaller patches.
+const LOD_SPLIT_FACTOR: Real = 2.0;
+
+/// A square region of a root quad that can be drawn as a single
+/// piece of low-detail geometry.
+///
+/// Each root quad is covered by two patches at the coarsest level,
+/// and each patch can be split into four smaller ones, down to
+/// patches exactly the size of a chunk.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LodPatch {
+    pub root: Root,
+    pub x: GridCoord,
+    pub y: GridCoord,
+    pub size: GridCoord,
+}
+
+impl LodPatch {
+    pub fn new(root: Root, x: GridCoord, y: GridCoord, size: GridCoord) -> LodPatch {
+        LodPatch { root, x, y, size }
+    }
+
+    /// The coarsest patches, which between them cover the whole globe.
+    pub fn coarsest(spec: &Spec) -> Vec<LodPatch> {
+        let size = spec.root_resolution[0];
+        ROOTS
+            .iter()
+            .flat_map(|root| {
+                vec![
+                    LodPatch::new(*root, 0, 0, size),
+                    LodPatch::new(*root, 0, size, size),
+                ]
+            })
+            .collect()
+    }
+
+    /// The four patches covering the same area as this one
+    /// at the next level of detail.
+    pub fn children(&self) -> [LodPatch; 4] {
+        let half = self.size / 2;
+        [
+            LodPatch::new(self.root, self.x, self.y, half),
+            LodPatch::new(self.root, self.x + half, self.y, half),
+            LodPatch::new(self.root, self.x, self.y + half, half),
+            LodPatch::new(self.root, self.x + half, self.y + half, half),
+        ]
+    }
+
+    /// Whether this patch is as small as patches get; i.e. the size of a chunk.
+    pub fn is_finest(&self, spec: &Spec) -> bool {
+        self.size / 2 < spec.chunk_resolution[0] || self.size % 2 != 0
+    }
+
+    pub fn overlaps(&self, other: &LodPatch) -> bool {
+        self.root == other.root
+            && self.x < other.x + other.size
+            && other.x < self.x + self.size
+            && self.y < other.y + other.size
+            && other.y < self.y + self.size
+    }
+
+    pub fn origin(&self) -> Point2 {
+        Point2::new(self.root, self.x, self.y)
+    }
+
+    pub fn center(&self) -> Point2 {
+        Point2::new(self.root, self.x + self.size / 2, self.y + self.size / 2)
+    }
+
+    /// Approximate distance from one corner of the patch to the
+    /// opposite corner, at sea level.
+    pub fn approx_width(&self, spec: &Spec) -> Real {
+        let a = spec.cell_center_on_unit_sphere(self.origin());
+        let b = spec.cell_center_on_unit_sphere(Point2::new(
+            self.root,
+            self.x + self.size,
+            self.y + self.size,
+        ));
+        (b - a).norm() * spec.ocean_radius
+    }
+}
+
+/// Pick the set of patches that should be drawn to cover
+/// the whole globe, with more detail near each viewer.
+///
+/// Viewers are given as positions relative to the globe,
+/// along with their view distance. Patches within a viewer's view
+/// distance are always as fine as they get, so that they line up
+/// with the chunks that might be drawn instead of them.
+///
+/// If there are no viewers, then no patches are needed at all.
+pub fn lod_patches_for_viewers(spec: &Spec, viewers: &[(Pt3, Real)]) -> Vec<LodPatch> {
+    let mut patches = Vec::new();
+    if viewers.is_empty() {
+        return patches;
+    }
+    let mut patches_to_visit = LodPatch::coarsest(spec);
+    while let Some(patch) = patches_to_visit.pop() {
+        let center = spec.cell_center_on_unit_sphere(patch.center()) * spec.ocean_radius;
+        let width = patch.approx_width(spec);
+        let should_split = !patch.is_finest(spec)
+            && viewers.iter().any(|(position, view_distance)| {
+                crate::na::distance(position, &center) < view_distance + width * LOD_SPLIT_FACTOR
+            });
+        if should_split {
+            patches_to_visit.extend_from_slice(&patch.children());
+        } else {
+            patches.push(patch);
+        }
+    }
+    patches
+}
+
+/// Low-detail geometry for part of a globe.
+///
+/// Entities with one of these also have a `Visual` and a `Spatial`,
+/// just like for a `ChunkView`.
+pub struct LodPatchView {
+    pub globe_entity: specs::Entity,
+    pub patch: LodPatch,
+    /// Lowest and highest land in the patch;
+    /// not known until we've made its geometry.
+    pub land_height_range: Option<(Real, Real)>,
+}
+
+impl LodPatchView {
+    pub fn new(globe_entity: specs::Entity, patch: LodPatch) -> LodPatchView {
+        LodPatchView {
+            globe_entity,
+            patch,
+            land_height_range: None,
+        }
+    }
+}
+
+impl specs::Component for LodPatchView {
+    type Storage = specs::HashMapStorage<LodPatchView>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_cover_globe_and_get_finer_near_viewers() {
+        // Big enough that far away patches don't need splitting much.
+        let spec = Spec::new(14, 400.0, 533.0, 0.65, [512, 1024], [16, 16, 4]);
+        assert!(lod_patches_for_viewers(&spec, &[]).is_empty());
+
+        let viewer_column = Point2::new(Root::new(2), 40, 90);
+        let viewer_position = spec.cell_center_on_unit_sphere(viewer_column) * spec.ocean_radius;
+        let patches = lod_patches_for_viewers(&spec, &[(viewer_position, 5.0)]);
+
+        // Every cell should be covered exactly once.
+        let covered_area: GridCoord = patches.iter().map(|patch| patch.size * patch.size).sum();
+        assert_eq!(
+            covered_area,
+            spec.root_resolution[0] * spec.root_resolution[1] * ROOTS.len() as GridCoord
+        );
+        for (i, a) in patches.iter().enumerate() {
+            for b in &patches[i + 1..] {
+                assert!(!a.overlaps(b));
+            }
+        }
+
+        // The patch under the viewer should be as detailed as they get,
+        // and those on the other side of the globe shouldn't.
+        let under_viewer = patches
+            .iter()
+            .find(|patch| {
+                patch.overlaps(&LodPatch::new(
+                    viewer_column.root,
+                    viewer_column.x,
+                    viewer_column.y,
+                    1,
+                ))
+            })
+            .expect("Viewer should be over some patch");
+        assert_eq!(under_viewer.size, spec.chunk_resolution[0]);
+        let chunk_columns = spec.chunks_per_root_side()[0] * spec.chunks_per_root_side()[1] * 5;
+        assert!(patches.len() < chunk_columns as usize / 10);
+        assert!(patches
+            .iter()
+            .any(|patch| patch.size >= spec.chunk_resolution[0] * 8));
+    }
+}
@@ -0,0 +1,443 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::na;
+use slog::Logger;
+use specs;
+use specs::Entities;
+use specs::{Read, ReadStorage, WriteStorage};
+
+use super::lod::{lod_patches_for_viewers, LodPatch, LodPatchView};
+use super::viewer::viewers_of_globe;
+use crate::globe::{ChunkOrigin, Globe, View, Viewer};
+use crate::grid::Point3;
+use crate::render::{ProtoMesh, Vertex, Visual};
+use crate::types::*;
+use crate::Spatial;
+
+/// Draws low-detail terrain for whole globes, so that there's
+/// something to see beyond the chunks near each `Viewer`.
+///
+/// Each globe is covered by `LodPatch`es, which get smaller
+/// closer to each `Viewer`. Patches are drawn using only the globe's
+/// `Gen::land_height`, so they don't need any chunks to be loaded.
+/// Where chunk views have been made for all the chunks a patch covers,
+/// that patch is hidden so the full-resolution chunk geometry shows instead.
+/// There's no blending between the two; a patch is either drawn or it isn't,
+/// so you may see terrain pop as chunk views come and go.
+///
+/// Like `ChunkViewSystem`, this does nothing if there are no `Viewer`s.
+pub struct LodSystem {
+    log: Logger,
+    seconds_between_geometry_creation: TimeDelta,
+    seconds_since_last_geometry_creation: TimeDelta,
+}
+
+impl LodSystem {
+    pub fn new(parent_log: &Logger, seconds_between_geometry_creation: TimeDelta) -> LodSystem {
+        LodSystem {
+            log: parent_log.new(o!()),
+            seconds_between_geometry_creation,
+            seconds_since_last_geometry_creation: 0.0,
+        }
+    }
+
+    // Get rid of views for patches we don't want any more.
+    //
+    // A patch that's no longer wanted is kept around until all the patches
+    // replacing it have geometry, so that we don't leave holes in the globe
+    // while they're being built.
+    fn remove_unwanted_patch_views<'a>(
+        &mut self,
+        entities: &Entities<'a>,
+        globe_entity: specs::Entity,
+        wanted_patches: &HashSet<LodPatch>,
+        lod_patch_views: &mut WriteStorage<'a, LodPatchView>,
+        visuals: &mut WriteStorage<'a, Visual>,
+    ) {
+        use specs::Join;
+
+        let existing_views: HashMap<LodPatch, (specs::Entity, bool)> =
+            (&**entities, &*lod_patch_views)
+                .join()
+                .filter(|(_, lod_patch_view)| lod_patch_view.globe_entity == globe_entity)
+                .map(|(entity, lod_patch_view)| {
+                    let is_built = lod_patch_view.land_height_range.is_some();
+                    (lod_patch_view.patch, (entity, is_built))
+                })
+                .collect();
+        let unbuilt_patches: Vec<LodPatch> = wanted_patches
+            .iter()
+            .filter(|patch| existing_views.get(patch).is_none_or(|view| !view.1))
+            .cloned()
+            .collect();
+
+        for (patch, (entity, _)) in &existing_views {
+            if wanted_patches.contains(patch) {
+                continue;
+            }
+            let is_still_needed = unbuilt_patches
+                .iter()
+                .any(|unbuilt_patch| unbuilt_patch.overlaps(patch));
+            if is_still_needed {
+                continue;
+            }
+            trace!(self.log, "Removing a LOD patch view"; "patch" => format!("{:?}", patch));
+            // Releasing its mesh lets the mesh repository destroy it
+            // the next time it collects garbage.
+            if let Some(mut visual) = visuals.remove(*entity) {
+                visual.release_mesh();
+            }
+            lod_patch_views.remove(*entity);
+            entities
+                .delete(*entity)
+                .expect("Somehow tried to use an entity with the wrong generation!");
+        }
+    }
+
+    // Hide patches that are completely covered by chunk views,
+    // and bring back any that aren't any more.
+    fn update_hidden_patches<'a>(
+        &mut self,
+        globe: &Globe,
+        globe_entity: specs::Entity,
+        lod_patch_views: &mut WriteStorage<'a, LodPatchView>,
+        visuals: &mut WriteStorage<'a, Visual>,
+    ) {
+        use specs::Join;
+
+        for (lod_patch_view, visual) in (lod_patch_views, visuals).join() {
+            if lod_patch_view.globe_entity != globe_entity {
+                continue;
+            }
+            let land_height_range = match lod_patch_view.land_height_range {
+                Some(land_height_range) => land_height_range,
+                None => continue,
+            };
+            let is_covered =
+                is_covered_by_chunk_views(globe, lod_patch_view.patch, land_height_range);
+            if is_covered && !visual.is_hidden() {
+                trace!(self.log, "Hiding a LOD patch"; "patch" => format!("{:?}", lod_patch_view.patch));
+                visual.set_hidden(true);
+            } else if !is_covered && visual.is_hidden() {
+                trace!(self.log, "Unhiding a LOD patch"; "patch" => format!("{:?}", lod_patch_view.patch));
+                visual.set_hidden(false);
+            }
+        }
+    }
+
+    fn build_lod_geometry<'a>(
+        &mut self,
+        globes: &ReadStorage<'a, Globe>,
+        lod_patch_views: &mut WriteStorage<'a, LodPatchView>,
+        visuals: &mut WriteStorage<'a, Visual>,
+    ) {
+        use specs::Join;
+
+        // Throttle rate of geometry creation.
+        let ready =
+            self.seconds_since_last_geometry_creation > self.seconds_between_geometry_creation;
+        if !ready {
+            return;
+        }
+
+        // Fill in the biggest holes first.
+        let next = (lod_patch_views, visuals)
+            .join()
+            .filter(|(lod_patch_view, _)| lod_patch_view.land_height_range.is_none())
+            .max_by_key(|(lod_patch_view, _)| lod_patch_view.patch.size);
+        let (lod_patch_view, visual) = match next {
+            Some(next) => next,
+            None => return,
+        };
+        let globe = match globes.get(lod_patch_view.globe_entity) {
+            Some(globe) => globe,
+            None => {
+                warn!(
+                    self.log,
+                    "The globe associated with this LodPatchView is not alive! Can't proceed!"
+                );
+                return;
+            }
+        };
+
+        let globe_view = View::new(globe.spec(), &self.log);
+        let mut vertex_data: Vec<Vertex> = Vec::new();
+        let mut index_data: Vec<u32> = Vec::new();
+        let land_height_range = globe_view.make_lod_geometry(
+            globe,
+            lod_patch_view.patch,
+            &mut vertex_data,
+            &mut index_data,
+        );
+        lod_patch_view.land_height_range = Some(land_height_range);
+        visual.proto_mesh = ProtoMesh::new(vertex_data, index_data).into();
+        self.seconds_since_last_geometry_creation = 0.0;
+    }
+}
+
+// Make views for patches that don't have one yet.
+fn add_patch_views<'a>(
+    entities: &Entities<'a>,
+    globe: &Globe,
+    globe_entity: specs::Entity,
+    new_patches: &[LodPatch],
+    lod_patch_views: &mut WriteStorage<'a, LodPatchView>,
+    visuals: &mut WriteStorage<'a, Visual>,
+    spatials: &mut WriteStorage<'a, Spatial>,
+) {
+    let spec = globe.spec();
+    for patch in new_patches {
+        // We store the geometry relative to the patch origin at sea level.
+        let patch_origin_pos = spec.cell_center_on_unit_sphere(patch.origin()) * spec.ocean_radius;
+        let patch_transform = Iso3::new(patch_origin_pos.coords, na::zero());
+        let new_ent = entities.create();
+        lod_patch_views
+            .insert(new_ent, LodPatchView::new(globe_entity, *patch))
+            .expect("Inserting LOD patch view failed");
+        visuals
+            .insert(new_ent, Visual::new_empty())
+            .expect("Inserting empty visual failed");
+        spatials
+            .insert(new_ent, Spatial::new(globe_entity, patch_transform))
+            .expect("Inserting spatial failed");
+    }
+}
+
+// Whether there is a chunk view for every chunk that
+// the surface of the given patch passes through.
+fn is_covered_by_chunk_views(
+    globe: &Globe,
+    patch: LodPatch,
+    land_height_range: (Real, Real),
+) -> bool {
+    let spec = globe.spec();
+    let chunk_res = spec.chunk_resolution;
+    // Only the finest patches line up exactly with chunks.
+    if patch.size != chunk_res[0] || patch.size != chunk_res[1] {
+        return false;
+    }
+    let lowest_z = spec.approx_cell_z_from_radius(land_height_range.0).max(0);
+    let highest_z = spec.approx_cell_z_from_radius(land_height_range.1).max(0);
+    (lowest_z / chunk_res[2]..=highest_z / chunk_res[2]).all(|chunk_z| {
+        let origin = ChunkOrigin::new(
+            Point3::new(patch.root, patch.x, patch.y, chunk_z * chunk_res[2]),
+            spec.root_resolution,
+            chunk_res,
+        );
+        globe
+            .chunk_at(origin)
+            .is_some_and(|chunk| chunk.view_entity.is_some())
+    })
+}
+
+impl<'a> specs::System<'a> for LodSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, TimeDeltaResource>,
+        ReadStorage<'a, Globe>,
+        WriteStorage<'a, Visual>,
+        WriteStorage<'a, Spatial>,
+        WriteStorage<'a, LodPatchView>,
+        ReadStorage<'a, Viewer>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        use specs::Join;
+        let (entities, dt, globes, mut visuals, mut spatials, mut lod_patch_views, viewers) = data;
+
+        self.seconds_since_last_geometry_creation += dt.0;
+
+        for (globe, globe_entity) in (&globes, &*entities).join() {
+            let globe_viewers = viewers_of_globe(&entities, &viewers, &spatials, globe_entity);
+            let wanted_patches: HashSet<LodPatch> =
+                lod_patches_for_viewers(&globe.spec(), &globe_viewers)
+                    .into_iter()
+                    .collect();
+            self.remove_unwanted_patch_views(
+                &entities,
+                globe_entity,
+                &wanted_patches,
+                &mut lod_patch_views,
+                &mut visuals,
+            );
+            let existing_patches: HashSet<LodPatch> = lod_patch_views
+                .join()
+                .filter(|lod_patch_view| lod_patch_view.globe_entity == globe_entity)
+                .map(|lod_patch_view| lod_patch_view.patch)
+                .collect();
+            let new_patches: Vec<LodPatch> = wanted_patches
+                .difference(&existing_patches)
+                .cloned()
+                .collect();
+            add_patch_views(
+                &entities,
+                globe,
+                globe_entity,
+                &new_patches,
+                &mut lod_patch_views,
+                &mut visuals,
+                &mut spatials,
+            );
+            self.update_hidden_patches(globe, globe_entity, &mut lod_patch_views, &mut visuals);
+        }
+
+        self.build_lod_geometry(&globes, &mut lod_patch_views, &mut visuals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lod_geometry_follows_viewers() {
+        use specs::{Builder, RunNow};
+
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let mut lod_system = LodSystem::new(&log, 0.0);
+        lod_system.setup(&mut world.res);
+        world.add_resource(TimeDeltaResource(0.1));
+
+        let globe = Globe::new_example();
+        let spec = globe.spec();
+        let globe_entity = world
+            .create_entity()
+            .with(globe)
+            .with(Spatial::new_root())
+            .build();
+
+        let lod_patch_view_count =
+            |world: &specs::World| world.read_storage::<LodPatchView>().count();
+
+        // Nobody is looking, so there's nothing to draw.
+        lod_system.run_now(&world.res);
+        world.maintain();
+        assert_eq!(lod_patch_view_count(&world), 0);
+
+        // Once someone is looking, the whole globe gets covered.
+        let viewer_column = crate::grid::Point2::new(crate::grid::Root::new(1), 20, 30);
+        let viewer_pos = spec.cell_center_on_unit_sphere(viewer_column) * spec.ocean_radius * 1.1;
+        world
+            .create_entity()
+            .with(Spatial::new(
+                globe_entity,
+                Iso3::new(viewer_pos.coords, na::zero()),
+            ))
+            .with(Viewer::new(10.0))
+            .build();
+        lod_system.run_now(&world.res);
+        world.maintain();
+        let wanted_patches = lod_patches_for_viewers(&spec, &[(viewer_pos, 10.0)]);
+        assert_eq!(lod_patch_view_count(&world), wanted_patches.len());
+
+        // Geometry gets built one patch at a time, and sits
+        // somewhere between the floor and the top of the land.
+        let lod_patch_views = world.read_storage::<LodPatchView>();
+        let visuals = world.read_storage::<Visual>();
+        let spatials = world.read_storage::<Spatial>();
+        use specs::Join;
+        let built: Vec<_> = (&lod_patch_views, &visuals, &spatials)
+            .join()
+            .filter(|(lod_patch_view, _, _)| lod_patch_view.land_height_range.is_some())
+            .collect();
+        assert_eq!(built.len(), 1);
+        let (_, visual, spatial) = built[0];
+        let proto_mesh = visual.proto_mesh.as_ref().expect("Should have made a mesh");
+        let patch_origin = spatial.local_transform().translation.vector;
+        for vertex in &proto_mesh.vertexes {
+            let pos = Vec3::new(
+                vertex.a_pos[0].into(),
+                vertex.a_pos[1].into(),
+                vertex.a_pos[2].into(),
+            ) + patch_origin;
+            assert!(pos.norm() > spec.floor_radius - 10.0);
+            assert!(pos.norm() < spec.ocean_radius * 1.2);
+        }
+    }
+
+    #[test]
+    fn covered_patches_are_hidden_without_losing_geometry() {
+        use crate::globe::globe::GlobeGuts;
+        use specs::{Builder, RunNow};
+
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let mut lod_system = LodSystem::new(&log, 0.0);
+        lod_system.setup(&mut world.res);
+
+        let mut globe = Globe::new_example();
+        let spec = globe.spec();
+        let chunk_res = spec.chunk_resolution;
+        let patch = LodPatch::new(crate::grid::Root::new(1), 0, 0, chunk_res[0]);
+        let land_height_range = (spec.floor_radius, spec.floor_radius + 1.0);
+        let chunk_origin = ChunkOrigin::new(
+            Point3::new(patch.root, patch.x, patch.y, 0),
+            spec.root_resolution,
+            chunk_res,
+        );
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+        let globe_entity = world.create_entity().with(Spatial::new_root()).build();
+
+        let mut lod_patch_view = LodPatchView::new(globe_entity, patch);
+        lod_patch_view.land_height_range = Some(land_height_range);
+        let mut visual = Visual::new_empty();
+        visual.proto_mesh =
+            ProtoMesh::new(vec![Vertex::new([0.0; 3], [0.0; 3])], vec![0, 0, 0]).into();
+        let patch_entity = world
+            .create_entity()
+            .with(lod_patch_view)
+            .with(visual)
+            .build();
+
+        let mut update = |globe: &Globe| {
+            lod_system.update_hidden_patches(
+                globe,
+                globe_entity,
+                &mut world.write_storage::<LodPatchView>(),
+                &mut world.write_storage::<Visual>(),
+            );
+        };
+        let is_hidden = |world: &specs::World| {
+            world
+                .read_storage::<Visual>()
+                .get(patch_entity)
+                .unwrap()
+                .is_hidden()
+        };
+
+        // No chunk views yet, so the patch is drawn.
+        update(&globe);
+        assert!(!is_hidden(&world));
+
+        // Once the chunk under it has a view, it gets hidden.
+        globe
+            .chunks_mut()
+            .get_mut(&chunk_origin)
+            .unwrap()
+            .view_entity = Some(globe_entity);
+        update(&globe);
+        assert!(is_hidden(&world));
+
+        // And shown again when that view goes away, without having to rebuild anything.
+        globe
+            .chunks_mut()
+            .get_mut(&chunk_origin)
+            .unwrap()
+            .view_entity = None;
+        update(&globe);
+        assert!(!is_hidden(&world));
+        assert!(world
+            .read_storage::<LodPatchView>()
+            .get(patch_entity)
+            .unwrap()
+            .land_height_range
+            .is_some());
+        assert!(world
+            .read_storage::<Visual>()
+            .get(patch_entity)
+            .unwrap()
+            .proto_mesh
+            .is_some());
+    }
+}
@@ -0,0 +1,212 @@
+//! Definitions of the materials that cells can be made of.
+//!
+//! Each `Cell` stores only a small `Material` ID; everything else
+//! about that material lives in a `MaterialDef` in the `MaterialRegistry`
+//! shared by the `Globe`. Games can register their own materials
+//! (through `AppBuilder::with_material`) in addition to the built-in
+//! ones, and systems should query material properties instead of
+//! comparing against specific materials wherever possible.
+
+use std::collections::HashMap;
+
+/// Identifies a material in a `MaterialRegistry`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash, Serialize, Deserialize)]
+pub struct Material(pub u16);
+
+impl Material {
+    /// Built-in materials. These are always registered,
+    /// in this order, in every `MaterialRegistry`.
+    pub const AIR: Material = Material(0);
+    pub const DIRT: Material = Material(1);
+    pub const WATER: Material = Material(2);
+}
+
+/// Properties of a single kind of material.
+#[derive(Clone, Debug)]
+pub struct MaterialDef {
+    /// Unique name for looking up the material, e.g., "sand".
+    pub name: String,
+    /// Can be stood on, and can't be walked through.
+    pub solid: bool,
+    /// Flows, and can be swum through.
+    pub liquid: bool,
+    /// Neighboring cells can be seen through this one,
+    /// and it won't be drawn itself.
+    pub transparent: bool,
+    /// Can be picked up by a `CellDweller`.
+    pub mineable: bool,
+    /// Base color before per-cell shading is applied.
+    pub color: [f32; 3],
+    /// How hard the material is to break up; 0.0 is not at all.
+    pub hardness: f32,
+}
+
+impl MaterialDef {
+    /// Something like rock or dirt, that can be stood on and mined.
+    pub fn solid(name: &str, color: [f32; 3], hardness: f32) -> MaterialDef {
+        MaterialDef {
+            name: name.to_string(),
+            solid: true,
+            liquid: false,
+            transparent: false,
+            mineable: true,
+            color,
+            hardness,
+        }
+    }
+
+    /// Something like water or lava.
+    pub fn liquid(name: &str, color: [f32; 3]) -> MaterialDef {
+        MaterialDef {
+            name: name.to_string(),
+            solid: false,
+            liquid: true,
+            transparent: false,
+            mineable: false,
+            color,
+            hardness: 0.0,
+        }
+    }
+
+    /// Something like air, that is neither solid nor liquid,
+    /// and which is never drawn.
+    pub fn gas(name: &str) -> MaterialDef {
+        MaterialDef {
+            name: name.to_string(),
+            solid: false,
+            liquid: false,
+            transparent: true,
+            mineable: false,
+            color: [0.0, 0.0, 0.0],
+            hardness: 0.0,
+        }
+    }
+
+    /// Neither solid nor liquid, like air.
+    pub fn is_gas(&self) -> bool {
+        !self.solid && !self.liquid
+    }
+}
+
+/// All materials that cells in a `Globe` can be made of.
+#[derive(Clone, Debug)]
+pub struct MaterialRegistry {
+    defs: Vec<MaterialDef>,
+    by_name: HashMap<String, Material>,
+}
+
+impl MaterialRegistry {
+    /// Create a registry containing only the built-in materials.
+    pub fn new() -> MaterialRegistry {
+        let mut registry = MaterialRegistry {
+            defs: Vec::new(),
+            by_name: HashMap::new(),
+        };
+        registry.register(MaterialDef::gas("air"));
+        // Grassy green
+        registry.register(MaterialDef::solid("dirt", [0.0, 0.4, 0.0], 1.0));
+        // Ocean blue
+        registry.register(MaterialDef::liquid("water", [0.0, 0.1, 0.7]));
+        registry
+    }
+
+    /// Add a new material, and return its ID.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a material with the same name has already been registered,
+    /// or if there are already too many materials.
+    pub fn register(&mut self, def: MaterialDef) -> Material {
+        assert!(
+            !self.by_name.contains_key(&def.name),
+            "A material with that name was already registered"
+        );
+        assert!(self.defs.len() <= u16::MAX as usize, "Too many materials");
+        let material = Material(self.defs.len() as u16);
+        self.by_name.insert(def.name.clone(), material);
+        self.defs.push(def);
+        material
+    }
+
+    /// Look up a material by its name.
+    pub fn find(&self, name: &str) -> Option<Material> {
+        self.by_name.get(name).cloned()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the material was not registered.
+    pub fn get(&self, material: Material) -> &MaterialDef {
+        self.try_get(material)
+            .expect("Unknown material; was it registered?")
+    }
+
+    /// Like `get`, but returns `None` if the material was not registered,
+    /// e.g., because it came from an untrusted peer.
+    pub fn try_get(&self, material: Material) -> Option<&MaterialDef> {
+        self.defs.get(material.0 as usize)
+    }
+
+    pub fn is_solid(&self, material: Material) -> bool {
+        self.get(material).solid
+    }
+
+    pub fn is_liquid(&self, material: Material) -> bool {
+        self.get(material).liquid
+    }
+
+    pub fn is_transparent(&self, material: Material) -> bool {
+        self.get(material).transparent
+    }
+
+    pub fn is_mineable(&self, material: Material) -> bool {
+        self.get(material).mineable
+    }
+
+    pub fn is_gas(&self, material: Material) -> bool {
+        self.get(material).is_gas()
+    }
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_materials() {
+        let registry = MaterialRegistry::new();
+        assert_eq!(registry.find("air"), Some(Material::AIR));
+        assert_eq!(registry.find("dirt"), Some(Material::DIRT));
+        assert_eq!(registry.find("water"), Some(Material::WATER));
+        assert!(registry.is_gas(Material::AIR));
+        assert!(registry.is_transparent(Material::AIR));
+        assert!(registry.is_solid(Material::DIRT));
+        assert!(registry.is_mineable(Material::DIRT));
+        assert!(registry.is_liquid(Material::WATER));
+        assert!(!registry.is_gas(Material::WATER));
+    }
+
+    #[test]
+    fn register_custom_material() {
+        let mut registry = MaterialRegistry::new();
+        let stone = registry.register(MaterialDef::solid("stone", [0.5, 0.5, 0.5], 3.0));
+        assert_eq!(registry.find("stone"), Some(stone));
+        assert!(registry.is_solid(stone));
+        assert_eq!(registry.get(stone).hardness, 3.0);
+        assert!(registry.try_get(stone).is_some());
+        assert!(registry.try_get(Material(stone.0 + 1)).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_names_are_rejected() {
+        let mut registry = MaterialRegistry::new();
+        registry.register(MaterialDef::gas("air"));
+    }
+}
@@ -1,13 +1,17 @@
 // Don't make `globe` public; we re-export the
 // main bits at this level below.
 pub mod chunk;
+mod chunk_builder;
+mod chunk_cells;
 mod chunk_origin;
 mod chunk_pair;
 mod chunk_shared_points;
+mod chunk_storage;
 mod chunk_system;
 mod chunk_view;
 mod chunk_view_system;
 mod cursor;
+mod flood_fill;
 mod gen;
 // It's a private module; allow this.
 // (It's just used for grouping implementation code;
@@ -19,25 +23,50 @@ mod globe;
 mod globe_ext;
 pub mod icosahedron;
 mod iters;
+mod layered_gen;
+mod lod;
+mod lod_system;
+pub mod material;
+mod raycast;
 mod spec;
 mod view;
+mod viewer;
 
 #[cfg(test)]
 mod tests;
 
+use crate::na;
 use crate::types::*;
 
 // TODO: be selective in what you export; no wildcards!
+pub use self::chunk_builder::ChunkBuilder;
+pub use self::chunk_cells::ChunkCells;
 pub use self::chunk_origin::*;
 pub use self::chunk_shared_points::ChunkSharedPoints;
+pub use self::chunk_storage::ChunkStorage;
 pub use self::chunk_system::ChunkSystem;
 pub use self::chunk_view::*;
 pub use self::chunk_view_system::*;
 pub use self::cursor::{Cursor, CursorMut};
+pub use self::flood_fill::Region;
+pub(crate) use self::gen::shade_cell;
+pub use self::gen::{DensityParams, Gen, SimpleGen};
 pub use self::globe::Globe;
+#[cfg(test)]
+pub(crate) use self::globe::GlobeGuts;
 pub use self::iters::*;
+pub use self::layered_gen::{
+    Biome, BiomesParams, BiomesPass, CavesParams, CavesPass, ColumnInfo, ContinentsParams,
+    ContinentsPass, DecorationParams, DecorationPass, GenMaterials, GenPass, LayeredGen,
+    MountainsParams, MountainsPass, StrataParams, StrataPass,
+};
+pub use self::lod::{lod_patches_for_viewers, LodPatch, LodPatchView};
+pub use self::lod_system::LodSystem;
+pub use self::material::{Material, MaterialDef, MaterialRegistry};
+pub use self::raycast::{CellFace, RayHit};
 pub use self::spec::*;
 pub use self::view::*;
+pub use self::viewer::{Viewer, DEFAULT_VIEW_DISTANCE};
 
 use crate::grid::{GridCoord, Point3, PosInOwningRoot, Root};
 
@@ -113,7 +142,51 @@ pub fn project(root: Root, mut pt_in_root_quad: Pt2) -> Pt3 {
     //             (1, 2)
     //              3_0
     //
-    // TODO: cache all this stuff somewhere. It's tiny, and we'll use it heaps.
+    let [a, b, c, d, e, f] = root_quad_vertices(root);
+
+    // Triangle 0
+    let ab = b - a;
+    let ac = c - a;
+    // Triangle 1
+    let db = b - d;
+    let dc = c - d;
+    // Triangle 2
+    let cd = d - c;
+    let ce = e - c;
+    // Triangle 3
+    let fd = d - f;
+    let fe = e - f;
+
+    // It'll be easier to do the math we need here if the positions
+    // lie between (0, 0) and (1, 2).
+    pt_in_root_quad[1] *= 2.0;
+
+    // Decide which triangle we're in.
+    let pos_on_icosahedron = if pt_in_root_quad[0] + pt_in_root_quad[1] < 1.0 {
+        // In triangle 0.
+        a + ab * pt_in_root_quad[0] + ac * pt_in_root_quad[1]
+    } else if pt_in_root_quad[1] < 1.0 {
+        // In triangle 1.
+        d + dc * (1.0 - pt_in_root_quad[0]) + db * (1.0 - pt_in_root_quad[1])
+    } else if pt_in_root_quad[0] + pt_in_root_quad[1] < 2.0 {
+        // In triangle 2.
+        // Bring the y-value back into [0, 1] so we can just repeat the math from above.
+        pt_in_root_quad[1] -= 1.0;
+        c + cd * pt_in_root_quad[0] + ce * pt_in_root_quad[1]
+    } else {
+        // In triangle 3.
+        // Bring the y-value back into [0, 1] so we can just repeat the math from above.
+        pt_in_root_quad[1] -= 1.0;
+        f + fe * (1.0 - pt_in_root_quad[0]) + fd * (1.0 - pt_in_root_quad[1])
+    };
+    Pt3::from(pos_on_icosahedron.coords.normalize())
+}
+
+// The icosahedron vertices at the corners of the triangles making up a root quad,
+// named as in the diagram in `project`.
+//
+// TODO: cache all this stuff somewhere. It's tiny, and we'll use it heaps.
+fn root_quad_vertices(root: Root) -> [Pt3; 6] {
     use self::icosahedron::{FACES, VERTICES};
     let triangle_indices = [
         root.index as usize * 4,
@@ -157,43 +230,59 @@ pub fn project(root: Root, mut pt_in_root_quad: Pt2) -> Pt3 {
         VERTICES[faces[3][0]][1],
         VERTICES[faces[3][0]][2],
     );
+    [a, b, c, d, e, f]
+}
 
-    // Triangle 0
-    let ab = b - a;
-    let ac = c - a;
-    // Triangle 1
-    let db = b - d;
-    let dc = c - d;
-    // Triangle 2
-    let cd = d - c;
-    let ce = e - c;
-    // Triangle 3
-    let fd = d - f;
-    let fe = e - f;
-
-    // It'll be easier to do the math we need here if the positions
-    // lie between (0, 0) and (1, 2).
-    pt_in_root_quad[1] *= 2.0;
-
-    // Decide which triangle we're in.
-    let pos_on_icosahedron = if pt_in_root_quad[0] + pt_in_root_quad[1] < 1.0 {
-        // In triangle 0.
-        a + ab * pt_in_root_quad[0] + ac * pt_in_root_quad[1]
-    } else if pt_in_root_quad[1] < 1.0 {
-        // In triangle 1.
-        d + dc * (1.0 - pt_in_root_quad[0]) + db * (1.0 - pt_in_root_quad[1])
-    } else if pt_in_root_quad[0] + pt_in_root_quad[1] < 2.0 {
-        // In triangle 2.
-        // Bring the y-value back into [0, 1] so we can just repeat the math from above.
-        pt_in_root_quad[1] -= 1.0;
-        c + cd * pt_in_root_quad[0] + ce * pt_in_root_quad[1]
-    } else {
-        // In triangle 3.
-        // Bring the y-value back into [0, 1] so we can just repeat the math from above.
-        pt_in_root_quad[1] -= 1.0;
-        f + fe * (1.0 - pt_in_root_quad[0]) + fd * (1.0 - pt_in_root_quad[1])
-    };
-    Pt3::from(pos_on_icosahedron.coords.normalize())
+/// Inverse of `project`: find which root quad a point lies over,
+/// and where it is in that root quad, with one corner at (0, 0)
+/// and the opposite at (1, 1).
+///
+/// `pt` needn't lie on the unit sphere; only its direction
+/// from the center of the globe matters. Points on the boundary
+/// between root quads could be expressed in either, and you might
+/// get either back.
+pub fn unproject(pt: Pt3) -> (Root, Pt2) {
+    let mut best: Option<(Root, Pt2)> = None;
+    // How far outside the triangle the best candidate so far was,
+    // in case rounding errors leave the point just outside all of them.
+    let mut best_miss = f64::MAX;
+    // A triangle as an origin and two edges, and how to get
+    // from a position on the triangle back to a position in its root quad.
+    type Triangle = (Pt3, Vec3, Vec3, fn(f64, f64) -> Pt2);
+    for root in &crate::grid::ROOTS {
+        let [a, b, c, d, e, f] = root_quad_vertices(*root);
+        // Matching the math in `project`.
+        let triangles: [Triangle; 4] = [
+            (a, b - a, c - a, |u, v| Pt2::new(u, v)),
+            (d, c - d, b - d, |u, v| Pt2::new(1.0 - u, 1.0 - v)),
+            (c, d - c, e - c, |u, v| Pt2::new(u, v + 1.0)),
+            (f, e - f, d - f, |u, v| Pt2::new(1.0 - u, 2.0 - v)),
+        ];
+        for (origin, edge_u, edge_v, to_root_quad) in &triangles {
+            // Find where the ray from the center of the globe through `pt`
+            // meets the plane of the triangle.
+            let m = na::Matrix3::from_columns(&[pt.coords, -edge_u, -edge_v]);
+            let solution = match m.lu().solve(&origin.coords) {
+                Some(solution) => solution,
+                // Ray is parallel to the triangle.
+                None => continue,
+            };
+            let (t, u, v) = (solution[0], solution[1], solution[2]);
+            if t <= 0.0 {
+                // Wrong side of the globe.
+                continue;
+            }
+            let miss = (-u).max(-v).max(u + v - 1.0).max(0.0);
+            if miss < best_miss {
+                let mut pt_in_root_quad = to_root_quad(u, v);
+                // Undo the stretching in `project`.
+                pt_in_root_quad[1] /= 2.0;
+                best = Some((*root, pt_in_root_quad));
+                best_miss = miss;
+            }
+        }
+    }
+    best.expect("Point should have been over some triangle")
 }
 
 /// Calculate the origin of a chunk that contains the given `pos`,
@@ -0,0 +1,127 @@
+//! Following rays through the cells of a globe; useful for aiming,
+//! picking blocks with the mouse, line-of-sight checks, etc.
+
+use super::globe::Globe;
+use super::spec::Spec;
+use crate::grid::{Neighbors, Point2, Point3, PosInOwningRoot, ROOTS};
+use crate::types::*;
+
+/// How far to move along a ray between checking which cell it's in,
+/// as a fraction of the smallest dimension of a cell.
+const RAY_STEP_FRACTION: Real = 0.25;
+
+/// Which face of a cell a ray entered through.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CellFace {
+    Top,
+    Bottom,
+    /// One of the sides; the cell on the other side of it
+    /// is the `empty_cell` in the `RayHit`.
+    Side,
+}
+
+/// The first cell a ray ran into; see `Globe::cast_ray`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RayHit {
+    pub cell: PosInOwningRoot,
+    pub face: CellFace,
+    /// The cell the ray was in right before it hit `cell`.
+    /// This is where a new block would go if you were
+    /// building against the face that was hit.
+    pub empty_cell: PosInOwningRoot,
+    /// Approximate distance along the ray to where it hit.
+    pub distance: Real,
+}
+
+impl Globe {
+    /// Follow a ray through the globe cell by cell until it runs into
+    /// a cell that isn't made of air (or any other gas), and report
+    /// which cell that was, and how the ray got there.
+    ///
+    /// `origin` is relative to the globe, and `direction` needn't be
+    /// normalized. The cell `origin` is in can't be hit, so rays fired
+    /// from within the ground will find the first solid cell beyond it.
+    ///
+    /// Cells in chunks that aren't loaded are treated as empty, so make
+    /// sure anything you want to be able to hit is loaded first.
+    ///
+    /// Returns `None` if nothing was hit within `max_distance`,
+    /// or if the ray went out the bottom of the globe.
+    pub fn cast_ray(&self, origin: Pt3, direction: Vec3, max_distance: Real) -> Option<RayHit> {
+        let spec = self.spec();
+        let direction = direction.try_normalize(f64::EPSILON)?;
+        let step = approx_smallest_cell_dimension(&spec) * RAY_STEP_FRACTION;
+
+        let mut current = spec.cell_containing(origin);
+        if current.z < 0 {
+            // Start from the bottom of the globe instead.
+            current.z = 0;
+        }
+        let mut distance = 0.0;
+        while distance < max_distance {
+            distance = (distance + step).min(max_distance);
+            let sample = origin + direction * distance;
+            if sample.coords.norm() < spec.floor_radius {
+                // There's nothing down here.
+                return None;
+            }
+
+            // Walk one cell at a time to wherever the ray has got to,
+            // so that we don't skip over the corners of any cells.
+            while let Some(next) = closer_neighbor(&spec, current, sample) {
+                let is_empty = self
+                    .maybe_non_authoritative_cell(next)
+                    .map(|cell| self.materials().is_gas(cell.material))
+                    .unwrap_or(true);
+                if !is_empty {
+                    let face = if next.z > current.z {
+                        CellFace::Bottom
+                    } else if next.z < current.z {
+                        CellFace::Top
+                    } else {
+                        CellFace::Side
+                    };
+                    return Some(RayHit {
+                        cell: PosInOwningRoot::new(next, spec.root_resolution),
+                        face,
+                        empty_cell: PosInOwningRoot::new(current, spec.root_resolution),
+                        distance,
+                    });
+                }
+                current = next;
+            }
+        }
+        None
+    }
+}
+
+// Rough size of the smallest cells on the globe, either across or
+// from top to bottom; steps along a ray should be smaller than this.
+fn approx_smallest_cell_dimension(spec: &Spec) -> Real {
+    // Cells near the middle of a root are about as small as they get.
+    let middle = Point2::new(
+        ROOTS[0],
+        spec.root_resolution[0] / 2,
+        spec.root_resolution[1] / 2,
+    );
+    let a = spec.cell_center_on_unit_sphere(middle);
+    let b = spec.cell_center_on_unit_sphere(middle.with_x(middle.x + 1));
+    let width = (b - a).norm() * spec.floor_radius;
+    width.min(spec.block_height)
+}
+
+// The neighbor of `pos` whose center is closest to `pt`,
+// if it's any closer than the center of `pos` itself.
+fn closer_neighbor(spec: &Spec, pos: Point3, pt: Pt3) -> Option<Point3> {
+    let distance_to = |pos: Point3| (spec.cell_center_center(pos) - pt).norm();
+    let mut best_distance = distance_to(pos);
+    let mut best_neighbor = None;
+    for neighbor in Neighbors::new(pos, spec.root_resolution) {
+        let distance = distance_to(neighbor);
+        if distance < best_distance {
+            best_distance = distance;
+            best_neighbor = Some(neighbor);
+        }
+    }
+    best_neighbor
+}
@@ -1,6 +1,6 @@
 use crate::types::*;
 
-use crate::grid::{GridCoord, Point2, Point3};
+use crate::grid::{GridCoord, Point2, Point3, Root, ROOTS};
 
 // Contains the specifications (dimensions, seed, etc.)
 // needed to deterministically generate a `Globe`.
@@ -115,6 +115,17 @@ impl Spec {
         true
     }
 
+    /// Whether `pos` is a cell on this globe; i.e. it's in a real root,
+    /// isn't beyond the edges of that root, and isn't below the floor.
+    pub fn contains(&self, pos: Point3) -> bool {
+        (pos.root.index as usize) < ROOTS.len()
+            && pos.x >= 0
+            && pos.x <= self.root_resolution[0]
+            && pos.y >= 0
+            && pos.y <= self.root_resolution[1]
+            && pos.z >= 0
+    }
+
     pub fn chunks_per_root_side(&self) -> [GridCoord; 2] {
         // Assume chunk resolution divides perfectly into root resolution.
         [
@@ -170,4 +181,123 @@ impl Spec {
     pub fn approx_cell_z_from_radius(&self, radius: f64) -> GridCoord {
         ((radius - self.floor_radius) / self.block_height) as GridCoord
     }
+
+    /// Inverse of `cell_center_on_unit_sphere`: find the column
+    /// that a line from the center of the globe through `pt` passes through.
+    ///
+    /// The column might be expressed in any root that contains it;
+    /// use `PosInOwningRoot` if you need a canonical representation.
+    pub fn column_containing(&self, pt: Pt3) -> Point2 {
+        let (root, pt_in_root_quad) = super::unproject(pt);
+        let (x, y) = round_to_hex(
+            pt_in_root_quad[0] * self.root_resolution[0] as f64,
+            pt_in_root_quad[1] * self.root_resolution[1] as f64,
+        );
+        // Rounding can't take us more than a third of a cell
+        // past the edge of the root quad, but floating point
+        // error could push us over.
+        Point2::new(
+            root,
+            x.clamp(0, self.root_resolution[0]),
+            y.clamp(0, self.root_resolution[1]),
+        )
+    }
+
+    /// Find the cell containing `pt`, relative to the center of the globe.
+    ///
+    /// The z-coordinate will be negative for points below `floor_radius`,
+    /// where there are no cells.
+    pub fn cell_containing(&self, pt: Pt3) -> Point3 {
+        let z = ((pt.coords.norm() - self.floor_radius) / self.block_height).floor() as GridCoord;
+        self.column_containing(pt).with_z(z)
+    }
+
+    /// Find the column at the given latitude and longitude, in degrees.
+    ///
+    /// See `column_to_lat_lon` for where the poles and prime meridian are.
+    pub fn lat_lon_to_column(&self, lat: f64, lon: f64) -> Point2 {
+        self.column_containing(Pt3::from(lat_lon_to_unit_vector(lat, lon)))
+    }
+
+    /// Latitude and longitude, in degrees, of the center of a column.
+    ///
+    /// The north pole is the cell at (0, 0) in every root, and the
+    /// south pole is at the opposite corner. The prime meridian runs
+    /// south from the north pole through the corner of the first root
+    /// at (`root_resolution[0]`, 0). Longitude increases to the east,
+    /// and is between -180 and 180.
+    pub fn column_to_lat_lon(&self, column: Point2) -> (f64, f64) {
+        unit_vector_to_lat_lon(self.cell_center_on_unit_sphere(column).coords)
+    }
+
+    /// Distance between the centers of two columns at sea level,
+    /// following the surface of the globe.
+    pub fn great_circle_distance(&self, from: Point2, to: Point2) -> f64 {
+        let a = self.cell_center_on_unit_sphere(from).coords;
+        let b = self.cell_center_on_unit_sphere(to).coords;
+        a.cross(&b).norm().atan2(a.dot(&b)) * self.ocean_radius
+    }
+
+    /// Direction to set off in to follow the shortest path from one column
+    /// to another, in degrees clockwise from north, in [0, 360).
+    ///
+    /// From the poles, where every direction is south (or north),
+    /// this is always zero.
+    pub fn initial_bearing(&self, from: Point2, to: Point2) -> f64 {
+        let a = self.cell_center_on_unit_sphere(from).coords;
+        let b = self.cell_center_on_unit_sphere(to).coords;
+        let (north_pole, _) = lat_lon_axes();
+        // Directions along the surface at `a`.
+        let east = north_pole.cross(&a);
+        let north = a.cross(&east);
+        let heading = b - a * a.dot(&b);
+        let bearing = heading.dot(&east).atan2(heading.dot(&north)).to_degrees();
+        if bearing < 0.0 {
+            bearing + 360.0
+        } else {
+            bearing
+        }
+    }
+}
+
+// Unit vectors pointing at the north pole, and at the equator on the prime meridian;
+// see `Spec::column_to_lat_lon`.
+fn lat_lon_axes() -> (Vec3, Vec3) {
+    let north_pole = super::project(Root::new(0), Pt2::new(0.0, 0.0)).coords;
+    let prime_meridian_corner = super::project(Root::new(0), Pt2::new(1.0, 0.0)).coords;
+    let prime_meridian =
+        (prime_meridian_corner - north_pole * north_pole.dot(&prime_meridian_corner)).normalize();
+    (north_pole, prime_meridian)
+}
+
+fn lat_lon_to_unit_vector(lat: f64, lon: f64) -> Vec3 {
+    let (north_pole, prime_meridian) = lat_lon_axes();
+    let east = north_pole.cross(&prime_meridian);
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    (prime_meridian * lon.cos() + east * lon.sin()) * lat.cos() + north_pole * lat.sin()
+}
+
+fn unit_vector_to_lat_lon(v: Vec3) -> (f64, f64) {
+    let (north_pole, prime_meridian) = lat_lon_axes();
+    let east = north_pole.cross(&prime_meridian);
+    let lat = v.dot(&north_pole).clamp(-1.0, 1.0).asin();
+    let lon = v.dot(&east).atan2(v.dot(&prime_meridian));
+    (lat.to_degrees(), lon.to_degrees())
+}
+
+// Round a position in grid coordinates to the center of the hexagon containing it.
+//
+// Neighbors in the grid are at (±1, 0), (0, ±1), (1, -1), and (-1, 1), so these
+// are really axial hex coordinates; convert to cube coordinates and round those,
+// fixing up whichever moved furthest so they still sum to zero.
+fn round_to_hex(x: f64, y: f64) -> (GridCoord, GridCoord) {
+    let z = -x - y;
+    let (mut rx, mut ry, rz) = (x.round(), y.round(), z.round());
+    let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+    if dx > dy && dx > dz {
+        rx = -ry - rz;
+    } else if dy > dz {
+        ry = -rx - rz;
+    }
+    (rx as GridCoord, ry as GridCoord)
 }
@@ -1,4 +1,5 @@
 use super::*;
+use crate::test_util::{flat_example_globe, FLAT_SURFACE_Z};
 
 #[test]
 fn find_spawn_points() {
@@ -29,6 +30,560 @@ fn find_spawn_points() {
     assert!(successes < TRIALS - 5);
 }
 
+#[test]
+fn modified_chunks_survive_unloading() {
+    use super::chunk::Material;
+    use crate::grid::Root;
+    use crate::test_util::TempPath;
+
+    let dir = TempPath::new("globe-storage-test");
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    globe.set_storage(ChunkStorage::new(&dir, spec.chunk_resolution).unwrap());
+
+    // Pick a cell in the middle of a chunk, so that it isn't shared.
+    let pos = PosInOwningRoot::new(Point3::new(Root::new(2), 5, 6, 2), spec.root_resolution);
+    let chunk_origin = globe.origin_of_chunk_owning(pos);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    let original_material = globe.authoritative_cell(pos).material;
+    let new_material = if original_material == Material::WATER {
+        Material::DIRT
+    } else {
+        Material::WATER
+    };
+    globe.authoritative_cell_mut(pos).material = new_material;
+
+    // Unload it and load it back in.
+    globe.unload_chunk(chunk_origin).unwrap();
+    assert!(globe.chunk_at(chunk_origin).is_none());
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert_eq!(globe.authoritative_cell(pos).material, new_material);
+
+    // A globe without storage would have generated it fresh.
+    let mut fresh_globe = Globe::new_example();
+    fresh_globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert_eq!(
+        fresh_globe.authoritative_cell(pos).material,
+        original_material
+    );
+}
+
+#[test]
+fn saved_chunks_load_in_background() {
+    use super::chunk::Material;
+    use crate::grid::Root;
+    use crate::test_util::TempPath;
+
+    let dir = TempPath::new("globe-background-storage-test");
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    globe.set_storage(ChunkStorage::new(&dir, spec.chunk_resolution).unwrap());
+    globe.build_chunks_in_background(1);
+    let pos = PosInOwningRoot::new(Point3::new(Root::new(2), 5, 6, 2), spec.root_resolution);
+    let chunk_origin = globe.origin_of_chunk_owning(pos);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    let new_material = if globe.authoritative_cell(pos).material == Material::WATER {
+        Material::DIRT
+    } else {
+        Material::WATER
+    };
+    globe.authoritative_cell_mut(pos).material = new_material;
+    globe.unload_chunk(chunk_origin).unwrap();
+
+    globe.request_chunk(chunk_origin).unwrap();
+    assert!(globe.is_chunk_pending(chunk_origin));
+    let started = std::time::Instant::now();
+    while globe.is_chunk_pending(chunk_origin) {
+        assert!(
+            started.elapsed().as_secs() < 30,
+            "Chunk took too long to load"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        globe.add_built_chunks().unwrap();
+    }
+    assert_eq!(globe.authoritative_cell(pos).material, new_material);
+}
+
+#[test]
+fn corrupt_saved_chunks_are_built_fresh() {
+    use super::chunk::Material;
+    use crate::grid::Root;
+    use crate::test_util::TempPath;
+
+    let dir = TempPath::new("globe-corrupt-storage-test");
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    globe.set_storage(ChunkStorage::new(&dir, spec.chunk_resolution).unwrap());
+    let pos = PosInOwningRoot::new(Point3::new(Root::new(2), 5, 6, 2), spec.root_resolution);
+    let chunk_origin = globe.origin_of_chunk_owning(pos);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    let original_material = globe.authoritative_cell(pos).material;
+    globe.authoritative_cell_mut(pos).material = if original_material == Material::WATER {
+        Material::DIRT
+    } else {
+        Material::WATER
+    };
+    globe.unload_chunk(chunk_origin).unwrap();
+
+    // Chop the end off the region file it was saved in.
+    let region_paths: Vec<_> = std::fs::read_dir(&*dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(region_paths.len(), 1);
+    let contents = std::fs::read(&region_paths[0]).unwrap();
+    std::fs::write(&region_paths[0], &contents[..contents.len() / 2]).unwrap();
+
+    // Next time we run, it should be built fresh,
+    // and the bad region file moved out of the way.
+    let mut globe = Globe::new_example();
+    globe.set_storage(ChunkStorage::new(&dir, spec.chunk_resolution).unwrap());
+    assert!(globe.ensure_chunk_present(chunk_origin).is_err());
+    assert_eq!(globe.authoritative_cell(pos).material, original_material);
+    assert!(!region_paths[0].exists());
+    assert!(region_paths[0].with_extension("json.corrupt").exists());
+
+    // Now it's as if it was never saved.
+    globe.unload_chunk(chunk_origin).unwrap();
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert_eq!(globe.authoritative_cell(pos).material, original_material);
+}
+
+#[test]
+fn chunks_are_identical_wherever_generated() {
+    use crate::grid::Root;
+
+    let mut globe_a = Globe::new_example();
+    let mut globe_b = Globe::new_example();
+    let spec = globe_a.spec();
+    let chunk_origin = ChunkOrigin::new(
+        Point3::new(Root::new(1), 16, 32, 60),
+        spec.root_resolution,
+        spec.chunk_resolution,
+    );
+    globe_a.ensure_chunk_present(chunk_origin).unwrap();
+    globe_b.ensure_chunk_present(chunk_origin).unwrap();
+    let cells_a = globe_a.chunk_at(chunk_origin).unwrap().cells.to_vec();
+    let cells_b = globe_b.chunk_at(chunk_origin).unwrap().cells.to_vec();
+    assert_eq!(cells_a, cells_b);
+
+    // Make sure there's actually some shade to compare.
+    let shades_differ = cells_a
+        .iter()
+        .filter(|cell| cell.material != Material::AIR)
+        .any(|cell| cell.shade != cells_a[0].shade);
+    assert!(shades_differ);
+}
+
+#[test]
+fn chunks_can_be_built_in_background() {
+    use crate::grid::Root;
+
+    let mut globe = Globe::new_example();
+    globe.build_chunks_in_background(2);
+    let spec = globe.spec();
+    let chunk_origin = ChunkOrigin::new(
+        Point3::new(Root::new(4), 16, 32, 60),
+        spec.root_resolution,
+        spec.chunk_resolution,
+    );
+    let neighbor_origin = ChunkOrigin::new(
+        Point3::new(Root::new(4), 16, 48, 60),
+        spec.root_resolution,
+        spec.chunk_resolution,
+    );
+    globe.request_chunk(chunk_origin).unwrap();
+    globe.request_chunk(neighbor_origin).unwrap();
+    assert_eq!(globe.pending_chunk_count(), 2);
+    assert!(globe.is_chunk_pending(chunk_origin));
+    // Pick a point in the middle of the chunk, so that it's not owned by a neighbor.
+    let pos_in_chunk = Point3::new(Root::new(4), 20, 36, 61);
+    assert!(globe.is_waiting_for_chunks_near(pos_in_chunk));
+
+    // Wait for the builder threads to finish.
+    let started = std::time::Instant::now();
+    while globe.pending_chunk_count() > 0 {
+        assert!(
+            started.elapsed().as_secs() < 30,
+            "Chunks took too long to build"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        globe.add_built_chunks().unwrap();
+    }
+    assert!(!globe.is_waiting_for_chunks_near(pos_in_chunk));
+
+    // Should be the same as building them immediately.
+    let mut other_globe = Globe::new_example();
+    other_globe.ensure_chunk_present(chunk_origin).unwrap();
+    other_globe.ensure_chunk_present(neighbor_origin).unwrap();
+    for origin in &[chunk_origin, neighbor_origin] {
+        assert_eq!(
+            globe.chunk_at(*origin).unwrap().cells.to_vec(),
+            other_globe.chunk_at(*origin).unwrap().cells.to_vec()
+        );
+    }
+}
+
+#[test]
+fn find_loaded_cells_within_radius() {
+    use crate::grid::Root;
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    let pos = Point3::new(Root::new(4), 20, 36, 61);
+    let pos_in_owning_root = PosInOwningRoot::new(pos, spec.root_resolution);
+    let center = spec.cell_center_center(pos);
+
+    // Nothing is loaded yet.
+    assert!(globe.loaded_cells_within(center, 2.0).is_empty());
+
+    globe
+        .ensure_chunk_present(globe.origin_of_chunk_owning(pos_in_owning_root))
+        .unwrap();
+    assert_eq!(
+        globe.loaded_cells_within(center, 0.1),
+        vec![pos_in_owning_root]
+    );
+    let cells = globe.loaded_cells_within(center, 2.0);
+    assert!(cells.contains(&pos_in_owning_root));
+    assert!(cells.contains(&PosInOwningRoot::new(
+        pos.with_z(pos.z + 1),
+        spec.root_resolution
+    )));
+    for cell in &cells {
+        assert!((spec.cell_center_center((*cell).into()) - center).norm() <= 2.0);
+    }
+}
+
+fn density_example_globe() -> Globe {
+    use std::sync::Arc;
+
+    let spec = Spec::new_earth_scale_example();
+    let gen = SimpleGen::new_with_density(spec, DensityParams::from_spec(&spec));
+    Globe::new_with_gen(spec, Arc::new(MaterialRegistry::new()), Box::new(gen))
+}
+
+#[test]
+fn density_mode_carves_caves() {
+    use crate::grid::{Point2, Root};
+
+    let globe = density_example_globe();
+    let spec = globe.spec();
+    let params = DensityParams::from_spec(&spec);
+    let mut cave_cells = 0;
+    for i in 0..200 {
+        let column = Point2::new(Root::new((i % 5) as u8), i * 31, i * 67);
+        let land_height = globe.gen.land_height(column);
+        let surface_z = spec.approx_cell_z_from_radius(land_height);
+        for z in 0..surface_z {
+            let grid_point = column.with_z(z);
+            let depth = land_height - spec.cell_center_center(grid_point).coords.norm();
+            let material = globe.gen.cell_at(grid_point).material;
+            if depth > params.cave_min_depth && material == Material::AIR {
+                cave_cells += 1;
+            }
+            // Caves stay dry.
+            if depth > params.cave_min_depth {
+                assert_ne!(material, Material::WATER);
+            }
+        }
+    }
+    assert!(cave_cells > 0);
+}
+
+#[test]
+fn density_mode_spawn_points() {
+    use crate::grid::Root;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256StarStar;
+
+    let mut globe = density_example_globe();
+    let spec = globe.spec();
+    let params = DensityParams::from_spec(&spec);
+    let mut rng = Xoshiro256StarStar::seed_from_u64(spec.seed);
+    for _ in 0..10 {
+        let pos = globe
+            .air_above_random_surface_dry_land(&mut rng, 5, 5, 100)
+            .expect("Should have found dry land");
+        // We should have found the real surface, not the floor of a cave.
+        let land_height = globe.gen.land_height(pos.rxy);
+        let pos_height = spec.cell_center_center(pos).coords.norm();
+        assert!(pos_height > land_height - params.overhang_height - spec.block_height);
+        let under_pos = pos.with_z(pos.z - 1);
+        let under_material = globe
+            .maybe_non_authoritative_cell(under_pos)
+            .unwrap()
+            .material;
+        assert!(globe.materials().is_solid(under_material));
+    }
+
+    // The lowest air might be in a cave, but it should still be air.
+    let column = Point3::new(Root::new(3), 1000, 2000, 0);
+    let air_pos = globe.find_lowest_cell_containing(column, Material::AIR);
+    let material = globe
+        .maybe_non_authoritative_cell(air_pos)
+        .unwrap()
+        .material;
+    assert_eq!(material, Material::AIR);
+}
+
+#[test]
+fn unproject_undoes_project() {
+    use crate::grid::ROOTS;
+
+    for root in &ROOTS {
+        for &(x, y) in &[(0.1, 0.2), (0.5, 0.25), (0.3, 0.6), (0.9, 0.95), (0.5, 0.5)] {
+            let pt_in_root_quad = Pt2::new(x, y);
+            let (unprojected_root, unprojected_pt) = unproject(project(*root, pt_in_root_quad));
+            assert_eq!(unprojected_root, *root);
+            assert!((unprojected_pt - pt_in_root_quad).norm() < 1e-9);
+        }
+    }
+}
+
+#[test]
+fn find_cell_containing_point() {
+    use crate::grid::cell_shape::DIR_OFFSETS;
+    use crate::grid::{Point2, ROOTS};
+
+    let spec = Globe::new_example().spec();
+    let res = spec.root_resolution;
+    let z = 3;
+    for root in &ROOTS {
+        // Include root edges and poles.
+        for &(x, y) in &[
+            (0, 0),
+            (res[0], res[1]),
+            (0, 17),
+            (res[0], 90),
+            (13, 0),
+            (40, res[1]),
+            (res[0], 0),
+            (0, res[1]),
+            (21, 64),
+            (37, 101),
+        ] {
+            let cell = Point3::new(*root, x, y, z);
+            let expected = PosInOwningRoot::new(cell, res);
+            let center = spec.cell_center_center(cell);
+            assert_eq!(
+                PosInOwningRoot::new(spec.cell_containing(center), res),
+                expected
+            );
+
+            // Only the direction matters for finding the column.
+            assert_eq!(
+                PosInOwningRoot::new(spec.column_containing(center * 7.0).with_z(z), res),
+                expected
+            );
+
+            // Anywhere inside the cell should do, even close to its corners.
+            let radius = spec.floor_radius + spec.block_height * (z as f64 + 0.9);
+            for vertex_offset in DIR_OFFSETS.iter().skip(1).step_by(2) {
+                // Skip the corners of cells on root edges that
+                // are outside of this root.
+                let vertex_x = x * 6 + vertex_offset[0];
+                let vertex_y = y * 6 + vertex_offset[1];
+                if vertex_x < 0 || vertex_y < 0 || vertex_x > res[0] * 6 || vertex_y > res[1] * 6 {
+                    continue;
+                }
+                let vertex = spec.cell_vertex_on_unit_sphere(cell, *vertex_offset);
+                let near_vertex = center.coords.normalize() * 0.1 + vertex.coords * 0.9;
+                assert_eq!(
+                    PosInOwningRoot::new(
+                        spec.cell_containing(Pt3::from(near_vertex * radius)),
+                        res
+                    ),
+                    expected
+                );
+            }
+        }
+    }
+
+    // Below the bottom of the globe.
+    let column = Point2::new(ROOTS[1], 5, 6);
+    let below = spec.cell_center_on_unit_sphere(column) * (spec.floor_radius - 0.1);
+    assert_eq!(spec.cell_containing(below), column.with_z(-1));
+}
+
+#[test]
+fn lat_lon_conversions() {
+    use crate::grid::{Point2, Root};
+    use std::f64::consts::PI;
+
+    let spec = Globe::new_example().spec();
+    let res = spec.root_resolution;
+    let owning = |column: Point2| PosInOwningRoot::new(column.with_z(0), res);
+    let north_pole = Point2::new(Root::new(3), 0, 0);
+    let south_pole = Point2::new(Root::new(1), res[0], res[1]);
+    assert_eq!(
+        owning(spec.lat_lon_to_column(90.0, 123.0)),
+        owning(north_pole)
+    );
+    assert_eq!(
+        owning(spec.lat_lon_to_column(-90.0, -20.0)),
+        owning(south_pole)
+    );
+    assert!((spec.column_to_lat_lon(north_pole).0 - 90.0).abs() < 1e-9);
+
+    // Going from columns to lat/long and back should land in the same column.
+    for &(x, y) in &[(3, 5), (30, 60), (64, 70), (12, 127), (50, 20)] {
+        for root in 0..5 {
+            let column = Point2::new(Root::new(root), x, y);
+            let (lat, lon) = spec.column_to_lat_lon(column);
+            assert!(lat.abs() <= 90.0 && lon.abs() <= 180.0);
+            assert_eq!(owning(spec.lat_lon_to_column(lat, lon)), owning(column));
+        }
+    }
+
+    // Pole to pole is half way around the globe,
+    // and heading due north from anywhere else gets you there.
+    let equator_column = spec.lat_lon_to_column(0.0, 0.0);
+    assert!(
+        (spec.great_circle_distance(north_pole, south_pole) - PI * spec.ocean_radius).abs() < 1e-9
+    );
+    let bearing = spec.initial_bearing(equator_column, north_pole);
+    assert!(!(1.0..=359.0).contains(&bearing));
+    assert!((spec.initial_bearing(equator_column, south_pole) - 180.0).abs() < 1.0);
+
+    // A quarter of the way around the equator to the east.
+    let east_column = spec.lat_lon_to_column(0.0, 90.0);
+    assert!((spec.initial_bearing(equator_column, east_column) - 90.0).abs() < 2.0);
+    assert!((spec.initial_bearing(east_column, equator_column) - 270.0).abs() < 2.0);
+    let quarter = PI / 2.0 * spec.ocean_radius;
+    assert!((spec.great_circle_distance(equator_column, east_column) - quarter).abs() < 2.0);
+}
+
+#[test]
+fn cast_ray_straight_down() {
+    use crate::grid::Root;
+
+    let mut globe = flat_example_globe();
+    let spec = globe.spec();
+    let ground = PosInOwningRoot::new(
+        Point3::new(Root::new(4), 20, 36, FLAT_SURFACE_Z - 1),
+        spec.root_resolution,
+    );
+    globe
+        .ensure_chunk_present(globe.origin_of_chunk_owning(ground))
+        .unwrap();
+
+    let origin = spec.cell_center_center(ground.pos().with_z(FLAT_SURFACE_Z + 5));
+    let hit = globe
+        .cast_ray(origin, -origin.coords, 10.0)
+        .expect("Should have hit the ground");
+    assert_eq!(hit.cell, ground);
+    assert_eq!(hit.face, CellFace::Top);
+    assert_eq!(
+        hit.empty_cell,
+        PosInOwningRoot::new(ground.pos().with_z(FLAT_SURFACE_Z), spec.root_resolution)
+    );
+    assert!((hit.distance - spec.block_height * 5.5).abs() < spec.block_height);
+
+    // Not far enough to reach the ground.
+    assert!(globe.cast_ray(origin, -origin.coords, 2.0).is_none());
+    // Straight up, there's nothing to hit.
+    assert!(globe.cast_ray(origin, origin.coords, 100.0).is_none());
+}
+
+#[test]
+fn cast_ray_across_roots() {
+    use crate::grid::{Dir, Root};
+    use crate::movement::{step_forward_and_face_neighbor, TurnDir};
+
+    let mut globe = flat_example_globe();
+    let spec = globe.spec();
+    let target = Point3::new(Root::new(0), 2, 40, FLAT_SURFACE_Z - 1);
+    let target_in_owning_root = PosInOwningRoot::new(target, spec.root_resolution);
+    globe
+        .ensure_chunk_present(globe.origin_of_chunk_owning(target_in_owning_root))
+        .unwrap();
+
+    // Walk a few cells away from the target, into the next root over,
+    // and fire from high above there.
+    let mut start = target;
+    let mut dir = Dir::new(6);
+    let mut last_turn_bias = TurnDir::Right;
+    for _ in 0..6 {
+        step_forward_and_face_neighbor(
+            &mut start,
+            &mut dir,
+            spec.root_resolution,
+            &mut last_turn_bias,
+        )
+        .unwrap();
+    }
+    assert_ne!(
+        PosInOwningRoot::new(start, spec.root_resolution).pos().root,
+        target.root
+    );
+    let origin = spec.cell_center_center(start.with_z(FLAT_SURFACE_Z + 30));
+    let aim_at = spec.cell_bottom_center(target.with_z(FLAT_SURFACE_Z));
+
+    let hit = globe
+        .cast_ray(origin, aim_at - origin, 100.0)
+        .expect("Should have hit the target");
+    assert_eq!(hit.cell, target_in_owning_root);
+    assert_eq!(hit.face, CellFace::Top);
+    assert_eq!(
+        hit.empty_cell,
+        PosInOwningRoot::new(target.with_z(FLAT_SURFACE_Z), spec.root_resolution)
+    );
+}
+
+#[test]
+fn flood_fill_finds_sealed_cave_across_roots() {
+    use crate::cell_dweller::{place_block, remove_block};
+    use crate::grid::{Neighbors, Root};
+
+    let mut globe = flat_example_globe();
+    let spec = globe.spec();
+    let res = spec.root_resolution;
+
+    // Dig a pit down from the surface, straddling the edge of a root.
+    let middle = Point3::new(Root::new(0), 0, 40, 6);
+    let columns: Vec<Point3> = std::iter::once(middle)
+        .chain(Neighbors::new(middle, res).filter(|neighbor| neighbor.z == middle.z))
+        .collect();
+    assert_eq!(columns.len(), 7);
+    for column in &columns {
+        for z in middle.z..FLAT_SURFACE_Z {
+            let pos = PosInOwningRoot::new(column.with_z(z), res);
+            globe
+                .ensure_chunk_present(globe.origin_of_chunk_owning(pos))
+                .unwrap();
+            remove_block(&mut globe, pos);
+        }
+    }
+    let is_air = |cell: &chunk::Cell| cell.material == Material::AIR;
+
+    // It's open to the sky, so there's no end to it.
+    let region = globe.flood_fill(middle, 200, is_air);
+    assert!(region.escaped_bound);
+    assert_eq!(region.volume(), 200);
+
+    // Put a lid on it, and now it's a cave.
+    for column in &columns {
+        let pos = PosInOwningRoot::new(column.with_z(FLAT_SURFACE_Z - 1), res);
+        place_block(&mut globe, pos, Material::DIRT);
+    }
+    let region = globe.flood_fill(middle, 200, is_air);
+    assert!(!region.escaped_bound);
+    assert_eq!(region.volume(), 7 * 3);
+    for column in &columns {
+        assert!(region.contains(PosInOwningRoot::new(*column, res)));
+    }
+    assert!(region.cells.iter().any(|pos| pos.pos().root != middle.root));
+
+    // Starting somewhere that doesn't match finds nothing.
+    let region = globe.flood_fill(middle.with_z(middle.z - 1), 200, is_air);
+    assert_eq!(region.volume(), 0);
+    assert!(!region.escaped_bound);
+}
+
 #[cfg(feature = "nightly")]
 pub mod benches {
     use test::Bencher;
@@ -1,13 +1,20 @@
 use slog::Logger;
 
-use super::chunk::Material;
+use super::lod::LodPatch;
 use super::spec::Spec;
-use super::{ChunkOrigin, Cursor, Globe};
+use super::{ChunkOrigin, Cursor, Globe, Material};
 use crate::grid::cell_shape;
-use crate::grid::Point3;
+use crate::grid::{GridCoord, Point2, Point3};
 use crate::render;
 use crate::types::Pt3;
 
+// How many steps to sample land height at along each side
+// of a LOD patch, regardless of its size.
+const LOD_PATCH_SEGMENTS: GridCoord = 16;
+
+// How far to sink LOD geometry below the real surface, in blocks.
+const LOD_SINK_BLOCKS: f64 = 1.0;
+
 // TODO: between this and "draw" we now have some confusing names.
 // Shuffle this code into something that implies it's just about
 // generating geometry for other components/systems, e.g., drawing
@@ -91,18 +98,13 @@ impl View {
                         // Eww... can I please have non-lexical borrow scopes? :)
                         let cell = cursor.cell().expect("We shouldn't be trying to build geometry for a chunk that isn't loaded.");
 
-                        // TEMP color dirt as green, ocean as blue.
                         // TEMP: Randomly mutate cell color to make it easier to see edges.
-                        let mut inner_cell_color = if cell.material == Material::Dirt {
-                            // Grassy green
-                            [0.0, 0.4, 0.0]
-                        } else if cell.material == Material::Water {
-                            // Ocean blue
-                            [0.0, 0.1, 0.7]
-                        } else {
-                            // Don't draw air or anything else we don't understand.
+                        let material = globe.materials().get(cell.material);
+                        if material.transparent {
+                            // Don't draw air or anything else we can see through.
                             continue;
-                        };
+                        }
+                        let mut inner_cell_color = material.color;
                         for color_channel in &mut inner_cell_color {
                             *color_channel *= 1.0 - 0.5 * cell.shade;
                         }
@@ -199,6 +201,113 @@ impl View {
         }
     }
 
+    /// Creates coarse geometry for a patch of the globe's surface
+    /// based only on the generator's `land_height`, for drawing
+    /// parts of the globe that don't have chunks loaded.
+    ///
+    /// Vertex positions are specified relative to the patch origin
+    /// projected onto the surface of the ocean.
+    ///
+    /// Returns the lowest and highest land height found in the patch.
+    pub fn make_lod_geometry(
+        &self,
+        globe: &Globe,
+        patch: LodPatch,
+        vertex_data: &mut Vec<render::Vertex>,
+        index_data: &mut Vec<u32>,
+    ) -> (f64, f64) {
+        trace!(self.log, "Building LOD geometry"; "patch" => format!("{:?}", patch));
+
+        let patch_origin_pos =
+            self.spec.cell_center_on_unit_sphere(patch.origin()) * self.spec.ocean_radius;
+
+        // Sample land height on a regular grid of columns across the patch,
+        // including those on the far edges.
+        let segments = LOD_PATCH_SEGMENTS.min(patch.size);
+        let step = patch.size / segments;
+        let samples_per_side = segments + 1;
+        // Sink the surface a little so that wherever it overlaps with
+        // full-resolution chunk geometry, the chunk geometry wins.
+        let sink = self.spec.block_height * LOD_SINK_BLOCKS;
+        let land_color = globe.materials().get(Material::DIRT).color;
+        let water_color = globe.materials().get(Material::WATER).color;
+        let mut land_height_range = (f64::MAX, f64::MIN);
+        let first_vertex_index = vertex_data.len() as u32;
+        let mut surface_pt3s: Vec<Pt3> =
+            Vec::with_capacity(samples_per_side as usize * samples_per_side as usize);
+        for j in 0..samples_per_side {
+            for i in 0..samples_per_side {
+                let column = Point2::new(patch.root, patch.x + i * step, patch.y + j * step);
+                let land_height = globe.gen.land_height(column);
+                land_height_range.0 = land_height_range.0.min(land_height);
+                land_height_range.1 = land_height_range.1.max(land_height);
+                let (radius, color) = if land_height < self.spec.ocean_radius {
+                    (self.spec.ocean_radius, water_color)
+                } else {
+                    (land_height, land_color)
+                };
+                let surface_pt3 = self.spec.cell_center_on_unit_sphere(column) * (radius - sink);
+                surface_pt3s.push(surface_pt3);
+                vertex_data.push(render::Vertex::new_from_pt3(
+                    Pt3::from(surface_pt3 - patch_origin_pos),
+                    color,
+                ));
+            }
+        }
+        let sample_index =
+            |i: GridCoord, j: GridCoord| first_vertex_index + (j * samples_per_side + i) as u32;
+
+        // Two triangles per step in each direction, split along
+        // the diagonal that joins neighboring cells.
+        for j in 0..segments {
+            for i in 0..segments {
+                let a = sample_index(i, j);
+                let b = sample_index(i + 1, j);
+                let c = sample_index(i, j + 1);
+                let d = sample_index(i + 1, j + 1);
+                index_data.extend_from_slice(&[a, b, c, b, d, c]);
+            }
+        }
+
+        // Hang a skirt down from each edge to hide any cracks
+        // between this patch and neighboring patches with a different
+        // level of detail. Emit both sides, because which side faces
+        // outward depends on which edge it is.
+        let skirt_depth = patch.approx_width(&self.spec) / segments as f64;
+        let last = segments;
+        let edges: [Vec<(GridCoord, GridCoord)>; 4] = [
+            (0..=last).map(|i| (i, 0)).collect(),
+            (0..=last).map(|i| (i, last)).collect(),
+            (0..=last).map(|j| (0, j)).collect(),
+            (0..=last).map(|j| (last, j)).collect(),
+        ];
+        for edge in &edges {
+            let first_skirt_vertex_index = vertex_data.len() as u32;
+            for &(i, j) in edge {
+                let surface_pt3 = surface_pt3s[(j * samples_per_side + i) as usize];
+                let radius = surface_pt3.coords.norm();
+                let skirt_pt3 = surface_pt3 * ((radius - skirt_depth) / radius);
+                let mut color = vertex_data[sample_index(i, j) as usize].a_color;
+                for color_channel in &mut color {
+                    *color_channel *= 0.5;
+                }
+                vertex_data.push(render::Vertex::new_from_pt3(
+                    Pt3::from(skirt_pt3 - patch_origin_pos),
+                    color,
+                ));
+            }
+            for (k, pair) in edge.windows(2).enumerate() {
+                let a = sample_index(pair[0].0, pair[0].1);
+                let b = sample_index(pair[1].0, pair[1].1);
+                let c = first_skirt_vertex_index + k as u32;
+                let d = c + 1;
+                index_data.extend_from_slice(&[a, c, b, b, c, d, a, b, c, b, d, c]);
+            }
+        }
+
+        land_height_range
+    }
+
     fn cull_cell(&self, cursor: &Cursor<'_>) -> bool {
         use crate::grid::Neighbors;
 
@@ -207,13 +316,13 @@ impl View {
         let grid_point = cursor.pos();
         let mut neighbor_cursor = cursor.clone();
 
-        // If none of the neighboring cells contain air,
+        // If none of the neighboring cells are transparent,
         // then we won't render the cell at all.
         let neighbors = Neighbors::new(grid_point, resolution);
         for neighbor_pos in neighbors {
             neighbor_cursor.set_pos(neighbor_pos);
             if let Some(neighbor) = neighbor_cursor.cell() {
-                if neighbor.material == Material::Air {
+                if cursor.globe().materials().is_transparent(neighbor.material) {
                     // This cell can be seen; we can't cull it.
                     return false;
                 }
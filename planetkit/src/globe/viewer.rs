@@ -0,0 +1,70 @@
+use specs;
+use specs::{Entities, ReadStorage, WriteStorage};
+
+use crate::types::*;
+use crate::Spatial;
+
+/// How far away a `Viewer` can see chunks unless told otherwise.
+pub const DEFAULT_VIEW_DISTANCE: Real = 150.0;
+
+/// Something that the terrain around it needs to be drawn for,
+/// e.g., a camera.
+///
+/// `ChunkViewSystem` only makes `ChunkView`s for loaded chunks that are
+/// within `view_distance` of at least one `Viewer` on the same globe
+/// (as measured from the middle of the chunk). This is independent
+/// of which chunks are loaded; a server with no local player will
+/// have plenty of chunks loaded for simulation, but no `Viewer`s,
+/// and so it won't make any views at all.
+///
+/// Entities with a `Viewer` must also have a `Spatial`, so that
+/// we can tell where they are relative to the globe.
+pub struct Viewer {
+    pub view_distance: Real,
+}
+
+impl Viewer {
+    pub fn new(view_distance: Real) -> Viewer {
+        Viewer { view_distance }
+    }
+}
+
+impl Default for Viewer {
+    fn default() -> Viewer {
+        Viewer::new(DEFAULT_VIEW_DISTANCE)
+    }
+}
+
+impl specs::Component for Viewer {
+    type Storage = specs::HashMapStorage<Viewer>;
+}
+
+// Positions of all `Viewer`s relative to the given globe,
+// along with how far each of them can see.
+pub(crate) fn viewers_of_globe<'a>(
+    entities: &Entities<'a>,
+    viewers: &ReadStorage<'a, Viewer>,
+    spatials: &WriteStorage<'a, Spatial>,
+    globe_entity: specs::Entity,
+) -> Vec<(Pt3, Real)> {
+    use crate::spatial::SpatialStorage;
+    use specs::Join;
+
+    if spatials.get(globe_entity).is_none() {
+        return Vec::new();
+    }
+    (&**entities, viewers)
+        .join()
+        .filter(|(viewer_entity, _)| {
+            spatials.get(*viewer_entity).is_some()
+                && spatials.have_common_ancestor(*viewer_entity, globe_entity)
+        })
+        .map(|(viewer_entity, viewer)| {
+            let viewer_transform = spatials.a_relative_to_b(viewer_entity, globe_entity);
+            (
+                Pt3::from(viewer_transform.translation.vector),
+                viewer.view_distance,
+            )
+        })
+        .collect()
+}
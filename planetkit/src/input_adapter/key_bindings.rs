@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use piston::input::keyboard::Key;
+use piston::input::{Button, ButtonState, Input};
+use serde_json;
+
+/// Something a player can do by pressing a key.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    StepForward,
+    StepBackward,
+    TurnLeft,
+    TurnRight,
+    /// Dig up the block in front of the active `CellDweller`.
+    PickUp,
+    /// Put a block down; see `PlacementSystem`.
+    Place,
+    /// PlanetKit doesn't shoot anything itself;
+    /// this is for games that do, like Kaboom.
+    Shoot,
+}
+
+/// Which keys trigger which `Action`s.
+///
+/// Each action can have any number of keys bound to it, but each key
+/// can only be bound to one action at a time.
+///
+/// Stored as JSON mapping each action to a list of keys, e.g.,
+/// `{"StepForward": ["Up", "W"], "Shoot": ["Space"]}`.
+/// Any actions not mentioned have no keys bound.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct KeyBindings {
+    keys: BTreeMap<Action, Vec<Key>>,
+}
+
+impl KeyBindings {
+    /// Bindings with no keys bound to anything.
+    pub fn new_empty() -> KeyBindings {
+        KeyBindings {
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// Load bindings from a JSON file; see `KeyBindings` for the format.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<KeyBindings> {
+        let reader = BufReader::new(File::open(path)?);
+        let key_bindings: KeyBindings = serde_json::from_reader(reader)?;
+        // Make sure each key is bound to at most one action,
+        // even if the file says otherwise.
+        let mut cleaned = KeyBindings::new_empty();
+        for (&action, keys) in &key_bindings.keys {
+            for &key in keys {
+                if cleaned.action_for_key(key).is_none() {
+                    cleaned.bind(key, action);
+                }
+            }
+        }
+        Ok(cleaned)
+    }
+
+    /// Write bindings to a JSON file, e.g., after letting the player change them.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Bind `key` to `action`, unbinding it from anything else.
+    pub fn bind(&mut self, key: Key, action: Action) {
+        self.unbind(key);
+        self.keys.entry(action).or_default().push(key);
+    }
+
+    /// Make `key` do nothing.
+    pub fn unbind(&mut self, key: Key) {
+        for keys in self.keys.values_mut() {
+            keys.retain(|&bound_key| bound_key != key);
+        }
+        self.keys.retain(|_, keys| !keys.is_empty());
+    }
+
+    /// Unbind all keys from `action`.
+    pub fn clear(&mut self, action: Action) {
+        self.keys.remove(&action);
+    }
+
+    pub fn keys_for_action(&self, action: Action) -> &[Key] {
+        self.keys.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    pub fn action_for_key(&self, key: Key) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(&action, _)| action)
+    }
+
+    /// If `input_event` is a key being pressed or released, then find which
+    /// action that key is bound to, and whether it's now down.
+    pub fn action_for_input(&self, input_event: &Input) -> Option<(Action, bool)> {
+        if let Input::Button(button_args) = *input_event {
+            if let Button::Keyboard(key) = button_args.button {
+                let is_down = match button_args.state {
+                    ButtonState::Press => true,
+                    ButtonState::Release => false,
+                };
+                return self.action_for_key(key).map(|action| (action, is_down));
+            }
+        }
+        None
+    }
+}
+
+impl Default for KeyBindings {
+    /// Arrow keys, IJKL, or WASD to move around,
+    /// U to pick up blocks, O to place them, and space to shoot.
+    fn default() -> KeyBindings {
+        let mut key_bindings = KeyBindings::new_empty();
+        for &(forward, backward, left, right) in &[
+            (Key::Up, Key::Down, Key::Left, Key::Right),
+            (Key::I, Key::K, Key::J, Key::L),
+            (Key::W, Key::S, Key::A, Key::D),
+        ] {
+            key_bindings.bind(forward, Action::StepForward);
+            key_bindings.bind(backward, Action::StepBackward);
+            key_bindings.bind(left, Action::TurnLeft);
+            key_bindings.bind(right, Action::TurnRight);
+        }
+        key_bindings.bind(Key::U, Action::PickUp);
+        key_bindings.bind(Key::O, Action::Place);
+        key_bindings.bind(Key::Space, Action::Shoot);
+        key_bindings
+    }
+}
+
+/// `KeyBindings` shared between all the input adapters that use them,
+/// so that they can be changed while the game is running.
+///
+/// `AppBuilder` adds one of these to the world as a resource.
+#[derive(Clone, Default)]
+pub struct SharedKeyBindings {
+    key_bindings: Arc<Mutex<KeyBindings>>,
+}
+
+impl SharedKeyBindings {
+    pub fn new(key_bindings: KeyBindings) -> SharedKeyBindings {
+        SharedKeyBindings {
+            key_bindings: Arc::new(Mutex::new(key_bindings)),
+        }
+    }
+
+    /// Get at the bindings to read or change them.
+    pub fn lock(&self) -> MutexGuard<'_, KeyBindings> {
+        self.key_bindings
+            .lock()
+            .expect("Something panicked while holding the key bindings")
+    }
+
+    /// See `KeyBindings::action_for_input`.
+    pub fn action_for_input(&self, input_event: &Input) -> Option<(Action, bool)> {
+        self.lock().action_for_input(input_event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use piston::input::ButtonArgs;
+
+    use super::*;
+    use crate::test_util::TempPath;
+
+    #[test]
+    fn rebinding_moves_key_to_new_action() {
+        let mut key_bindings = KeyBindings::default();
+        assert_eq!(
+            key_bindings.action_for_key(Key::W),
+            Some(Action::StepForward)
+        );
+        assert_eq!(key_bindings.action_for_key(Key::F), None);
+
+        key_bindings.bind(Key::W, Action::Shoot);
+        assert_eq!(key_bindings.action_for_key(Key::W), Some(Action::Shoot));
+        assert_eq!(
+            key_bindings.keys_for_action(Action::StepForward),
+            &[Key::Up, Key::I]
+        );
+        assert_eq!(
+            key_bindings.keys_for_action(Action::Shoot),
+            &[Key::Space, Key::W]
+        );
+
+        key_bindings.clear(Action::Shoot);
+        assert_eq!(key_bindings.action_for_key(Key::Space), None);
+        assert!(key_bindings.keys_for_action(Action::Shoot).is_empty());
+
+        let release_up = Input::Button(ButtonArgs {
+            state: ButtonState::Release,
+            button: Button::Keyboard(Key::Up),
+            scancode: None,
+        });
+        assert_eq!(
+            key_bindings.action_for_input(&release_up),
+            Some((Action::StepForward, false))
+        );
+    }
+
+    #[test]
+    fn load_saved_key_bindings() {
+        let path = TempPath::new("key-bindings-test.json");
+
+        let mut key_bindings = KeyBindings::default();
+        key_bindings.bind(Key::F, Action::PickUp);
+        key_bindings.save(&path).unwrap();
+        assert_eq!(KeyBindings::load(&path).unwrap(), key_bindings);
+
+        // Keys bound to more than one action only get the first.
+        std::fs::write(&path, r#"{"TurnLeft": ["Q"], "TurnRight": ["E", "Q"]}"#).unwrap();
+        let loaded = KeyBindings::load(&path).unwrap();
+        assert_eq!(loaded.action_for_key(Key::Q), Some(Action::TurnLeft));
+        assert_eq!(loaded.keys_for_action(Action::TurnRight), &[Key::E]);
+        assert_eq!(loaded.action_for_key(Key::W), None);
+    }
+}
@@ -0,0 +1,14 @@
+mod key_bindings;
+
+pub use self::key_bindings::{Action, KeyBindings, SharedKeyBindings};
+
+use piston::input::Input;
+
+/// Handles Piston input events and dispatches them to systems.
+///
+/// Adapters for player controls should look up which `Action`
+/// each event is for in the game's `SharedKeyBindings`, rather than
+/// matching on particular keys, so that players can rebind them.
+pub trait InputAdapter {
+    fn handle(&self, input_event: &Input);
+}
@@ -0,0 +1,165 @@
+//! Recording player input to a file, and replaying it later,
+//! e.g., to reproduce bugs found in playtests, or in regression tests.
+//!
+//! A recording holds every input event along with the tick it arrived
+//! before, plus the seed needed to recreate the same world. Replaying it
+//! passes each event to the app's input adapters just before the same tick,
+//! so the systems see exactly the same messages over the same channels as
+//! they did the first time around. See `App::record_input` and
+//! `App::replay_input`.
+//!
+//! Replays will only turn out the same if everything else the systems do
+//! is deterministic, too. In particular, globes that build chunks in the
+//! background (see `Globe::build_chunks_in_background`) may make chunks
+//! available after a different number of ticks each time.
+//!
+//! Recordings are stored as JSON, one line per event, after a first line
+//! with the seed and tick rate.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use piston::input::Input;
+use serde_json;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Header {
+    seed: u64,
+    ticks_per_second: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct RecordedInput {
+    tick: u64,
+    input: Input,
+}
+
+/// Writes input events to a file as they happen.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    /// Start a new recording at `path`, replacing any file already there.
+    ///
+    /// `seed` should be whatever the game needs to recreate the same world;
+    /// usually the seed of its globe.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        seed: u64,
+        ticks_per_second: f64,
+    ) -> io::Result<InputRecorder> {
+        let mut recorder = InputRecorder {
+            writer: BufWriter::new(File::create(path)?),
+        };
+        recorder.write_line(&Header {
+            seed,
+            ticks_per_second,
+        })?;
+        Ok(recorder)
+    }
+
+    /// Record an input event that will be handled before the given tick is run.
+    pub fn record(&mut self, tick: u64, input: &Input) -> io::Result<()> {
+        self.write_line(&RecordedInput {
+            tick,
+            input: input.clone(),
+        })
+    }
+
+    // Flush as we go, so that we still have everything up to
+    // the point where the game crashed, if it does.
+    fn write_line<T: serde::Serialize>(&mut self, value: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+/// Input events loaded from a recording, waiting to be replayed.
+pub struct InputReplay {
+    seed: u64,
+    ticks_per_second: f64,
+    inputs: VecDeque<RecordedInput>,
+}
+
+impl InputReplay {
+    /// Load a whole recording made by `InputRecorder`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<InputReplay> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Input recording is empty",
+                ))
+            }
+        };
+        let mut inputs = VecDeque::new();
+        for line in lines {
+            inputs.push_back(serde_json::from_str(&line?)?);
+        }
+        Ok(InputReplay {
+            seed: header.seed,
+            ticks_per_second: header.ticks_per_second,
+            inputs,
+        })
+    }
+
+    /// Seed given when the recording was made; use it to recreate the same world.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Tick rate of the app that made the recording.
+    pub fn ticks_per_second(&self) -> f64 {
+        self.ticks_per_second
+    }
+
+    /// Whether every recorded input event has been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Take all the events that should be handled before the given tick is run.
+    pub fn take_inputs_for_tick(&mut self, tick: u64) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        while self.inputs.front().is_some_and(|input| input.tick <= tick) {
+            let recorded = self.inputs.pop_front().expect("We just checked it's there");
+            inputs.push(recorded.input);
+        }
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use piston::input::{ButtonState, Key};
+
+    use super::*;
+    use crate::test_util::{key_input, TempPath};
+
+    #[test]
+    fn recording_round_trip() {
+        let path = TempPath::new("input-recording-test.jsonl");
+        let press = key_input(Key::Up, ButtonState::Press);
+        let release = key_input(Key::Up, ButtonState::Release);
+        {
+            let mut recorder = InputRecorder::create(&path, 1234, 30.0).unwrap();
+            recorder.record(0, &press).unwrap();
+            recorder.record(3, &release).unwrap();
+            recorder.record(3, &press).unwrap();
+        }
+
+        let mut replay = InputReplay::open(&path).unwrap();
+        assert_eq!(replay.seed(), 1234);
+        assert_eq!(replay.ticks_per_second(), 30.0);
+        assert_eq!(replay.take_inputs_for_tick(0), vec![press.clone()]);
+        assert!(replay.take_inputs_for_tick(2).is_empty());
+        assert_eq!(replay.take_inputs_for_tick(3), vec![release, press]);
+        assert!(replay.is_finished());
+    }
+}
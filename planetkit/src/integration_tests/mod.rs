@@ -1 +1,2 @@
 mod random_walk;
+mod replay;
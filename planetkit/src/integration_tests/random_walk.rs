@@ -73,7 +73,7 @@ impl Walker {
             let globe = globes
                 .get_mut(globe_entity)
                 .expect("Uh oh, where did our Globe go?");
-            globe.find_lowest_cell_containing(guy_pos, Material::Air)
+            globe.find_lowest_cell_containing(guy_pos, Material::AIR)
         };
 
         let guy_entities: Vec<_> = (0..walker_count)
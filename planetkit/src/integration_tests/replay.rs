@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use piston::input::{ButtonState, Key};
+use specs::{self, Builder, Write};
+
+use crate::app::App;
+use crate::cell_dweller::{self, CellDweller, Inventory, ItemKind};
+use crate::globe::chunk::{Cell, Material};
+use crate::globe::{self, ChunkOrigin};
+use crate::grid::{Dir, Point3};
+use crate::input_adapter::SharedKeyBindings;
+use crate::input_recording::InputReplay;
+use crate::net::{Destination, EntityIds, NetMarker, NodeResource, PeerId, RecvMessage};
+use crate::test_util::{key_input, TempPath};
+
+// Stands in for the network, for a game where we are the master
+// and there is nobody else to talk to.
+struct LoopbackSystem;
+
+impl<'a> specs::System<'a> for LoopbackSystem {
+    type SystemData = (
+        Write<'a, cell_dweller::SendMessageQueue>,
+        Write<'a, cell_dweller::RecvMessageQueue>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut send_message_queue, mut recv_message_queue) = data;
+        while let Some(message) = send_message_queue.queue.pop_front() {
+            match message.destination {
+                Destination::Master
+                | Destination::EveryoneIncludingSelf
+                | Destination::One(PeerId(0)) => {
+                    recv_message_queue.queue.push_back(RecvMessage {
+                        source: PeerId(0),
+                        game_message: message.game_message,
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+// Make a headless app with a player character standing on a small globe,
+// that takes input through the usual input adapters.
+fn make_app() -> (App, specs::Entity) {
+    // Log to nowhere.
+    let drain = slog::Discard;
+    let root_log = slog::Logger::root(drain, o!("pk_version" => env!("CARGO_PKG_VERSION")));
+
+    let mut world = specs::World::new();
+    world.register::<CellDweller>();
+    world.register::<Inventory>();
+    world.register::<crate::Spatial>();
+    world.register::<globe::Globe>();
+    world.register::<NetMarker>();
+
+    let (movement_input_sender, movement_input_receiver) = mpsc::channel();
+    let (mining_input_sender, mining_input_receiver) = mpsc::channel();
+    let (placement_input_sender, placement_input_receiver) = mpsc::channel();
+    let dispatcher_builder = specs::DispatcherBuilder::new()
+        .with(
+            cell_dweller::MovementSystem::new(movement_input_receiver, &root_log),
+            "cd_movement",
+            &[],
+        )
+        .with(
+            cell_dweller::MiningSystem::new(mining_input_receiver, &root_log),
+            "cd_mining",
+            &["cd_movement"],
+        )
+        .with(
+            cell_dweller::PlacementSystem::new(placement_input_receiver, &root_log),
+            "cd_placement",
+            &["cd_mining"],
+        )
+        .with_barrier()
+        .with(LoopbackSystem, "loopback", &[])
+        .with_barrier()
+        .with(cell_dweller::RecvSystem::new(&root_log), "cd_recv", &[])
+        .with_barrier()
+        .with(
+            cell_dweller::PhysicsSystem::new(&root_log, 0.1),
+            "cd_physics",
+            &[],
+        )
+        .with(globe::ChunkSystem::new(&root_log), "chunk", &[]);
+
+    // Build chunks on this thread, so that they're always
+    // ready after the same number of ticks.
+    let globe = globe::Globe::new_example();
+    let globe_spec = globe.spec();
+    let globe_entity = world.create_entity().with(globe).build();
+    let guy_pos = {
+        let mut globes = world.write_storage::<globe::Globe>();
+        let globe = globes
+            .get_mut(globe_entity)
+            .expect("Uh oh, where did our Globe go?");
+        globe.find_lowest_cell_containing(Point3::default(), Material::AIR)
+    };
+    // Bring some blocks to build with.
+    let mut inventory = Inventory::default();
+    inventory.add(ItemKind::Block(Material::DIRT), 5);
+    let guy_entity = world
+        .create_entity()
+        .with(CellDweller::new(
+            guy_pos,
+            Dir::default(),
+            globe_spec,
+            Some(globe_entity),
+        ))
+        .with(inventory)
+        .with(crate::Spatial::new_root())
+        .with(NetMarker { id: 1 })
+        .build();
+
+    let mut app = App::new_headless(&root_log, world, dispatcher_builder);
+    app.world_mut()
+        .write_resource::<cell_dweller::ActiveCellDweller>()
+        .maybe_entity = Some(guy_entity);
+    {
+        // Dig and build by asking the master, which is us.
+        let world = app.world_mut();
+        world
+            .write_resource::<EntityIds>()
+            .mapping
+            .insert(1, guy_entity);
+        world.write_resource::<NodeResource>().is_master = true;
+        world
+            .write_resource::<cell_dweller::SendMessageQueue>()
+            .has_consumer = true;
+    }
+    let key_bindings = SharedKeyBindings::default();
+    app.add_input_adapter(Box::new(cell_dweller::MovementInputAdapter::new(
+        movement_input_sender,
+        key_bindings.clone(),
+    )));
+    app.add_input_adapter(Box::new(cell_dweller::MiningInputAdapter::new(
+        mining_input_sender,
+        key_bindings.clone(),
+    )));
+    app.add_input_adapter(Box::new(cell_dweller::PlacementInputAdapter::new(
+        placement_input_sender,
+        key_bindings,
+    )));
+    (app, guy_entity)
+}
+
+fn guy_state(app: &mut App, guy_entity: specs::Entity) -> (Point3, Dir, Inventory) {
+    let world = app.world_mut();
+    let cell_dwellers = world.read_storage::<CellDweller>();
+    let inventories = world.read_storage::<Inventory>();
+    let cd = cell_dwellers.get(guy_entity).unwrap();
+    (cd.pos, cd.dir, inventories.get(guy_entity).unwrap().clone())
+}
+
+// Every cell in every chunk that has been changed since it was generated.
+fn modified_cells(app: &mut App) -> HashMap<ChunkOrigin, Vec<Cell>> {
+    use crate::globe::GlobeGuts;
+    use specs::Join;
+
+    let world = app.world_mut();
+    let globes = world.read_storage::<globe::Globe>();
+    let globe = globes.join().next().expect("Should have a globe");
+    globe
+        .chunks()
+        .values()
+        .filter(|chunk| chunk.is_modified)
+        .map(|chunk| (chunk.origin, chunk.cells.to_vec()))
+        .collect()
+}
+
+#[test]
+fn replay_reproduces_recorded_game() {
+    let path = TempPath::new("replay-test.jsonl");
+
+    // Wander around and dig a bit, recording as we go.
+    let (mut app, guy_entity) = make_app();
+    let start_state = guy_state(&mut app, guy_entity);
+    let seed = globe::Globe::new_example().spec().seed;
+    app.record_input(&path, seed).unwrap();
+    let script = [
+        (Key::Up, 40),
+        (Key::Left, 7),
+        (Key::U, 1),
+        (Key::O, 1),
+        (Key::W, 25),
+        (Key::D, 13),
+        (Key::U, 1),
+        (Key::O, 1),
+        (Key::Up, 30),
+    ];
+    let mut ticks = 0;
+    for &(key, ticks_held) in &script {
+        app.handle_input(&key_input(key, ButtonState::Press));
+        app.run_ticks(ticks_held);
+        app.handle_input(&key_input(key, ButtonState::Release));
+        app.run_ticks(2);
+        ticks += ticks_held + 2;
+    }
+    let recorded_state = guy_state(&mut app, guy_entity);
+    assert_ne!(recorded_state.0, start_state.0);
+    let recorded_cells = modified_cells(&mut app);
+    assert!(!recorded_cells.is_empty(), "Should have built something");
+
+    // Play it all back in a fresh world.
+    let (mut app, guy_entity) = make_app();
+    let replay = InputReplay::open(&path).unwrap();
+    assert_eq!(replay.seed(), seed);
+    app.replay_input(replay);
+    app.run_ticks(ticks);
+    assert_eq!(guy_state(&mut app, guy_entity), recorded_state);
+    assert_eq!(modified_cells(&mut app), recorded_cells);
+}
@@ -66,6 +66,7 @@ pub mod camera;
 pub mod cell_dweller;
 pub mod globe;
 pub mod input_adapter;
+pub mod input_recording;
 pub mod net;
 pub mod physics;
 pub mod render;
@@ -80,8 +81,13 @@ pub use crate::spatial::SpatialStorage;
 mod log_resource;
 pub use crate::log_resource::LogResource;
 
+mod quit_resource;
+pub use crate::quit_resource::QuitResource;
+
 mod app_builder;
 pub use crate::app_builder::AppBuilder;
 
 #[cfg(test)]
 mod integration_tests;
+#[cfg(test)]
+mod test_util;
@@ -191,7 +191,10 @@ pub struct NetworkPeer<G> {
     pub id: PeerId,
     pub tcp_sender: futures::sync::mpsc::Sender<WireMessage<G>>,
     pub socket_addr: SocketAddr,
-    // TODO: connection state, etc.
+    // Cleared when the peer says goodbye, or the TCP connection closes.
+    // We keep the peer around so that its ID still means something.
+    pub connected: bool,
+    // TODO: more connection state, etc.
 }
 
 /// `World`-global resource for network peers.
@@ -202,6 +205,8 @@ pub struct NetworkPeers<G> {
     // TODO: This makes yet another good use case for some kind
     // of pub/sub event system.
     pub new_peers: VecDeque<PeerId>,
+    // Likewise for peers that have disconnected.
+    pub disconnected_peers: VecDeque<PeerId>,
 }
 
 // `derive(Default)` doesn't seem to work here.
@@ -212,6 +217,7 @@ impl<G: GameMessage> Default for NetworkPeers<G> {
         NetworkPeers {
             peers: Vec::<NetworkPeer<G>>::new(),
             new_peers: VecDeque::<PeerId>::new(),
+            disconnected_peers: VecDeque::<PeerId>::new(),
         }
     }
 }
@@ -260,3 +266,14 @@ pub struct NodeResource {
     // when hosting/joining a game.
     pub is_master: bool,
 }
+
+impl NodeResource {
+    /// Whether a message from the given peer came from the master,
+    /// and can therefore be trusted to tell us what happened.
+    ///
+    /// The master only trusts itself. Clients are only ever connected
+    /// to the master, so they trust anything they receive.
+    pub fn is_from_master(&self, source: PeerId) -> bool {
+        !self.is_master || source == PeerId(0)
+    }
+}
@@ -54,6 +54,7 @@ where
                         id: next_peer_id,
                         tcp_sender: new_peer.tcp_sender,
                         socket_addr: new_peer.socket_addr,
+                        connected: true,
                     };
                     network_peers.peers.push(peer);
 
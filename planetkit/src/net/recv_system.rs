@@ -2,7 +2,7 @@ use std::sync::mpsc;
 
 use slog::Logger;
 use specs;
-use specs::{Read, Write};
+use specs::Write;
 
 use super::{
     GameMessage, NetworkPeers, RecvMessage, RecvMessageQueue, RecvWireMessage, WireMessage,
@@ -40,10 +40,10 @@ impl<'a, G> specs::System<'a> for RecvSystem<G>
 where
     G: GameMessage,
 {
-    type SystemData = (Write<'a, RecvMessageQueue<G>>, Read<'a, NetworkPeers<G>>);
+    type SystemData = (Write<'a, RecvMessageQueue<G>>, Write<'a, NetworkPeers<G>>);
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut recv_message_queue, network_peers) = data;
+        let (mut recv_message_queue, mut network_peers) = data;
 
         // Slurp everything the server sent us.
         loop {
@@ -68,20 +68,29 @@ where
             // TODO: ruh roh, what if two clients connect from the same IP?
             // We need to make peers always identify themselves in every message,
             // (and then use the HMAC to validate identity and message).
-            let peer_id = match network_peers
+            let peer = match network_peers
                 .peers
-                .iter()
-                .find(|peer| peer.socket_addr == src)
+                .iter_mut()
+                .find(|peer| peer.connected && peer.socket_addr == src)
             {
-                Some(peer) => peer.id,
+                Some(peer) => peer,
                 None => {
                     warn!(self.log, "Got message from address we don't recognise; did they disconnect"; "peer_addr" => format!("{:?}", src), "message" => format!("{:?}", message));
                     continue;
                 }
             };
 
+            let peer_id = peer.id;
+
             let game_message = match message {
                 WireMessage::Game(game_message) => game_message,
+                WireMessage::Goodbye => {
+                    info!(self.log, "Peer disconnected"; "peer_id" => peer_id.0, "peer_addr" => format!("{:?}", src));
+                    peer.connected = false;
+                    // Let game-specific systems clean up after them.
+                    network_peers.disconnected_peers.push_back(peer_id);
+                    continue;
+                }
                 _ => {
                     warn!(
                         self.log,
@@ -35,6 +35,12 @@ where
         dest_peer: &mut NetworkPeer<G>,
         transport: Transport,
     ) {
+        // There's nobody listening any more; see `NetworkPeer::connected`.
+        if !dest_peer.connected {
+            trace!(self.log, "Not sending message to disconnected peer"; "peer_id" => dest_peer.id.0);
+            return;
+        }
+
         // Decide whether the message should go over TCP or UDP.
         match transport {
             Transport::UDP => {
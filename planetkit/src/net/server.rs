@@ -1,5 +1,5 @@
 use std;
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::thread;
 
 use futures;
@@ -57,7 +57,17 @@ impl<G: GameMessage> Server<G> {
         }
     }
 
+    /// Listen on all network interfaces. See `start_listen_on`.
     pub fn start_listen<MaybePort>(&mut self, port: MaybePort)
+    where
+        MaybePort: Into<Option<u16>>,
+    {
+        self.start_listen_on(Ipv4Addr::UNSPECIFIED.into(), port);
+    }
+
+    /// Listen for TCP connections and UDP messages at the given
+    /// address, and on the given port, or any free port if `None`.
+    pub fn start_listen_on<MaybePort>(&mut self, ip: IpAddr, port: MaybePort)
     where
         MaybePort: Into<Option<u16>>,
     {
@@ -66,6 +76,7 @@ impl<G: GameMessage> Server<G> {
             self.recv_system_sender.clone(),
             self.send_system_new_peer_sender.clone(),
             self.remote.clone(),
+            ip,
             port,
         )
         .into();
@@ -76,6 +87,7 @@ impl<G: GameMessage> Server<G> {
                 .take()
                 .expect("Somebody else took it!"),
             self.remote.clone(),
+            ip,
             self.port,
         );
     }
@@ -98,6 +110,7 @@ impl<G: GameMessage> Server<G> {
                 .take()
                 .expect("Somebody else took it!"),
             self.remote.clone(),
+            Ipv4Addr::UNSPECIFIED.into(),
             local_port,
         );
     }
@@ -1,7 +1,7 @@
 use std;
 use std::io;
 use std::mem::size_of;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::result::Result;
 
 use bytes::{BigEndian, ByteOrder, BytesMut};
@@ -131,6 +131,7 @@ pub fn start_tcp_server<G: GameMessage, MaybePort>(
     // to send messages to those connections.
     send_system_new_peer_sender: std::sync::mpsc::Sender<NewPeer<G>>,
     remote: Remote,
+    ip: IpAddr,
     port: MaybePort,
 ) -> u16
 where
@@ -145,8 +146,7 @@ where
     let (actual_port_tx, actual_port_rx) = std::sync::mpsc::channel::<u16>();
 
     // Pick a random port if none was specified.
-    let addr = format!("0.0.0.0:{}", port.into().unwrap_or(0));
-    let addr = addr.parse::<SocketAddr>().unwrap();
+    let addr = SocketAddr::new(ip, port.into().unwrap_or(0));
 
     // Run reactor on its own thread so we can always be receiving messages
     // from peers, and buffer them up until we're ready to process them.
@@ -304,9 +304,10 @@ fn handle_tcp_stream<G: GameMessage>(
     // Receiver future
     let peer_server_log = parent_log.new(o!("peer_addr" => format!("{}", peer_addr)));
     let peer_server_error_log = peer_server_log.clone();
+    let goodbye_sender = recv_system_sender.clone();
     // First wait for the RecvSystem to signal that it's registered
     // the peer and is ready to receive.
-    let f = rtr_rx.then(|_| {
+    let f = rtr_rx.then(move |_| {
         stream
             .filter(|recv_wire_message| {
                 // TODO: log
@@ -334,6 +335,16 @@ fn handle_tcp_stream<G: GameMessage>(
                 info!(peer_server_error_log, "Peer broke pipe"; "error" => format!("{}", error));
                 futures::future::ok(())
             })
+            .then(move |_: Result<(), std::io::Error>| {
+                // Either way, the connection is closed now. Say goodbye on
+                // the peer's behalf so the RecvSystem knows they're gone.
+                // (Ignore errors; we might be shutting down.)
+                let _ = goodbye_sender.send(RecvWireMessage {
+                    src: peer_addr,
+                    message: Ok(WireMessage::Goodbye),
+                });
+                futures::future::ok(())
+            })
     });
     Box::new(f)
 }
@@ -343,6 +354,7 @@ mod tests {
     use super::*;
 
     use std;
+    use std::net::Ipv4Addr;
     use std::thread;
 
     use bytes::BufMut;
@@ -380,7 +392,14 @@ mod tests {
         let log = slog::Logger::root(drain, o!("pk_version" => env!("CARGO_PKG_VERSION")));
         let (tx, rx) = std::sync::mpsc::channel::<RecvWireMessage<TestMessage>>();
         let (new_peer_tx, _new_peer_rx) = std::sync::mpsc::channel::<NewPeer<TestMessage>>();
-        let server_port = start_tcp_server(&log, tx, new_peer_tx, remote, None);
+        let server_port = start_tcp_server(
+            &log,
+            tx,
+            new_peer_tx,
+            remote,
+            Ipv4Addr::UNSPECIFIED.into(),
+            None,
+        );
 
         // Connect to server.
         let connect_addr = format!("127.0.0.1:{}", server_port);
@@ -446,7 +465,14 @@ mod tests {
         let log = slog::Logger::root(drain, o!("pk_version" => env!("CARGO_PKG_VERSION")));
         let (tx, rx) = std::sync::mpsc::channel::<RecvWireMessage<TestMessage>>();
         let (new_peer_tx, new_peer_rx) = std::sync::mpsc::channel::<NewPeer<TestMessage>>();
-        let server_port = start_tcp_server(&log, tx, new_peer_tx, remote, None);
+        let server_port = start_tcp_server(
+            &log,
+            tx,
+            new_peer_tx,
+            remote,
+            Ipv4Addr::UNSPECIFIED.into(),
+            None,
+        );
 
         // Connect to server.
         let connect_addr = format!("127.0.0.1:{}", server_port);
@@ -504,6 +530,12 @@ mod tests {
             recv_wire_message.message,
             Ok(WireMessage::Game(TestMessage {}))
         );
+        // We dropped our end of the connection after writing to it,
+        // so the server should say goodbye on our behalf.
+        let recv_wire_message = rx
+            .recv_timeout(blink)
+            .expect("Should have found a goodbye on the channel");
+        assert_eq!(recv_wire_message.message, Ok(WireMessage::Goodbye));
         // There shouldn't be any more messages on the channel.
         assert_eq!(rx.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty));
 
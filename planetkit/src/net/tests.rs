@@ -330,3 +330,59 @@ fn server_sends_tcp_messages_to_client() {
     // TODO: gracefully shut down the server before the end of all tests;
     // you don't want to leave the thread hanging around awkwardly.
 }
+
+#[test]
+fn nothing_is_sent_to_disconnected_peers() {
+    use futures::Stream;
+
+    let mut node = Node::new();
+
+    // Stand in for the TCP connections to two peers,
+    // one of which has since disconnected.
+    let (connected_tx, connected_rx) = futures::sync::mpsc::channel(10);
+    let (disconnected_tx, disconnected_rx) = futures::sync::mpsc::channel(10);
+    {
+        let peers = &mut node
+            .world
+            .write_resource::<NetworkPeers<TestMessage>>()
+            .peers;
+        peers.push(NetworkPeer {
+            id: PeerId(1),
+            tcp_sender: connected_tx,
+            socket_addr: "127.0.0.1:1".parse().unwrap(),
+            connected: true,
+        });
+        peers.push(NetworkPeer {
+            id: PeerId(2),
+            tcp_sender: disconnected_tx,
+            socket_addr: "127.0.0.1:2".parse().unwrap(),
+            connected: false,
+        });
+    }
+
+    let destinations = vec![
+        Destination::One(PeerId(2)),
+        Destination::EveryoneElse,
+        Destination::EveryoneElseExcept(PeerId(3)),
+        Destination::Master,
+        Destination::EveryoneIncludingSelf,
+    ];
+    for destination in destinations {
+        node.enqueue_message(SendMessage {
+            destination,
+            game_message: TestMessage {
+                disposition: "Lonely".to_string(),
+            },
+            transport: Transport::TCP,
+        });
+    }
+    node.dispatch();
+
+    // Dropping the node drops the senders,
+    // so we can collect everything that was sent.
+    drop(node);
+    let sent_to_connected: Vec<_> = connected_rx.wait().collect();
+    let sent_to_disconnected: Vec<_> = disconnected_rx.wait().collect();
+    assert_eq!(sent_to_connected.len(), 4);
+    assert!(sent_to_disconnected.is_empty());
+}
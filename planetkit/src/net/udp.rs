@@ -1,6 +1,6 @@
 use std;
 use std::io;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::result::Result;
 use std::sync::mpsc;
 
@@ -66,6 +66,7 @@ pub fn start_udp_server<G: GameMessage, MaybePort>(
     recv_system_sender: mpsc::Sender<RecvWireMessage<G>>,
     send_system_udp_receiver: sync::mpsc::Receiver<SendWireMessage<G>>,
     remote: Remote,
+    ip: IpAddr,
     port: MaybePort,
 ) -> u16
 where
@@ -80,8 +81,7 @@ where
     let (actual_port_tx, actual_port_rx) = std::sync::mpsc::channel::<u16>();
 
     // Pick a random port if none was specified.
-    let addr = format!("0.0.0.0:{}", port.into().unwrap_or(0));
-    let addr = addr.parse::<SocketAddr>().unwrap();
+    let addr = SocketAddr::new(ip, port.into().unwrap_or(0));
 
     // Run reactor on its own thread so we can always be receiving messages
     // from peers, and buffer them up until we're ready to process them.
@@ -152,6 +152,7 @@ mod tests {
     use super::*;
 
     use std;
+    use std::net::Ipv4Addr;
     use std::thread;
     use std::time::Duration;
 
@@ -190,7 +191,14 @@ mod tests {
         // Tiny buffer is fine for test. Someone else can figure out how
         // big is reasonable in the real world.
         let (_send_tx, send_rx) = sync::mpsc::channel::<SendWireMessage<TestMessage>>(10);
-        let server_port = start_udp_server(&log, recv_tx, send_rx, remote, None);
+        let server_port = start_udp_server(
+            &log,
+            recv_tx,
+            send_rx,
+            remote,
+            Ipv4Addr::UNSPECIFIED.into(),
+            None,
+        );
 
         // Bind socket for sending message.
         let addr = "0.0.0.0:0".to_string();
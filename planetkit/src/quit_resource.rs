@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Set by the signal handler installed by `QuitResource::quit_on_signals`.
+// Signal handlers can't capture anything, so this has to be global.
+static QUIT_SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// `World`-global resource for asking a running `App` to stop
+/// after its current update, saving anything that needs saving.
+///
+/// Clones all share the same flag, so a clone can be handed
+/// to another thread to stop the app from there.
+#[derive(Clone, Default)]
+pub struct QuitResource {
+    quit: Arc<AtomicBool>,
+}
+
+impl QuitResource {
+    pub fn quit(&self) {
+        self.quit.store(true, Ordering::SeqCst);
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit.load(Ordering::SeqCst) || QUIT_SIGNALLED.load(Ordering::SeqCst)
+    }
+
+    /// Quit on the first SIGINT (e.g., Ctrl-C) or SIGTERM.
+    /// A second one kills the process as usual, in case quitting gets stuck.
+    ///
+    /// Does nothing on platforms without Unix signals.
+    pub fn quit_on_signals() {
+        #[cfg(unix)]
+        unsafe {
+            let handler: extern "C" fn(libc::c_int) = handle_quit_signal;
+            libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+            libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+        }
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle_quit_signal(signal: libc::c_int) {
+    // Only async-signal-safe things in here.
+    QUIT_SIGNALLED.store(true, Ordering::SeqCst);
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
@@ -118,6 +118,10 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> System<R, C> {
                 continue;
             }
 
+            if visual.is_hidden() {
+                continue;
+            }
+
             // Visual might not have its mesh created yet.
             let mesh_pointer = match visual.mesh_pointer() {
                 Some(mesh_pointer) => mesh_pointer,
@@ -15,6 +15,8 @@ pub struct Visual {
     // actual mesh whenever this is present.
     // TODO: privacy
     pub proto_mesh: Option<ProtoMesh>,
+    // Hidden visuals keep their mesh, but aren't drawn.
+    hidden: bool,
 }
 
 impl Visual {
@@ -22,6 +24,7 @@ impl Visual {
         Visual {
             mesh_pointer: None,
             proto_mesh: None,
+            hidden: false,
         }
     }
 
@@ -32,6 +35,25 @@ impl Visual {
     pub fn set_mesh_pointer(&mut self, new_mesh_pointer: froggy::Pointer<MeshWrapper>) {
         self.mesh_pointer = new_mesh_pointer.into();
     }
+
+    /// Let go of this visual's mesh, if it has one.
+    ///
+    /// The mesh itself is destroyed the next time the `MeshRepository`
+    /// collects garbage, as long as nothing else still points to it.
+    pub fn release_mesh(&mut self) {
+        self.mesh_pointer = None;
+        self.proto_mesh = None;
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Stop (or resume) drawing this visual, without
+    /// throwing away its mesh.
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
 }
 
 impl specs::Component for Visual {
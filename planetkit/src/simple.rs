@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use specs;
 use specs::{Builder, Entities, LazyUpdate, Read};
 
@@ -6,6 +8,7 @@ use crate::cell_dweller;
 use crate::globe;
 use crate::render;
 use crate::types::*;
+use crate::LogResource;
 
 // TODO: Retire most of this stuff. Or, rather, turn it into
 // a module (eventually split out into a separate crate) of easy-to-combine
@@ -17,13 +20,82 @@ use crate::types::*;
 // load/save, etc.)
 
 pub fn populate_world(world: &mut specs::World) {
-    let globe_entity = create_simple_globe_now(world);
+    let globe_entity = create_simple_globe_now(world, SimpleTerrain::HeightMap, None);
     let player_character_entity = create_simple_player_character_now(world, globe_entity);
     create_simple_chase_camera_now(world, player_character_entity);
 }
 
-pub fn create_simple_globe_now(world: &mut specs::World) -> specs::Entity {
-    let globe = globe::Globe::new_earth_scale_example();
+/// Which world generator `create_simple_globe_now` should use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimpleTerrain {
+    /// Rolling hills and oceans from a single height map; see `globe::SimpleGen`.
+    HeightMap,
+    /// The same hills and oceans, but carved from 3D noise so that
+    /// there are caves and overhangs; see `globe::SimpleGen::new_with_density`.
+    Density,
+    /// Continents, mountains, biomes, rock strata, caves, and so on;
+    /// see `globe::LayeredGen`. Registers the extra materials it needs
+    /// if the game hasn't already.
+    Layered,
+}
+
+/// Create an earth-sized globe, generating terrain with `terrain`.
+///
+/// If `save_dir` is given, then changes to the terrain are kept between
+/// runs in a subdirectory of it named after the globe's seed and terrain,
+/// which is created if it doesn't exist yet. Otherwise nothing is
+/// written to disk, and changes are lost when chunks are unloaded.
+pub fn create_simple_globe_now(
+    world: &mut specs::World,
+    terrain: SimpleTerrain,
+    save_dir: Option<&Path>,
+) -> specs::Entity {
+    use std::sync::Arc;
+
+    // Use any custom materials the game has registered.
+    let mut materials = world
+        .res
+        .try_fetch::<globe::MaterialRegistry>()
+        .map(|materials| materials.clone())
+        .unwrap_or_default();
+    let spec = globe::Spec::new_earth_scale_example();
+    let mut globe = match terrain {
+        SimpleTerrain::HeightMap => globe::Globe::new_with_materials(spec, Arc::new(materials)),
+        SimpleTerrain::Density => {
+            let gen =
+                globe::SimpleGen::new_with_density(spec, globe::DensityParams::from_spec(&spec));
+            globe::Globe::new_with_gen(spec, Arc::new(materials), Box::new(gen))
+        }
+        SimpleTerrain::Layered => {
+            globe::GenMaterials::register(&mut materials);
+            let gen = globe::LayeredGen::new(spec, &materials);
+            globe::Globe::new_with_gen(spec, Arc::new(materials), Box::new(gen))
+        }
+    };
+    // Building chunks as the player walks around the earth-scale
+    // globe is slow enough to cause noticeable hitches.
+    globe.build_chunks_in_background(2);
+
+    if let Some(save_dir) = save_dir {
+        let spec = globe.spec();
+        // Keep chunks from different generators apart, so a globe never
+        // loads terrain that it didn't generate.
+        let globe_dir = match terrain {
+            SimpleTerrain::HeightMap => save_dir.join(format!("globe-{}", spec.seed)),
+            SimpleTerrain::Density => save_dir.join(format!("globe-{}-density", spec.seed)),
+            SimpleTerrain::Layered => save_dir.join(format!("globe-{}-layered", spec.seed)),
+        };
+        match globe::ChunkStorage::new(&globe_dir, spec.chunk_resolution) {
+            Ok(storage) => globe.set_storage(storage),
+            Err(err) => {
+                if let Some(log_resource) = world.res.try_fetch::<LogResource>() {
+                    warn!(log_resource.log, "Couldn't create directory to save chunks in; changes to terrain will be lost";
+                        "dir" => format!("{}", globe_dir.display()),
+                        "error" => format!("{}", err));
+                }
+            }
+        }
+    }
     world
         .create_entity()
         .with(globe)
@@ -71,6 +143,7 @@ pub fn create_simple_player_character_now(
             globe_spec,
             Some(globe_entity),
         ))
+        .with(cell_dweller::Inventory::default())
         .with(player_character_visual)
         // The CellDweller's transformation will be set based
         // on its coordinates in cell space.
@@ -97,6 +170,8 @@ pub fn create_simple_chase_camera_now(
             player_character_entity,
             camera_transform,
         ))
+        // Draw the terrain around the camera.
+        .with(crate::globe::Viewer::default())
         .build();
     use crate::camera::DefaultCamera;
     // TODO: gah, where does this belong?
@@ -121,6 +196,31 @@ pub fn create_simple_chase_camera(
         entity,
         crate::Spatial::new(player_character_entity, camera_transform),
     );
+    // Draw the terrain around the camera.
+    updater.insert(entity, crate::globe::Viewer::default());
     default_camera.camera_entity = Some(entity);
     entity
 }
+
+#[cfg(test)]
+mod tests {
+    use specs;
+
+    use super::*;
+    use crate::globe::Globe;
+
+    #[test]
+    fn layered_globe_registers_its_materials() {
+        let mut world = specs::World::new();
+        world.register::<Globe>();
+        world.register::<crate::Spatial>();
+        let globe_entity = create_simple_globe_now(&mut world, SimpleTerrain::Layered, None);
+        let globes = world.read_storage::<Globe>();
+        let globe = globes
+            .get(globe_entity)
+            .expect("Globe should have been created");
+        for name in &["stone", "sand", "snow"] {
+            assert!(globe.materials().find(name).is_some(), "missing {}", name);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use piston::input::{Button, ButtonArgs, ButtonState, Input, Key};
+
+use crate::globe::chunk::{Cell, Material};
+use crate::globe::{ChunkOrigin, Gen, Globe, MaterialRegistry, Spec};
+use crate::grid::{GridCoord, Point2, Point3};
+
+/// A file or directory path in the system temp directory that is
+/// unique to this test process, and deleted when dropped,
+/// even if the test panics.
+pub struct TempPath {
+    path: PathBuf,
+}
+
+impl TempPath {
+    /// Make a path ending in `name`, removing anything left
+    /// over there from an earlier run.
+    pub fn new(name: &str) -> TempPath {
+        let temp_path = TempPath {
+            path: std::env::temp_dir().join(format!("planetkit-{}-{}", std::process::id(), name)),
+        };
+        temp_path.remove();
+        temp_path
+    }
+
+    fn remove(&self) {
+        // It's fine if there was nothing there.
+        let _ = if self.path.is_dir() {
+            fs::remove_dir_all(&self.path)
+        } else {
+            fs::remove_file(&self.path)
+        };
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A key being pressed or released.
+pub fn key_input(key: Key, state: ButtonState) -> Input {
+    Input::Button(ButtonArgs {
+        state,
+        button: Button::Keyboard(key),
+        scancode: None,
+    })
+}
+
+/// Everything below this layer of a `flat_example_globe`
+/// is dirt, and everything above it is air.
+pub const FLAT_SURFACE_Z: GridCoord = 10;
+
+struct FlatGen {
+    spec: Spec,
+}
+
+impl Gen for FlatGen {
+    fn land_height(&self, _column: Point2) -> f64 {
+        self.spec.floor_radius + self.spec.block_height * FLAT_SURFACE_Z as f64
+    }
+
+    fn spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    fn cell_at(&self, grid_point: Point3) -> Cell {
+        let material = if grid_point.z < FLAT_SURFACE_Z {
+            Material::DIRT
+        } else {
+            Material::AIR
+        };
+        Cell {
+            material,
+            shade: 1.0,
+        }
+    }
+
+    fn populate_cells(&self, origin: ChunkOrigin, cells: &mut Vec<Cell>) {
+        let chunk_res = self.spec.chunk_resolution;
+        let origin = origin.pos();
+        for z in origin.z..(origin.z + chunk_res[2]) {
+            for y in origin.y..=(origin.y + chunk_res[1]) {
+                for x in origin.x..=(origin.x + chunk_res[0]) {
+                    cells.push(self.cell_at(Point3::new(origin.root, x, y, z)));
+                }
+            }
+        }
+    }
+}
+
+/// Same dimensions as `Globe::new_example`, but perfectly flat;
+/// see `FLAT_SURFACE_Z`.
+pub fn flat_example_globe() -> Globe {
+    let spec = Globe::new_example().spec();
+    Globe::new_with_gen(
+        spec,
+        Arc::new(MaterialRegistry::new()),
+        Box::new(FlatGen { spec }),
+    )
+}
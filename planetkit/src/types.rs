@@ -31,4 +31,14 @@ pub type TimeDelta = f64;
 #[derive(Default)]
 pub struct TimeDeltaResource(pub TimeDelta);
 
+/// `World`-global resource counting how many times
+/// the systems have been run. See `App`.
+#[derive(Default)]
+pub struct TickResource(pub u64);
+
+/// `World`-global resource for how far between the last tick and
+/// the next we are when rendering, from 0 to 1. See `App`.
+#[derive(Default)]
+pub struct InterpolationAlphaResource(pub f64);
+
 pub type Mat4 = na::Matrix4<f64>;
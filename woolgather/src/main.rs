@@ -35,7 +35,8 @@ fn create_entities(world: &mut specs::World) {
 
     // Create the globe first, because we'll need it to figure out where
     // to place the shepherd (player character).
-    let globe_entity = pk::simple::create_simple_globe_now(world);
+    let globe_entity =
+        pk::simple::create_simple_globe_now(world, pk::simple::SimpleTerrain::Layered, None);
 
     // Create the shepherd.
     let shepherd_entity = shepherd::create_now(world, globe_entity);
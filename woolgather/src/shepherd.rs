@@ -44,6 +44,7 @@ pub fn create_now(world: &mut specs::World, globe_entity: specs::Entity) -> spec
             globe_spec,
             Some(globe_entity),
         ))
+        .with(cell_dweller::Inventory::default())
         .with(shepherd_visual)
         // The CellDweller's transformation will be set based
         // on its coordinates in cell space.